@@ -1,5 +1,8 @@
+use crate::geometry::{is_degenerate, signed_volume};
 use crate::traits::*;
 use bevy_math::{Quat, Vec2, Vec3};
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
 use std::hash::Hash;
 
 #[doc(hidden)]
@@ -34,6 +37,11 @@ pub trait MeshBuilder {
     where
         Self: Sized;
 
+    /// Append this to a mesh.
+    ///
+    /// # Panics
+    /// Builders that can be given invalid input, like [`IndexedMesh`], may panic on it. They have their own
+    /// `try_append_to` to report the problem instead.
     fn append_to<M: BuildMesh>(&self, mesh: M);
     fn build<M: Default>(&self) -> M
     where
@@ -111,6 +119,166 @@ macro_rules! impl_builder_via_transformed {
     };
 }
 
+/// An error from building an [`IndexedMesh`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexedMeshError {
+    /// A tetrahedron references a vertex past the end of the vertex array.
+    VertexOutOfRange { tetra: usize, index: u32 },
+    /// A tetrahedron uses the same vertex more than once.
+    RepeatedVertex { tetra: usize },
+    /// A tetrahedron is flat, so it has no orientation.
+    Degenerate { tetra: usize },
+    /// A face is shared by more than two tetrahedra.
+    NonManifoldFace { face: [u32; 3], tetras: Vec<usize> },
+}
+impl Display for IndexedMeshError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VertexOutOfRange { tetra, index } => {
+                write!(f, "tetrahedron {tetra} references missing vertex {index}")
+            }
+            Self::RepeatedVertex { tetra } => {
+                write!(f, "tetrahedron {tetra} uses the same vertex more than once")
+            }
+            Self::Degenerate { tetra } => write!(f, "tetrahedron {tetra} is degenerate"),
+            Self::NonManifoldFace { face, tetras } => {
+                write!(f, "face {face:?} is shared by tetrahedra {tetras:?}")
+            }
+        }
+    }
+}
+impl std::error::Error for IndexedMeshError {}
+
+/// The IDs assigned by [`IndexedMesh::try_append_to`], in the same order as the input arrays.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedIds<K> {
+    pub verts: Vec<VertexId<K>>,
+    pub tetras: Vec<TetraId<K>>,
+}
+
+/// A mesh given as a vertex array and tetrahedra indexing into it.
+///
+/// Neighbor links are computed by matching up faces that share the same three vertices, and tetrahedra given in the
+/// wrong winding are flipped, so the input only needs to describe which points make up each tetrahedron.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IndexedMesh<V, T> {
+    pub verts: V,
+    pub tetras: T,
+}
+impl<V, T> IndexedMesh<V, T> {
+    pub const fn new(verts: V, tetras: T) -> Self {
+        Self { verts, tetras }
+    }
+}
+impl<V: AsRef<[Vec3]>, T: AsRef<[[u32; 4]]>> IndexedMesh<V, T> {
+    /// Check the tetrahedra, fix their orientation, and find the neighbors of each face.
+    #[allow(clippy::type_complexity)]
+    fn resolve(&self) -> Result<Vec<[(u32, Option<(usize, VertexIdx)>); 4]>, IndexedMeshError> {
+        let verts = self.verts.as_ref();
+        let tetras = self.tetras.as_ref();
+        let mut oriented = Vec::with_capacity(tetras.len());
+        for (tetra, &indices) in tetras.iter().enumerate() {
+            if let Some(&index) = indices.iter().find(|&&i| i as usize >= verts.len()) {
+                return Err(IndexedMeshError::VertexOutOfRange { tetra, index });
+            }
+            if (0..4).any(|i| indices[(i + 1)..].contains(&indices[i])) {
+                return Err(IndexedMeshError::RepeatedVertex { tetra });
+            }
+            let points = indices.map(|i| verts[i as usize]);
+            if is_degenerate(points) {
+                return Err(IndexedMeshError::Degenerate { tetra });
+            }
+            let [a, b, c, d] = indices;
+            oriented.push(if signed_volume(points) < 0.0 {
+                [a, b, d, c]
+            } else {
+                indices
+            });
+        }
+        let mut faces = HashMap::<_, Vec<_>>::with_capacity(tetras.len() * 2);
+        for (tetra, indices) in oriented.iter().enumerate() {
+            for face in VertexIdx::VALS {
+                let mut key = face.others().map(|i| *i.in_arr(indices));
+                key.sort_unstable();
+                faces.entry(key).or_default().push((tetra, face));
+            }
+        }
+        let mut out = oriented
+            .into_iter()
+            .map(|indices| indices.map(|i| (i, None)))
+            .collect::<Vec<_>>();
+        for (face, tetras) in faces {
+            match *tetras {
+                [_] => {}
+                [(t1, f1), (t2, f2)] => {
+                    f1.in_arr_mut(&mut out[t1]).1 = Some((t2, f2));
+                    f2.in_arr_mut(&mut out[t2]).1 = Some((t1, f1));
+                }
+                _ => {
+                    let mut tetras = tetras.into_iter().map(|t| t.0).collect::<Vec<_>>();
+                    tetras.sort_unstable();
+                    return Err(IndexedMeshError::NonManifoldFace { face, tetras });
+                }
+            }
+        }
+        Ok(out)
+    }
+    /// Append this mesh, returning the IDs of the new vertices and tetrahedra.
+    ///
+    /// Nothing is added to the mesh if this returns an error.
+    pub fn try_append_to<M: BuildMesh>(
+        &self,
+        mut mesh: M,
+    ) -> Result<IndexedIds<M::Key>, IndexedMeshError> {
+        let resolved = self.resolve()?;
+        let verts = self
+            .verts
+            .as_ref()
+            .iter()
+            .map(|&v| mesh.add_vertex(v))
+            .collect::<Vec<_>>();
+        let mut tetras = Vec::with_capacity(resolved.len());
+        for conns in resolved {
+            // links only have to be given in one direction, so only link to tetrahedra that have already been added
+            let tetra = mesh.add_tetra(conns.map(|(v, adj)| {
+                (
+                    verts[v as usize],
+                    adj.and_then(|(t, f)| tetras.get(t).map(|&id| (id, f))),
+                )
+            }));
+            tetras.push(tetra);
+        }
+        Ok(IndexedIds { verts, tetras })
+    }
+    /// Build a new mesh from this one.
+    pub fn try_build<M: Default>(&self) -> Result<M, IndexedMeshError>
+    where
+        for<'a> &'a mut M: BuildMesh,
+    {
+        let mut mesh = M::default();
+        self.try_append_to(&mut mesh)?;
+        Ok(mesh)
+    }
+}
+impl<V: AsRef<[Vec3]> + AsMut<[Vec3]>, T: AsRef<[[u32; 4]]>> MeshBuilder for IndexedMesh<V, T> {
+    type Transformed = Self;
+
+    /// Append this mesh, panicking if it's invalid.
+    ///
+    /// See [`Self::try_append_to`] for a non-panicking version.
+    fn append_to<M: BuildMesh>(&self, mesh: M) {
+        if let Err(err) = self.try_append_to(mesh) {
+            panic!("invalid indexed mesh: {err}");
+        }
+    }
+    fn transform(mut self, transform: Affine3A) -> Self::Transformed {
+        for pt in self.verts.as_mut() {
+            *pt = transform.transform_point3(*pt);
+        }
+        self
+    }
+}
+
 /// A hexahedron, or a "deformed cube".
 ///
 /// Points are expected to form a convex hexahedron and be given in a U-shaped order, such as:
@@ -124,6 +292,9 @@ macro_rules! impl_builder_via_transformed {
 /// (1, 1, 1)
 /// (0, 1, 1)
 /// ```
+///
+/// Tetrahedra without any volume, like the ones a flat hexahedron is split into, are left out instead of panicking, along
+/// with any corners only they use, so a completely flat hexahedron doesn't add anything.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Hexahedron {
     pub points: [Vec3; 8],
@@ -167,33 +338,34 @@ impl From<Cuboid> for Hexahedron {
 impl MeshBuilder for Hexahedron {
     type Transformed = Self;
 
-    fn append_to<M: BuildMesh>(&self, mut mesh: M) {
-        let [a, b, c, d, e, f, g, h] = self.points.map(|p| mesh.add_vertex(p));
-        let center = mesh.add_tetra([(b, None), (e, None), (d, None), (g, None)]);
-        mesh.add_tetra([
-            (a, Some((center, VertexIdx::V3))),
-            (b, None),
-            (e, None),
-            (d, None),
-        ]);
-        mesh.add_tetra([
-            (f, Some((center, VertexIdx::V2))),
-            (b, None),
-            (g, None),
-            (e, None),
-        ]);
-        mesh.add_tetra([
-            (c, Some((center, VertexIdx::V1))),
-            (b, None),
-            (d, None),
-            (g, None),
-        ]);
-        mesh.add_tetra([
-            (h, Some((center, VertexIdx::V0))),
-            (g, None),
-            (d, None),
-            (e, None),
-        ]);
+    fn append_to<M: BuildMesh>(&self, mesh: M) {
+        // six tetrahedra around the diagonal from 0 to 6, so every side is split along the diagonal through 0 or 6,
+        // and the sides of two hexahedra next to each other are split the same way
+        const TETRAS: [[u32; 4]; 6] = [
+            [0, 6, 1, 2],
+            [0, 6, 2, 3],
+            [0, 6, 3, 7],
+            [0, 6, 7, 4],
+            [0, 6, 4, 5],
+            [0, 6, 5, 1],
+        ];
+        let mut tetras = TETRAS
+            .into_iter()
+            .filter(|t| !is_degenerate(t.map(|i| self.points[i as usize])))
+            .collect::<Vec<_>>();
+        if tetras.is_empty() {
+            return;
+        }
+        // only add the points that are still used, since corners of a flat hexahedron can be left out entirely
+        let mut remap = [None; 8];
+        let mut points = Vec::with_capacity(8);
+        for idx in tetras.iter_mut().flatten() {
+            *idx = *remap[*idx as usize].get_or_insert_with(|| {
+                points.push(self.points[*idx as usize]);
+                points.len() as u32 - 1
+            });
+        }
+        IndexedMesh::new(points, tetras).append_to(mesh);
     }
     fn transform(self, transform: Affine3A) -> Self::Transformed {
         Self::new(self.points.map(|p| transform.transform_point3(p)))
@@ -320,3 +492,131 @@ impl<B: AsRef<[Vec3]> + AsMut<[Vec3]>> MeshBuilder for Bipyramid<B> {
     }
 }
 pub type Octahedron = Bipyramid<[Vec3; 4]>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::connected_components;
+    use crate::slab_mesh::SlabMesh;
+    use crate::validate::validate;
+    use std::collections::HashSet;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    /// Check that every vertex in a mesh is used by one of its tetrahedra.
+    fn all_verts_used(mesh: &Mesh) -> bool {
+        let used = mesh
+            .tetras()
+            .flat_map(|(_, tet)| VertexIdx::VALS.map(|i| tet.vertex(i)))
+            .collect::<HashSet<_>>();
+        mesh.verts().all(|(id, _)| used.contains(&id))
+    }
+
+    #[test]
+    fn indexed_fixes_orientation() {
+        let verts = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z, Vec3::NEG_Z];
+        // the second tetrahedron is given inside out
+        let mesh = IndexedMesh::new(verts, [[0, 1, 2, 3], [0, 1, 2, 4]])
+            .try_build::<Mesh>()
+            .unwrap();
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+        for (_, tet) in mesh.tetras() {
+            assert!(signed_volume(mesh.tetra_points(tet).unwrap()) > 0.0);
+            assert_eq!(
+                VertexIdx::VALS
+                    .into_iter()
+                    .filter(|&i| tet.face(i).is_some())
+                    .count(),
+                1
+            );
+        }
+    }
+
+    #[test]
+    fn indexed_non_manifold() {
+        let verts = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+            Vec3::new(0.5, 0.5, 2.0),
+        ];
+        let result =
+            IndexedMesh::new(verts, [[0, 1, 2, 3], [0, 1, 2, 4], [0, 2, 1, 5]]).try_build::<Mesh>();
+        assert_eq!(
+            result.unwrap_err(),
+            IndexedMeshError::NonManifoldFace {
+                face: [0, 1, 2],
+                tetras: vec![0, 1, 2],
+            }
+        );
+    }
+
+    #[test]
+    fn indexed_errors() {
+        let verts = [
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::Y,
+            Vec3::Z,
+            Vec3::new(1.0, 1.0, 0.0),
+        ];
+        let build = |tetra| IndexedMesh::new(verts, [tetra]).try_build::<Mesh>();
+        assert_eq!(
+            build([0, 1, 2, 7]).unwrap_err(),
+            IndexedMeshError::VertexOutOfRange { tetra: 0, index: 7 }
+        );
+        assert_eq!(
+            build([0, 1, 1, 3]).unwrap_err(),
+            IndexedMeshError::RepeatedVertex { tetra: 0 }
+        );
+        assert_eq!(
+            build([0, 1, 2, 4]).unwrap_err(),
+            IndexedMeshError::Degenerate { tetra: 0 }
+        );
+    }
+
+    #[test]
+    fn flat_hexahedron_corners() {
+        // the front bottom edge is squashed onto the back one, leaving a wedge
+        let mut points = Hexahedron::UNIT_CUBE.points;
+        points[4] = points[0];
+        points[5] = points[1];
+        let mesh = Hexahedron::new(points).build::<Mesh>();
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+        assert!(all_verts_used(&mesh));
+        assert_eq!(mesh.verts().count(), 6);
+
+        let mut points = Hexahedron::UNIT_CUBE.points;
+        for p in &mut points {
+            p.z = 0.0;
+        }
+        let mesh = Hexahedron::new(points).build::<Mesh>();
+        assert_eq!(mesh.verts().count(), 0);
+        assert_eq!(mesh.tetras().count(), 0);
+    }
+
+    #[test]
+    fn adjacent_hexahedra_stitch() {
+        for offset in [Vec3::X, Vec3::NEG_Y, Vec3::Z] {
+            let mut mesh = Mesh::with_vertex_merging();
+            Cuboid::UNIT_CUBE.append_to(&mut mesh);
+            Cuboid::UNIT_CUBE.translate(offset).append_to(&mut mesh);
+            assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+            assert_eq!(connected_components(&mesh).len(), 1);
+            assert_eq!(mesh.verts().count(), 12);
+            let open = mesh
+                .tetras()
+                .map(|(_, tet)| {
+                    VertexIdx::VALS
+                        .into_iter()
+                        .filter(|&i| tet.face(i).is_none())
+                        .count()
+                })
+                .sum::<usize>();
+            // two triangles on each of the ten sides left outside
+            assert_eq!(open, 20);
+        }
+    }
+}
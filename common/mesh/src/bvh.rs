@@ -0,0 +1,651 @@
+//! A bounding volume hierarchy over the tetrahedra in a mesh.
+//!
+//! The tree stores an axis-aligned box for each tetrahedron, and is kept separately from the mesh, so it works with any
+//! [`TetraMesh`]. It can be built all at once with [`Bvh::build`], which gives the best tree, or kept up to date as the
+//! mesh changes with [`Bvh::update_tetra`] and friends.
+
+use crate::geometry::{bounds_of, closest_point_on_tetra, tetra_contains};
+use crate::traits::*;
+use bevy_math::{Affine3A, Vec3};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Check if two boxes overlap, including if they only touch.
+#[inline(always)]
+fn overlaps([amin, amax]: [Vec3; 2], [bmin, bmax]: [Vec3; 2]) -> bool {
+    amin.cmple(bmax).all() && bmin.cmple(amax).all()
+}
+
+/// Check if one box is completely inside another.
+#[inline(always)]
+fn encloses([outer_min, outer_max]: [Vec3; 2], [min, max]: [Vec3; 2]) -> bool {
+    outer_min.cmple(min).all() && max.cmple(outer_max).all()
+}
+
+#[inline(always)]
+fn union([amin, amax]: [Vec3; 2], [bmin, bmax]: [Vec3; 2]) -> [Vec3; 2] {
+    [amin.min(bmin), amax.max(bmax)]
+}
+
+/// Get half of the surface area of a box, which is proportional to the chance of a random ray hitting it.
+#[inline(always)]
+fn half_area([min, max]: [Vec3; 2]) -> f32 {
+    let size = (max - min).max(Vec3::ZERO);
+    size.x * size.y + size.y * size.z + size.z * size.x
+}
+
+/// Get the squared distance from a point to a box, which is zero inside of it.
+#[inline(always)]
+fn distance_squared([min, max]: [Vec3; 2], point: Vec3) -> f32 {
+    (point.clamp(min, max) - point).length_squared()
+}
+
+/// Get the range of distances along a ray that are inside of a box, if there are any within `max`.
+#[inline(always)]
+pub(crate) fn ray_box(
+    [min, max]: [Vec3; 2],
+    origin: Vec3,
+    inv_dir: Vec3,
+    limit: f32,
+) -> Option<f32> {
+    let t0 = (min - origin) * inv_dir;
+    let t1 = (max - origin) * inv_dir;
+    // NaNs from 0 * inf are ignored by min and max
+    let near = t0.min(t1).max_element().max(0.0);
+    let far = t0.max(t1).min_element().min(limit);
+    (near <= far).then_some(near)
+}
+
+/// Get the axis-aligned box around a transformed box.
+#[inline(always)]
+fn transform_box([min, max]: [Vec3; 2], transform: Affine3A) -> [Vec3; 2] {
+    let center = transform.transform_point3((min + max) / 2.0);
+    let half = (max - min) / 2.0;
+    let m = transform.matrix3;
+    let extent = m.x_axis.abs() * half.x + m.y_axis.abs() * half.y + m.z_axis.abs() * half.z;
+    [center - Vec3::from(extent), center + Vec3::from(extent)]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NodeKind<K> {
+    Leaf(TetraId<K>),
+    Branch([usize; 2]),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Node<K> {
+    bounds: [Vec3; 2],
+    parent: Option<usize>,
+    kind: NodeKind<K>,
+}
+
+/// A bounding volume hierarchy keyed by [`TetraId`].
+///
+/// Leaf boxes can be padded by a margin, so that small movements of the vertices don't require the tree to be updated.
+/// Queries are conservative with respect to the stored boxes, and the methods that take a mesh do exact tests against
+/// the tetrahedra themselves.
+#[derive(Debug, Clone)]
+pub struct Bvh<K> {
+    nodes: Vec<Node<K>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    leaves: HashMap<TetraId<K>, usize>,
+    /// How far each leaf box is extended past the tetrahedron it contains.
+    pub margin: f32,
+}
+impl<K> Default for Bvh<K> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            leaves: HashMap::new(),
+            margin: 0.0,
+        }
+    }
+}
+impl<K: Copy + Eq + Hash> Bvh<K> {
+    /// Create an empty tree.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create an empty tree with a margin around each leaf.
+    pub fn with_margin(margin: f32) -> Self {
+        Self {
+            margin,
+            ..Self::default()
+        }
+    }
+    /// Build a tree over every tetrahedron in a mesh.
+    ///
+    /// This splits the tetrahedra by their centers, which gives a better tree than inserting them one at a time.
+    pub fn build<M: TetraMesh<Key = K>>(mesh: &M) -> Self {
+        Self::build_with_margin(mesh, 0.0)
+    }
+    /// Build a tree over every tetrahedron in a mesh, with a margin around each leaf.
+    pub fn build_with_margin<M: TetraMesh<Key = K>>(mesh: &M, margin: f32) -> Self {
+        let mut this = Self::with_margin(margin);
+        let mut items = mesh
+            .tetras()
+            .filter_map(|(id, tet)| {
+                let bounds = this.padded(bounds_of(mesh.tetra_points(tet)?));
+                Some((id, bounds))
+            })
+            .collect::<Vec<_>>();
+        this.nodes.reserve(items.len() * 2);
+        this.root = this.build_recursive(&mut items, None);
+        this
+    }
+    fn build_recursive(
+        &mut self,
+        items: &mut [(TetraId<K>, [Vec3; 2])],
+        parent: Option<usize>,
+    ) -> Option<usize> {
+        if let [(id, bounds)] = *items {
+            let idx = self.alloc(Node {
+                bounds,
+                parent,
+                kind: NodeKind::Leaf(id),
+            });
+            self.leaves.insert(id, idx);
+            return Some(idx);
+        }
+        if items.is_empty() {
+            return None;
+        }
+        let bounds = items.iter().map(|i| i.1).reduce(union)?;
+        let center = |b: &[Vec3; 2]| b[0] + b[1];
+        let size = bounds[1] - bounds[0];
+        let axis = if size.x >= size.y && size.x >= size.z {
+            0
+        } else if size.y >= size.z {
+            1
+        } else {
+            2
+        };
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| {
+            center(&a.1)[axis].total_cmp(&center(&b.1)[axis])
+        });
+        let idx = self.alloc(Node {
+            bounds,
+            parent,
+            kind: NodeKind::Branch([0; 2]),
+        });
+        let (left, right) = items.split_at_mut(mid);
+        let children = [
+            self.build_recursive(left, Some(idx))?,
+            self.build_recursive(right, Some(idx))?,
+        ];
+        self.nodes[idx].kind = NodeKind::Branch(children);
+        Some(idx)
+    }
+    fn alloc(&mut self, node: Node<K>) -> usize {
+        if let Some(idx) = self.free.pop() {
+            self.nodes[idx] = node;
+            idx
+        } else {
+            self.nodes.push(node);
+            self.nodes.len() - 1
+        }
+    }
+    fn padded(&self, [min, max]: [Vec3; 2]) -> [Vec3; 2] {
+        [min - self.margin, max + self.margin]
+    }
+
+    /// Get the number of tetrahedra in the tree.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+    /// Check if the tree is empty.
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+    /// Check if a tetrahedron is in the tree.
+    pub fn contains(&self, id: TetraId<K>) -> bool {
+        self.leaves.contains_key(&id)
+    }
+    /// Get the bounds of everything in the tree.
+    pub fn bounds(&self) -> Option<[Vec3; 2]> {
+        self.root.map(|r| self.nodes[r].bounds)
+    }
+    /// Get the stored bounds of a tetrahedron, including the margin.
+    pub fn get(&self, id: TetraId<K>) -> Option<[Vec3; 2]> {
+        self.leaves.get(&id).map(|&i| self.nodes[i].bounds)
+    }
+
+    /// Insert a tetrahedron with the given bounds, replacing it if it was already in the tree.
+    pub fn insert(&mut self, id: TetraId<K>, bounds: [Vec3; 2]) {
+        self.remove(id);
+        let bounds = self.padded(bounds);
+        let leaf = self.alloc(Node {
+            bounds,
+            parent: None,
+            kind: NodeKind::Leaf(id),
+        });
+        self.leaves.insert(id, leaf);
+        let Some(mut sibling) = self.root else {
+            self.root = Some(leaf);
+            return;
+        };
+        // walk down to the sibling that grows the least
+        while let NodeKind::Branch(children) = self.nodes[sibling].kind {
+            let cost = |i: usize| {
+                let b = self.nodes[i].bounds;
+                half_area(union(b, bounds)) - half_area(b)
+            };
+            sibling = if cost(children[0]) <= cost(children[1]) {
+                children[0]
+            } else {
+                children[1]
+            };
+        }
+        let old_parent = self.nodes[sibling].parent;
+        let branch = self.alloc(Node {
+            bounds: union(self.nodes[sibling].bounds, bounds),
+            parent: old_parent,
+            kind: NodeKind::Branch([sibling, leaf]),
+        });
+        self.nodes[sibling].parent = Some(branch);
+        self.nodes[leaf].parent = Some(branch);
+        match old_parent {
+            None => self.root = Some(branch),
+            Some(p) => {
+                self.replace_child(p, sibling, branch);
+                self.refit(p);
+            }
+        }
+    }
+    /// Remove a tetrahedron, returning whether it was in the tree.
+    pub fn remove(&mut self, id: TetraId<K>) -> bool {
+        let Some(leaf) = self.leaves.remove(&id) else {
+            return false;
+        };
+        self.free.push(leaf);
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return true;
+        };
+        let NodeKind::Branch(children) = self.nodes[parent].kind else {
+            unreachable!("parent node is a leaf");
+        };
+        let sibling = if children[0] == leaf {
+            children[1]
+        } else {
+            children[0]
+        };
+        self.free.push(parent);
+        let grandparent = self.nodes[parent].parent;
+        self.nodes[sibling].parent = grandparent;
+        match grandparent {
+            None => self.root = Some(sibling),
+            Some(g) => {
+                self.replace_child(g, parent, sibling);
+                self.refit(g);
+            }
+        }
+        true
+    }
+    /// Change the bounds of a tetrahedron, inserting it if it isn't in the tree.
+    ///
+    /// If the new bounds are still inside of the stored ones, nothing happens.
+    pub fn update(&mut self, id: TetraId<K>, bounds: [Vec3; 2]) {
+        if let Some(&leaf) = self.leaves.get(&id)
+            && encloses(self.nodes[leaf].bounds, bounds)
+        {
+            return;
+        }
+        self.insert(id, bounds);
+    }
+    /// Bring a tetrahedron up to date with a mesh, inserting, moving, or removing it as needed.
+    pub fn update_tetra<M: TetraMesh<Key = K>>(&mut self, mesh: &M, id: TetraId<K>) {
+        match mesh.get_tetra(id).and_then(|t| mesh.tetra_points(t)) {
+            Some(points) => self.update(id, bounds_of(points)),
+            None => {
+                self.remove(id);
+            }
+        }
+    }
+    /// Bring the whole tree up to date with a mesh.
+    ///
+    /// This is linear in the size of the mesh, so it's best used after large changes. For small edits, calling
+    /// [`Self::update_tetra`] for the IDs in an [`Edit`](crate::edit::Edit) is much faster.
+    pub fn sync<M: TetraMesh<Key = K>>(&mut self, mesh: &M) {
+        let stale = self
+            .leaves
+            .keys()
+            .copied()
+            .filter(|&id| mesh.get_tetra(id).is_none())
+            .collect::<Vec<_>>();
+        for id in stale {
+            self.remove(id);
+        }
+        for (id, _) in mesh.tetras() {
+            self.update_tetra(mesh, id);
+        }
+    }
+    fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+        if let NodeKind::Branch(children) = &mut self.nodes[parent].kind {
+            for c in children {
+                if *c == old {
+                    *c = new;
+                }
+            }
+        }
+    }
+    /// Recompute the bounds of a node and all of its ancestors.
+    fn refit(&mut self, mut idx: usize) {
+        loop {
+            if let NodeKind::Branch([a, b]) = self.nodes[idx].kind {
+                self.nodes[idx].bounds = union(self.nodes[a].bounds, self.nodes[b].bounds);
+            }
+            match self.nodes[idx].parent {
+                Some(p) => idx = p,
+                None => break,
+            }
+        }
+    }
+
+    /// Call a function for every tetrahedron whose stored bounds overlap a box.
+    pub fn query(&self, bounds: [Vec3; 2], mut f: impl FnMut(TetraId<K>)) {
+        let mut stack = Vec::from_iter(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !overlaps(node.bounds, bounds) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf(id) => f(id),
+                NodeKind::Branch(children) => stack.extend(children),
+            }
+        }
+    }
+    /// Get every tetrahedron whose stored bounds overlap a box.
+    pub fn overlapping(&self, bounds: [Vec3; 2]) -> Vec<TetraId<K>> {
+        let mut out = Vec::new();
+        self.query(bounds, |id| out.push(id));
+        out
+    }
+    /// Call a function for every pair of tetrahedra from this tree and another one whose stored bounds overlap.
+    ///
+    /// The other tree can be in a different space, with `transform` taking its boxes into the space of this one. Its
+    /// boxes are made axis-aligned again after transforming them, which makes them grow under rotations.
+    pub fn query_pairs<K2: Copy>(
+        &self,
+        other: &Bvh<K2>,
+        transform: Affine3A,
+        mut f: impl FnMut(TetraId<K>, TetraId<K2>),
+    ) {
+        let (Some(a), Some(b)) = (self.root, other.root) else {
+            return;
+        };
+        let other_bounds = |idx: usize| transform_box(other.nodes[idx].bounds, transform);
+        let mut stack = vec![(a, b, other_bounds(b))];
+        while let Some((a, b, b_bounds)) = stack.pop() {
+            let node = &self.nodes[a];
+            if !overlaps(node.bounds, b_bounds) {
+                continue;
+            }
+            match (node.kind, other.nodes[b].kind) {
+                (NodeKind::Leaf(x), NodeKind::Leaf(y)) => f(x, y),
+                // split the bigger node, so both sides shrink at about the same rate
+                (NodeKind::Branch(children), NodeKind::Leaf(_)) => {
+                    stack.extend(children.map(|c| (c, b, b_bounds)));
+                }
+                (NodeKind::Branch(children), NodeKind::Branch(_))
+                    if half_area(node.bounds) >= half_area(b_bounds) =>
+                {
+                    stack.extend(children.map(|c| (c, b, b_bounds)));
+                }
+                (_, NodeKind::Branch(children)) => {
+                    stack.extend(children.map(|c| (a, c, other_bounds(c))));
+                }
+            }
+        }
+    }
+    /// Find a tetrahedron containing a point.
+    ///
+    /// If the point is on a face shared by multiple tetrahedra, any of them can be returned.
+    pub fn locate<M: TetraMesh<Key = K>>(&self, mesh: &M, point: Vec3) -> Option<TetraId<K>> {
+        let mut stack = Vec::from_iter(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if !overlaps(node.bounds, [point; 2]) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf(id) => {
+                    if mesh
+                        .get_tetra(id)
+                        .and_then(|t| mesh.tetra_points(t))
+                        .is_some_and(|p| tetra_contains(p, point))
+                    {
+                        return Some(id);
+                    }
+                }
+                NodeKind::Branch(children) => stack.extend(children),
+            }
+        }
+        None
+    }
+    /// Find the tetrahedron closest to a point, returning it along with the closest point on it.
+    ///
+    /// Points inside of a tetrahedron are their own closest point.
+    pub fn nearest<M: TetraMesh<Key = K>>(
+        &self,
+        mesh: &M,
+        point: Vec3,
+    ) -> Option<(TetraId<K>, Vec3)> {
+        self.nearest_by(point, |id| {
+            let points = mesh.get_tetra(id).and_then(|t| mesh.tetra_points(t))?;
+            let closest = closest_point_on_tetra(points, point);
+            Some((closest.distance_squared(point), (id, closest)))
+        })
+        .map(|(_, found)| found)
+    }
+    /// Find the closest of something near a point.
+    ///
+    /// `dist` is called for tetrahedra whose boxes could be closer than the closest one found so far, and returns the
+    /// squared distance to whatever it found in that tetrahedron, along with any extra data. This way only some of the
+    /// tetrahedra can be considered, or only parts of them, like their boundary faces.
+    pub fn nearest_by<T>(
+        &self,
+        point: Vec3,
+        mut dist: impl FnMut(TetraId<K>) -> Option<(f32, T)>,
+    ) -> Option<(f32, T)> {
+        let mut best = None;
+        let mut best_dist = f32::INFINITY;
+        let mut stack = Vec::from_iter(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if distance_squared(node.bounds, point) > best_dist {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf(id) => {
+                    if let Some((d, data)) = dist(id)
+                        && d < best_dist
+                    {
+                        best_dist = d;
+                        best = Some((d, data));
+                    }
+                }
+                NodeKind::Branch([a, b]) => {
+                    // visit the closer child first so the other one is more likely to be pruned
+                    let da = distance_squared(self.nodes[a].bounds, point);
+                    let db = distance_squared(self.nodes[b].bounds, point);
+                    if da < db {
+                        stack.extend([b, a]);
+                    } else {
+                        stack.extend([a, b]);
+                    }
+                }
+            }
+        }
+        best
+    }
+    /// Find the closest hit along a ray.
+    ///
+    /// `hit` is called for each tetrahedron whose box the ray passes through, and returns the distance along the ray
+    /// of a hit, along with any extra data. Tetrahedra are visited roughly front to back, and boxes past the closest
+    /// hit found so far are skipped. The direction doesn't need to be normalized, and distances are in multiples of it.
+    pub fn raycast<T>(
+        &self,
+        origin: Vec3,
+        dir: Vec3,
+        max: f32,
+        mut hit: impl FnMut(TetraId<K>) -> Option<(f32, T)>,
+    ) -> Option<(f32, T)> {
+        let inv_dir = dir.recip();
+        let mut best = None;
+        let mut limit = max;
+        let mut stack = Vec::from_iter(self.root);
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if ray_box(node.bounds, origin, inv_dir, limit).is_none() {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf(id) => {
+                    if let Some((t, data)) = hit(id)
+                        && t <= limit
+                    {
+                        limit = t;
+                        best = Some((t, data));
+                    }
+                }
+                NodeKind::Branch([a, b]) => {
+                    let ta = ray_box(self.nodes[a].bounds, origin, inv_dir, limit);
+                    let tb = ray_box(self.nodes[b].bounds, origin, inv_dir, limit);
+                    match (ta, tb) {
+                        (Some(ta), Some(tb)) if ta < tb => stack.extend([b, a]),
+                        (Some(_), Some(_)) => stack.extend([a, b]),
+                        (Some(_), None) => stack.push(a),
+                        (None, Some(_)) => stack.push(b),
+                        (None, None) => {}
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    fn tetra_bounds(mesh: &Mesh, id: TetraId<u32>) -> [Vec3; 2] {
+        bounds_of(mesh.tetra_points(mesh.get_tetra(id).unwrap()).unwrap())
+    }
+
+    /// Compare queries on the tree with checking every tetrahedron in the mesh.
+    fn check(bvh: &Bvh<u32>, mesh: &Mesh) {
+        assert_eq!(bvh.len(), mesh.tetras().count());
+        for (id, _) in mesh.tetras() {
+            assert!(encloses(bvh.get(id).unwrap(), tetra_bounds(mesh, id)));
+        }
+
+        let [min, max] = bounds_of(mesh.verts().map(|(_, v)| v.pos));
+        let samples = (0..216).map(|i| {
+            let t = Vec3::new((i % 6) as f32, (i / 6 % 6) as f32, (i / 36) as f32) / 5.0;
+            min - 0.1 + (max - min + 0.2) * t
+        });
+        for point in samples {
+            let containing = mesh
+                .tetras()
+                .filter(|(_, t)| tetra_contains(mesh.tetra_points(t).unwrap(), point))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            match bvh.locate(mesh, point) {
+                Some(id) => assert!(containing.contains(&id), "{point} isn't in {id:?}"),
+                None => assert!(containing.is_empty(), "{point} is in {containing:?}"),
+            }
+
+            let nearest = mesh
+                .tetras()
+                .map(|(_, t)| {
+                    let closest = closest_point_on_tetra(mesh.tetra_points(t).unwrap(), point);
+                    closest.distance(point)
+                })
+                .fold(f32::INFINITY, f32::min);
+            let (id, closest) = bvh.nearest(mesh, point).unwrap();
+            assert!(mesh.get_tetra(id).is_some());
+            assert!((closest.distance(point) - nearest).abs() < 1e-6);
+
+            let query = [point - 0.15, point + 0.15];
+            let mut found = bvh.overlapping(query);
+            found.sort_unstable();
+            let mut expected = mesh
+                .tetras()
+                .map(|(id, _)| id)
+                .filter(|&id| overlaps(bvh.get(id).unwrap(), query))
+                .collect::<Vec<_>>();
+            expected.sort_unstable();
+            assert_eq!(found, expected);
+            // the stored bounds can only be bigger than the tetrahedra, so nothing that's really there is missed
+            for (id, _) in mesh.tetras() {
+                if overlaps(tetra_bounds(mesh, id), query) {
+                    assert!(found.contains(&id));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_updates_match_brute_force() {
+        let mut mesh: Mesh = lattice(5);
+        let mut bvh = Bvh::new();
+        bvh.sync(&mesh);
+        check(&bvh, &mesh);
+
+        // remove every third tetrahedron
+        let ids = mesh.tetras().map(|(id, _)| id).collect::<Vec<_>>();
+        let mut removed = Vec::new();
+        for &id in ids.iter().step_by(3) {
+            removed.push(mesh.remove_tetra(id).unwrap());
+            assert!(bvh.remove(id));
+            assert!(!bvh.remove(id));
+            assert!(!bvh.contains(id));
+        }
+        check(&bvh, &mesh);
+
+        // stretch the mesh, so every box has to move
+        let verts = mesh.verts().map(|(id, _)| id).collect::<Vec<_>>();
+        for v in verts {
+            mesh.get_vertex_mut(v).unwrap().pos *= Vec3::new(1.5, 1.0, 0.8);
+        }
+        let ids = mesh.tetras().map(|(id, _)| id).collect::<Vec<_>>();
+        for (i, &id) in ids.iter().enumerate() {
+            if i % 2 == 0 {
+                bvh.update_tetra(&mesh, id);
+            } else {
+                bvh.update(id, tetra_bounds(&mesh, id));
+            }
+        }
+        check(&bvh, &mesh);
+
+        // add the removed ones back, which can reuse their IDs
+        for tet in removed {
+            let id = mesh.add_tetra(tet);
+            bvh.insert(id, tetra_bounds(&mesh, id));
+        }
+        check(&bvh, &mesh);
+
+        // and let sync find changes that the tree wasn't told about
+        let ids = mesh.tetras().map(|(id, _)| id).collect::<Vec<_>>();
+        for &id in ids.iter().step_by(4) {
+            mesh.remove_tetra(id);
+        }
+        let verts = mesh.verts().map(|(id, _)| id).collect::<Vec<_>>();
+        for v in verts {
+            mesh.get_vertex_mut(v).unwrap().pos.y += 0.25;
+        }
+        bvh.sync(&mesh);
+        check(&bvh, &mesh);
+    }
+}
@@ -0,0 +1,386 @@
+//! Collisions between tetrahedral meshes.
+//!
+//! [`collide`] is a narrow phase between two meshes placed in the world with their own transforms. Their BVHs are
+//! walked together to find the tetrahedra that could touch, and then every boundary vertex of one mesh that's inside
+//! of the other becomes a contact, pushed out through the closest boundary face of the other mesh.
+//!
+//! Two coarse meshes can also cross edge to edge without either having a vertex inside of the other, so boundary edges
+//! are tested against the boundary faces of the other mesh too. Wherever an edge goes in through one face and out
+//! through another, the point halfway between them becomes a contact the same way.
+
+use crate::bvh::Bvh;
+use crate::geometry::{closest_point_on_triangle, tetra_contains};
+use crate::raycast::{is_boundary, ray_triangle};
+use crate::traits::*;
+use bevy_ecs::entity::Entity;
+use bevy_math::{Affine3A, Dir3, Ray3d, Vec3};
+use std::collections::{HashMap, HashSet};
+
+/// A mesh placed in the world, for [`collide`].
+#[derive(Debug)]
+pub struct Collider<'a, M: TetraMesh> {
+    pub mesh: &'a M,
+    /// A BVH over the mesh, which has to be up to date with it.
+    pub bvh: &'a Bvh<M::Key>,
+    /// The transform from the space of the mesh to the world.
+    pub transform: Affine3A,
+}
+impl<M: TetraMesh> Clone for Collider<'_, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<M: TetraMesh> Copy for Collider<'_, M> {}
+impl<'a, M: TetraMesh> Collider<'a, M> {
+    /// Place a mesh in the world.
+    pub const fn new(mesh: &'a M, bvh: &'a Bvh<M::Key>, transform: Affine3A) -> Self {
+        Self {
+            mesh,
+            bvh,
+            transform,
+        }
+    }
+}
+
+/// A point where two meshes overlap, from [`collide`].
+///
+/// Everything is in world space.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact<A, B> {
+    /// The deepest point of the first mesh's surface in this contact.
+    pub point_a: Vec3,
+    /// The deepest point of the second mesh's surface in this contact.
+    pub point_b: Vec3,
+    /// The direction to move the first mesh in to separate them, or the second one against.
+    pub normal: Vec3,
+    /// How far the meshes overlap along the normal.
+    pub depth: f32,
+    /// The tetrahedron of the first mesh that the contact is on.
+    pub tetra_a: TetraId<A>,
+    /// The tetrahedron of the second mesh that the contact is on.
+    pub tetra_b: TetraId<B>,
+}
+
+impl<A, B> Contact<A, B> {
+    /// Get the same contact from the point of view of the second mesh.
+    pub fn swap(self) -> Contact<B, A> {
+        Contact {
+            point_a: self.point_b,
+            point_b: self.point_a,
+            normal: -self.normal,
+            depth: self.depth,
+            tetra_a: self.tetra_b,
+            tetra_b: self.tetra_a,
+        }
+    }
+}
+
+/// Every contact between two meshes, from [`collide`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collision<A, B> {
+    pub contacts: Vec<Contact<A, B>>,
+}
+impl<A, B> Default for Collision<A, B> {
+    fn default() -> Self {
+        Self {
+            contacts: Vec::new(),
+        }
+    }
+}
+impl<A, B> Collision<A, B> {
+    /// Check if the meshes don't touch.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty()
+    }
+    /// Get the contact where the meshes overlap the most.
+    pub fn deepest(&self) -> Option<&Contact<A, B>> {
+        self.contacts
+            .iter()
+            .max_by(|a, b| a.depth.total_cmp(&b.depth))
+    }
+}
+
+/// An event for two entities whose meshes collided.
+#[derive(Debug, Clone, PartialEq, bevy_ecs_macros::Event)]
+pub struct CollisionEvent<A: Send + Sync + 'static, B: Send + Sync + 'static> {
+    pub a: Entity,
+    pub b: Entity,
+    pub collision: Collision<A, B>,
+}
+
+/// Check if a tetrahedron has any faces on the boundary of its mesh.
+fn has_boundary<M: TetraMesh>(mesh: &M, id: TetraId<M::Key>) -> bool {
+    mesh.get_tetra(id)
+        .is_some_and(|t| VertexIdx::VALS.iter().any(|&f| is_boundary(mesh, t, f)))
+}
+
+/// A boundary vertex of one mesh inside of the other, along with a tetrahedron of each mesh it was found in.
+struct Inside<K, O> {
+    vertex: VertexId<K>,
+    tetra: TetraId<K>,
+    other: TetraId<O>,
+}
+
+/// A point halfway along a boundary edge of one mesh where it passes through the other, in world space.
+struct Crossing<K, O> {
+    point: Vec3,
+    tetra: TetraId<K>,
+    other: TetraId<O>,
+}
+
+/// Find the boundary vertices of `a` that are inside of `b`.
+fn vertices_inside<A: TetraMesh, B: TetraMesh>(
+    a: Collider<A>,
+    b: Collider<B>,
+    pairs: impl IntoIterator<Item = (TetraId<A::Key>, TetraId<B::Key>)>,
+) -> Vec<Inside<A::Key, B::Key>> {
+    let to_b = b.transform.inverse() * a.transform;
+    let mut seen = HashSet::new();
+    let mut out = Vec::new();
+    for (ta, tb) in pairs {
+        let (Some(tet), Some(other)) = (a.mesh.get_tetra(ta), b.mesh.get_tetra(tb)) else {
+            continue;
+        };
+        let Some(points) = b.mesh.tetra_points(other) else {
+            continue;
+        };
+        for face in VertexIdx::VALS {
+            if !is_boundary(a.mesh, tet, face) {
+                continue;
+            }
+            for i in face.others() {
+                let vertex = tet.vertex(i);
+                if seen.contains(&vertex) {
+                    continue;
+                }
+                let Some(vert) = a.mesh.get_vertex(vertex) else {
+                    continue;
+                };
+                if tetra_contains(points, to_b.transform_point3(vert.as_vec3())) {
+                    seen.insert(vertex);
+                    out.push(Inside {
+                        vertex,
+                        tetra: ta,
+                        other: tb,
+                    });
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Find the closest point on the boundary of a mesh, in its own space, along with the outward normal of its face.
+fn closest_boundary<M: TetraMesh>(
+    c: Collider<M>,
+    point: Vec3,
+) -> Option<(TetraId<M::Key>, Vec3, Vec3)> {
+    c.bvh
+        .nearest_by(point, |id| {
+            let tet = c.mesh.get_tetra(id)?;
+            let points = c.mesh.tetra_points(tet)?;
+            VertexIdx::VALS
+                .into_iter()
+                .filter(|&face| is_boundary(c.mesh, tet, face))
+                .map(|face| {
+                    let tri = face.face_order().map(|i| *i.in_arr(&points));
+                    let closest = closest_point_on_triangle(tri, point);
+                    let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]);
+                    (closest.distance_squared(point), (id, closest, normal))
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+        })
+        .map(|(_, found)| found)
+}
+
+/// Find where the boundary edges of `a` pass through `b`, going in through one boundary face and out through another.
+fn edges_through<A: TetraMesh, B: TetraMesh>(
+    a: Collider<A>,
+    b: Collider<B>,
+    pairs: impl IntoIterator<Item = (TetraId<A::Key>, TetraId<B::Key>)>,
+) -> Vec<Crossing<A::Key, B::Key>> {
+    let to_b = b.transform.inverse() * a.transform;
+    // the fractions along each edge where it crosses a boundary face, keyed by its sorted vertices
+    let mut edges = HashMap::<_, (TetraId<A::Key>, [Vec3; 2], Vec<(f32, TetraId<B::Key>)>)>::new();
+    let mut tested = HashSet::new();
+    for (ta, tb) in pairs {
+        let (Some(tet), Some(other)) = (a.mesh.get_tetra(ta), b.mesh.get_tetra(tb)) else {
+            continue;
+        };
+        let (Some(points), Some(other_points)) =
+            (a.mesh.tetra_points(tet), b.mesh.tetra_points(other))
+        else {
+            continue;
+        };
+        let points = points.map(|p| to_b.transform_point3(p));
+        for face in VertexIdx::VALS
+            .into_iter()
+            .filter(|&f| is_boundary(a.mesh, tet, f))
+        {
+            let [x, y, z] = face.others();
+            for [i, j] in [[x, y], [y, z], [z, x]] {
+                let mut edge = [
+                    (tet.vertex(i), *i.in_arr(&points)),
+                    (tet.vertex(j), *j.in_arr(&points)),
+                ];
+                edge.sort_unstable_by_key(|e| e.0);
+                let [(v, start), (w, end)] = edge;
+                let Ok(direction) = Dir3::new(end - start) else {
+                    continue;
+                };
+                let length = start.distance(end);
+                let entry = edges
+                    .entry([v, w])
+                    .or_insert_with(|| (ta, [start, end], Vec::new()));
+                for other_face in VertexIdx::VALS {
+                    if !is_boundary(b.mesh, other, other_face)
+                        || !tested.insert(([v, w], tb, other_face))
+                    {
+                        continue;
+                    }
+                    let tri = other_face.others().map(|k| *k.in_arr(&other_points));
+                    if let Some((t, _)) = ray_triangle(Ray3d::new(start, direction), tri)
+                        && t <= length
+                    {
+                        entry.2.push((t / length, tb));
+                    }
+                }
+            }
+        }
+    }
+    let mut out = Vec::new();
+    for (tetra, [start, end], mut crossings) in edges.into_values() {
+        crossings.sort_unstable_by(|x, y| x.0.total_cmp(&y.0));
+        for pair in crossings.windows(2) {
+            let [(t0, other), (t1, _)] = [pair[0], pair[1]];
+            let mid = start.lerp(end, (t0 + t1) / 2.0);
+            // crossings on the edge between two faces show up twice, with nothing between them
+            if t1 - t0 > f32::EPSILON && b.bvh.locate(b.mesh, mid).is_some() {
+                out.push(Crossing {
+                    point: b.transform.transform_point3(mid),
+                    tetra,
+                    other,
+                });
+            }
+        }
+    }
+    out
+}
+
+/// Turn a point of one mesh inside of another into a contact, pushing it out of the closest boundary face.
+///
+/// `other` is a tetrahedron of `b` that the point is in, which is used if there aren't any boundary faces.
+fn push_out<A: TetraMesh, B: TetraMesh>(
+    b: Collider<B>,
+    point: Vec3,
+    tetra_a: TetraId<A::Key>,
+    other: TetraId<B::Key>,
+) -> Option<Contact<A::Key, B::Key>> {
+    let local = b.transform.inverse().transform_point3(point);
+    let (tetra, closest, normal) = closest_boundary(b, local).unwrap_or((other, local, Vec3::ZERO));
+    let closest = b.transform.transform_point3(closest);
+    let offset = closest - point;
+    let depth = offset.length();
+    let normal = if depth > f32::EPSILON * point.abs().max_element().max(1.0) {
+        offset / depth
+    } else {
+        // normals take the inverse transpose, so they stay perpendicular to faces under non-uniform scales
+        b.transform
+            .matrix3
+            .inverse()
+            .transpose()
+            .mul_vec3(normal)
+            .normalize_or_zero()
+    };
+    Some(Contact {
+        point_a: point,
+        point_b: closest,
+        normal,
+        depth,
+        tetra_a,
+        tetra_b: tetra,
+    })
+}
+
+/// Find every contact between two meshes.
+///
+/// Contacts are found in both directions, at the boundary vertices of each mesh that are inside of the other one, and
+/// halfway along boundary edges that pass through the other one. The closest boundary points are found in the space of
+/// the other mesh, so depths are exact for rigid transforms and uniform scales, and close to it otherwise.
+pub fn collide<A: TetraMesh, B: TetraMesh>(
+    a: Collider<A>,
+    b: Collider<B>,
+) -> Collision<A::Key, B::Key> {
+    let mut pairs = Vec::new();
+    // only pairs with some boundary can have a boundary vertex inside of the other mesh
+    a.bvh
+        .query_pairs(b.bvh, a.transform.inverse() * b.transform, |ta, tb| {
+            let (in_a, in_b) = (has_boundary(a.mesh, ta), has_boundary(b.mesh, tb));
+            if in_a || in_b {
+                pairs.push((ta, tb, in_a, in_b));
+            }
+        });
+    let mut collision = Collision::default();
+    for inside in vertices_inside(a, b, pairs.iter().filter(|p| p.2).map(|p| (p.0, p.1))) {
+        let contact = a
+            .mesh
+            .get_vertex(inside.vertex)
+            .map(|v| a.transform.transform_point3(v.as_vec3()))
+            .and_then(|point| push_out::<A, B>(b, point, inside.tetra, inside.other));
+        collision.contacts.extend(contact);
+    }
+    for inside in vertices_inside(b, a, pairs.iter().filter(|p| p.3).map(|p| (p.1, p.0))) {
+        let contact = b
+            .mesh
+            .get_vertex(inside.vertex)
+            .map(|v| b.transform.transform_point3(v.as_vec3()))
+            .and_then(|point| push_out::<B, A>(a, point, inside.tetra, inside.other));
+        collision.contacts.extend(contact.map(Contact::swap));
+    }
+    // edges can only cross faces in pairs where both have some boundary
+    let both = pairs.iter().filter(|p| p.2 && p.3);
+    for crossing in edges_through(a, b, both.clone().map(|p| (p.0, p.1))) {
+        let contact = push_out::<A, B>(b, crossing.point, crossing.tetra, crossing.other);
+        collision.contacts.extend(contact);
+    }
+    for crossing in edges_through(b, a, both.map(|p| (p.1, p.0))) {
+        let contact = push_out::<B, A>(a, crossing.point, crossing.tetra, crossing.other);
+        collision.contacts.extend(contact.map(Contact::swap));
+    }
+    collision
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Cuboid, MeshBuilder};
+    use crate::slab_mesh::DefaultPackedMesh;
+    use std::f32::consts::{FRAC_PI_4, SQRT_2};
+
+    #[test]
+    fn rotated_cubes_cross_edge_to_edge() {
+        let mut mesh = DefaultPackedMesh::<u8>::new();
+        Cuboid::CENTERED_CUBE.append_to(&mut mesh);
+        let bvh = Bvh::build(&mesh);
+        // a vertical edge of one cube at x = sqrt(2), and a horizontal one of the other just behind it, so they cross
+        // without any vertex of either being inside of the other
+        let a = Collider::new(&mesh, &bvh, Affine3A::from_rotation_z(FRAC_PI_4));
+        let place = |x| {
+            Affine3A::from_translation(Vec3::new(x, 0.0, 0.0))
+                * Affine3A::from_rotation_y(FRAC_PI_4)
+        };
+        let apart = collide(a, Collider::new(&mesh, &bvh, place(2.0 * SQRT_2 + 0.1)));
+        assert!(apart.is_empty());
+        let collision = collide(a, Collider::new(&mesh, &bvh, place(2.0 * SQRT_2 - 0.1)));
+        let deepest = collision.deepest().expect("the cubes should collide");
+        assert!(deepest.normal.x < 0.0, "{deepest:?}");
+        assert!(deepest.depth > 0.0 && deepest.depth <= 0.1, "{deepest:?}");
+        for contact in &collision.contacts {
+            assert!(
+                contact.point_a.distance(Vec3::new(SQRT_2, 0.0, 0.0)) < 0.2,
+                "{contact:?}"
+            );
+        }
+    }
+}
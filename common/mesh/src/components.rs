@@ -0,0 +1,262 @@
+//! Connected components of a mesh.
+//!
+//! Two tetrahedra are connected if they share a face, so pieces that only touch at a vertex or an edge are separate
+//! components, since they can move independently of each other.
+
+use crate::traits::*;
+use std::collections::HashMap;
+
+/// The connected components of a mesh, from [`connected_components`].
+#[derive(Debug, Clone)]
+pub struct Components<K> {
+    /// The tetrahedra in each component, in the order they were found.
+    pub tetras: Vec<Vec<TetraId<K>>>,
+    /// The index of the component each tetrahedron is in.
+    pub labels: HashMap<TetraId<K>, usize>,
+}
+impl<K: Copy + Eq + std::hash::Hash> Components<K> {
+    /// Get the number of components.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.tetras.len()
+    }
+    /// Check if there are no components, which only happens for an empty mesh.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.tetras.is_empty()
+    }
+    /// Get the index of the component containing a tetrahedron.
+    #[inline(always)]
+    pub fn component_of(&self, id: TetraId<K>) -> Option<usize> {
+        self.labels.get(&id).copied()
+    }
+}
+
+/// Find the connected components of a mesh by flood filling over the face links.
+pub fn connected_components<M: TetraMesh>(mesh: &M) -> Components<M::Key> {
+    let mut out = Components {
+        tetras: Vec::new(),
+        labels: HashMap::new(),
+    };
+    let mut stack = Vec::new();
+    for (id, _) in mesh.tetras() {
+        if out.labels.contains_key(&id) {
+            continue;
+        }
+        let label = out.tetras.len();
+        let mut component = Vec::new();
+        out.labels.insert(id, label);
+        stack.push(id);
+        while let Some(id) = stack.pop() {
+            let Some(tet) = mesh.get_tetra(id) else {
+                continue;
+            };
+            component.push(id);
+            for face in VertexIdx::VALS {
+                if let Some((n, _)) = tet.face(face)
+                    && mesh.get_tetra(n).is_some()
+                    && !out.labels.contains_key(&n)
+                {
+                    out.labels.insert(n, label);
+                    stack.push(n);
+                }
+            }
+        }
+        out.tetras.push(component);
+    }
+    out
+}
+
+/// How the IDs in a mesh map to the IDs in another one, like a piece split off from it.
+#[derive(Debug, Clone)]
+pub struct IdMap<K, K2 = K> {
+    pub verts: HashMap<VertexId<K>, VertexId<K2>>,
+    pub tetras: HashMap<TetraId<K>, TetraId<K2>>,
+}
+
+/// Move each connected component of a mesh into its own new mesh.
+///
+/// Every tetrahedron is removed from the original mesh, along with the vertices they used, so only vertices that
+/// weren't part of any tetrahedron are left behind. The pieces come in the same order as in [`connected_components`],
+/// and each one comes with the mapping from its old IDs to its new ones.
+///
+/// Vertices shared by components that only touch at a vertex or an edge are copied into each of them.
+pub fn split_components<M: TetraMeshMut + Default>(mesh: &mut M) -> Vec<(M, IdMap<M::Key>)>
+where
+    M::Vertex: Clone,
+{
+    let components = connected_components(mesh);
+    split_groups(mesh, &components.tetras)
+}
+
+/// Move groups of tetrahedra into their own new meshes, dropping the links between different groups.
+///
+/// Vertices used by multiple groups are copied into each of them, and every vertex that was used is removed from the
+/// original mesh.
+pub(crate) fn split_groups<M: TetraMeshMut + Default>(
+    mesh: &mut M,
+    groups: &[Vec<TetraId<M::Key>>],
+) -> Vec<(M, IdMap<M::Key>)>
+where
+    M::Vertex: Clone,
+{
+    let mut pieces = Vec::with_capacity(groups.len());
+    for component in groups {
+        let mut piece = M::default();
+        let mut map = IdMap {
+            verts: HashMap::new(),
+            tetras: HashMap::with_capacity(component.len()),
+        };
+        for &id in component {
+            let Some(tet) = mesh.get_tetra(id) else {
+                continue;
+            };
+            for v in VertexIdx::VALS.map(|i| tet.vertex(i)) {
+                if !map.verts.contains_key(&v)
+                    && let Some(vert) = mesh.get_vertex(v)
+                {
+                    map.verts.insert(v, piece.add_vertex(vert.clone()));
+                }
+            }
+        }
+        // removing a tetrahedron can unlink its neighbors, so the links are read before anything is removed
+        let links = component
+            .iter()
+            .map(|&id| {
+                let tet = mesh.get_tetra(id)?;
+                Some(VertexIdx::VALS.map(|i| tet.face(i)))
+            })
+            .collect::<Vec<_>>();
+        let mut faces = Vec::with_capacity(component.len());
+        for (&id, links) in component.iter().zip(links) {
+            let (Some(links), Some(mut tet)) = (links, mesh.remove_tetra(id)) else {
+                continue;
+            };
+            for i in VertexIdx::VALS {
+                if let Some(&v) = map.verts.get(&tet.vertex(i)) {
+                    tet.set_vertex(i, v);
+                }
+                tet.set_face(i, None);
+            }
+            let new = piece.add_tetra(tet);
+            map.tetras.insert(id, new);
+            faces.push((new, links));
+        }
+        // the tetrahedra have to all be added before they can be linked to each other
+        for (id, links) in faces {
+            let Some(tet) = piece.get_tetra_mut(id) else {
+                continue;
+            };
+            for i in VertexIdx::VALS {
+                let link = i
+                    .in_arr(&links)
+                    .and_then(|(n, j)| Some((*map.tetras.get(&n)?, j)));
+                tet.set_face(i, link);
+            }
+        }
+        pieces.push((piece, map));
+    }
+    for (_, map) in &pieces {
+        for &v in map.verts.keys() {
+            mesh.remove_vertex(v);
+        }
+    }
+    pieces
+}
+
+/// Copy every vertex and tetrahedron from one mesh into another, keeping the links between them.
+pub(crate) fn copy_into<M: TetraMeshMut>(out: &mut M, src: &M) -> IdMap<M::Key>
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let verts = src
+        .verts()
+        .map(|(id, v)| (id, out.add_vertex(v.clone())))
+        .collect::<HashMap<_, _>>();
+    let tetras = src
+        .tetras()
+        .map(|(id, tet)| {
+            let mut tet = tet.clone();
+            for i in VertexIdx::VALS {
+                if let Some(&v) = verts.get(&tet.vertex(i)) {
+                    tet.set_vertex(i, v);
+                }
+                tet.set_face(i, None);
+            }
+            (id, out.add_tetra(tet))
+        })
+        .collect::<HashMap<_, _>>();
+    for (id, tet) in src.tetras() {
+        let Some(new) = out.get_tetra_mut(tetras[&id]) else {
+            continue;
+        };
+        for i in VertexIdx::VALS {
+            let link = tet.face(i).and_then(|(n, j)| Some((*tetras.get(&n)?, j)));
+            new.set_face(i, link);
+        }
+    }
+    IdMap { verts, tetras }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Hexahedron, MeshBuilder};
+    use crate::mass::mass_properties;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::assert_map_resolves;
+    use crate::validate::validate;
+    use crate::weld::{Weld, weld};
+    use bevy_math::Vec3;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    /// Two cubes on their own, and two that share an edge along the Z axis at (1, 4).
+    fn four_cubes() -> (Mesh, Weld<u32>) {
+        let mut mesh = Mesh::new();
+        for offset in [
+            Vec3::ZERO,
+            Vec3::new(3.0, 0.0, 0.0),
+            Vec3::new(0.0, 3.0, 0.0),
+            Vec3::new(1.0, 4.0, 0.0),
+        ] {
+            Hexahedron::UNIT_CUBE.translate(offset).append_to(&mut mesh);
+        }
+        let welded = weld(&mut mesh, 0.0);
+        (mesh, welded)
+    }
+
+    #[test]
+    fn split_disjoint_and_edge_touching_cubes() {
+        // the mesh isn't `Clone`, but building it again hands out the same ids
+        let (original, _) = four_cubes();
+        let (mut mesh, welded) = four_cubes();
+        assert_eq!(welded.verts.len(), 2);
+        assert_eq!(welded.linked, 0);
+        assert_eq!(connected_components(&mesh).len(), 4);
+
+        let pieces = split_components(&mut mesh);
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(mesh.tetras().count(), 0);
+        assert_eq!(mesh.verts().count(), 0);
+
+        let mut tetras = 0;
+        for (piece, map) in &pieces {
+            let report = validate(piece);
+            assert!(report.is_valid(), "{report}");
+            assert!((mass_properties(piece).volume - 1.0).abs() < 1e-5);
+            assert_eq!(map.tetras.len(), piece.tetras().count());
+            assert_eq!(map.verts.len(), piece.verts().count());
+            assert_map_resolves(&original, piece, map);
+            tetras += piece.tetras().count();
+        }
+        assert_eq!(tetras, original.tetras().count());
+        // the shared edge is copied into both of the cubes touching it
+        let copies = pieces
+            .iter()
+            .filter(|(_, map)| welded.verts.values().all(|v| map.verts.contains_key(v)))
+            .count();
+        assert_eq!(copies, 2);
+    }
+}
@@ -0,0 +1,611 @@
+//! Boolean operations between tetrahedral meshes.
+//!
+//! The boundaries of the two meshes are combined with a BSP tree over their faces, the same way as in
+//! [csg.js](https://github.com/evanw/csg.js), which keeps faces that lie on top of each other from being doubled up.
+//! The faces are then welded back into a watertight surface and filled in with [`TriangleSurface`], so the result is
+//! tetrahedralized from scratch rather than reusing the tetrahedra of the inputs. Each new tetrahedron takes its data
+//! from the input tetrahedron it's in.
+//!
+//! If the inputs don't overlap at all, the result is made by copying them instead, which keeps their tetrahedra as
+//! they are.
+
+use crate::bvh::Bvh;
+use crate::components::copy_into;
+use crate::edit::attach;
+use crate::geometry::{bounds_of, signed_volume};
+use crate::surface::{SurfaceError, TriangleSurface};
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::{HashMap, HashSet};
+
+/// How far points can be from a plane and still be on it, as a fraction of the size of the meshes.
+const EPSILON_FRACTION: f32 = 1e-5;
+/// The most rounds of fixing T-junctions before giving up on a surface.
+const MAX_JUNCTION_ROUNDS: usize = 8;
+
+/// A boolean operation, for [`csg`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CsgOp {
+    /// Everything in either mesh.
+    Union,
+    /// Everything in the first mesh that isn't in the second.
+    Difference,
+    /// Everything in both meshes.
+    Intersection,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Plane {
+    normal: Vec3,
+    w: f32,
+}
+
+#[derive(Debug, Clone)]
+struct Polygon {
+    verts: Vec<Vec3>,
+    plane: Plane,
+}
+impl Polygon {
+    fn flip(&mut self) {
+        self.verts.reverse();
+        self.plane.normal = -self.plane.normal;
+        self.plane.w = -self.plane.w;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Node {
+    plane: Option<Plane>,
+    front: Option<usize>,
+    back: Option<usize>,
+    polygons: Vec<Polygon>,
+}
+
+/// Where a polygon ended up relative to a plane.
+#[derive(Default)]
+struct Split {
+    coplanar_front: Vec<Polygon>,
+    coplanar_back: Vec<Polygon>,
+    front: Vec<Polygon>,
+    back: Vec<Polygon>,
+}
+
+/// A BSP tree over the boundary of a solid.
+///
+/// The nodes are stored in a flat list with the root first, and everything is done with explicit stacks, since the
+/// trees for convex shapes are as deep as they have faces.
+struct Bsp {
+    nodes: Vec<Node>,
+    epsilon: f32,
+}
+impl Bsp {
+    fn new(polygons: Vec<Polygon>, epsilon: f32) -> Self {
+        let mut this = Self {
+            nodes: vec![Node::default()],
+            epsilon,
+        };
+        this.build(polygons);
+        this
+    }
+    fn split(&self, plane: Plane, polygon: Polygon, out: &mut Split) {
+        const COPLANAR: u8 = 0;
+        const FRONT: u8 = 1;
+        const BACK: u8 = 2;
+        let dists = polygon
+            .verts
+            .iter()
+            .map(|&v| plane.normal.dot(v) - plane.w)
+            .collect::<Vec<_>>();
+        let kinds = dists
+            .iter()
+            .map(|&t| {
+                if t < -self.epsilon {
+                    BACK
+                } else if t > self.epsilon {
+                    FRONT
+                } else {
+                    COPLANAR
+                }
+            })
+            .collect::<Vec<_>>();
+        match kinds.iter().fold(COPLANAR, |a, &b| a | b) {
+            COPLANAR if plane.normal.dot(polygon.plane.normal) > 0.0 => {
+                out.coplanar_front.push(polygon)
+            }
+            COPLANAR => out.coplanar_back.push(polygon),
+            FRONT => out.front.push(polygon),
+            BACK => out.back.push(polygon),
+            _ => {
+                let n = polygon.verts.len();
+                let mut front = Vec::with_capacity(n + 1);
+                let mut back = Vec::with_capacity(n + 1);
+                for i in 0..n {
+                    let j = (i + 1) % n;
+                    let (vi, vj) = (polygon.verts[i], polygon.verts[j]);
+                    if kinds[i] != BACK {
+                        front.push(vi);
+                    }
+                    if kinds[i] != FRONT {
+                        back.push(vi);
+                    }
+                    if kinds[i] | kinds[j] == FRONT | BACK {
+                        let t = dists[i] / (dists[i] - dists[j]);
+                        let v = vi.lerp(vj, t);
+                        front.push(v);
+                        back.push(v);
+                    }
+                }
+                if front.len() >= 3 {
+                    out.front.push(Polygon {
+                        verts: front,
+                        plane: polygon.plane,
+                    });
+                }
+                if back.len() >= 3 {
+                    out.back.push(Polygon {
+                        verts: back,
+                        plane: polygon.plane,
+                    });
+                }
+            }
+        }
+    }
+    fn build(&mut self, polygons: Vec<Polygon>) {
+        let mut stack = vec![(0, polygons)];
+        while let Some((idx, polygons)) = stack.pop() {
+            if polygons.is_empty() {
+                continue;
+            }
+            let plane = *self.nodes[idx].plane.get_or_insert(polygons[0].plane);
+            let mut split = Split::default();
+            for polygon in polygons {
+                self.split(plane, polygon, &mut split);
+            }
+            let node = &mut self.nodes[idx];
+            node.polygons.append(&mut split.coplanar_front);
+            node.polygons.append(&mut split.coplanar_back);
+            for (polygons, is_front) in [(split.front, true), (split.back, false)] {
+                if polygons.is_empty() {
+                    continue;
+                }
+                let child = if is_front {
+                    self.nodes[idx].front
+                } else {
+                    self.nodes[idx].back
+                };
+                let child = child.unwrap_or_else(|| {
+                    self.nodes.push(Node::default());
+                    let child = self.nodes.len() - 1;
+                    if is_front {
+                        self.nodes[idx].front = Some(child);
+                    } else {
+                        self.nodes[idx].back = Some(child);
+                    }
+                    child
+                });
+                stack.push((child, polygons));
+            }
+        }
+    }
+    /// Turn the solid inside out.
+    fn invert(&mut self) {
+        for node in &mut self.nodes {
+            for polygon in &mut node.polygons {
+                polygon.flip();
+            }
+            if let Some(plane) = &mut node.plane {
+                plane.normal = -plane.normal;
+                plane.w = -plane.w;
+            }
+            std::mem::swap(&mut node.front, &mut node.back);
+        }
+    }
+    /// Remove the parts of some polygons that are inside of this solid.
+    fn clip_polygons(&self, polygons: Vec<Polygon>) -> Vec<Polygon> {
+        let mut out = Vec::new();
+        let mut stack = vec![(0, polygons)];
+        while let Some((idx, polygons)) = stack.pop() {
+            let node = &self.nodes[idx];
+            let Some(plane) = node.plane else {
+                out.extend(polygons);
+                continue;
+            };
+            let mut split = Split::default();
+            for polygon in polygons {
+                self.split(plane, polygon, &mut split);
+            }
+            let mut front = split.front;
+            front.append(&mut split.coplanar_front);
+            let mut back = split.back;
+            back.append(&mut split.coplanar_back);
+            match node.front {
+                Some(child) => stack.push((child, front)),
+                None => out.extend(front),
+            }
+            // anything behind a leaf is inside, so it's dropped
+            if let Some(child) = node.back {
+                stack.push((child, back));
+            }
+        }
+        out
+    }
+    /// Remove the parts of this solid's polygons that are inside of another one.
+    fn clip_to(&mut self, other: &Self) {
+        for node in &mut self.nodes {
+            node.polygons = other.clip_polygons(std::mem::take(&mut node.polygons));
+        }
+    }
+    fn into_polygons(self) -> Vec<Polygon> {
+        self.nodes.into_iter().flat_map(|n| n.polygons).collect()
+    }
+}
+
+/// Get the boundary faces of a mesh as polygons.
+fn boundary<M: TetraMesh>(mesh: &M) -> Vec<Polygon> {
+    let mut out = Vec::new();
+    for (_, tet) in mesh.tetras() {
+        let Some(points) = mesh.tetra_points(tet) else {
+            continue;
+        };
+        for face in VertexIdx::VALS {
+            if tet
+                .face(face)
+                .is_some_and(|(n, _)| mesh.get_tetra(n).is_some())
+            {
+                continue;
+            }
+            let verts = face.face_order().map(|i| *i.in_arr(&points));
+            let normal = (verts[1] - verts[0])
+                .cross(verts[2] - verts[0])
+                .normalize_or_zero();
+            if normal == Vec3::ZERO {
+                continue;
+            }
+            out.push(Polygon {
+                verts: verts.to_vec(),
+                plane: Plane {
+                    normal,
+                    w: normal.dot(verts[0]),
+                },
+            });
+        }
+    }
+    out
+}
+
+/// Weld polygons into a watertight triangle surface.
+///
+/// Splitting polygons computes the same points more than once with slightly different rounding, and splitting a
+/// polygon without splitting its neighbors leaves T-junctions, where a vertex of one polygon is in the middle of an edge
+/// of another. Points closer than `epsilon` are merged, and vertices at T-junctions are added to the edges they're on.
+fn weld(polygons: Vec<Polygon>, epsilon: f32) -> TriangleSurface<Vec<Vec3>, Vec<[u32; 3]>> {
+    let mut verts = Vec::new();
+    let mut grid = HashMap::<[i64; 3], Vec<u32>>::new();
+    let cell = |p: Vec3| (p / epsilon).floor().as_i64vec3().to_array();
+    let mut index = |p: Vec3, verts: &mut Vec<Vec3>| {
+        let [x, y, z] = cell(p);
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    for &i in grid.get(&[x + dx, y + dy, z + dz]).into_iter().flatten() {
+                        if verts[i as usize].distance(p) <= epsilon {
+                            return i;
+                        }
+                    }
+                }
+            }
+        }
+        verts.push(p);
+        let i = verts.len() as u32 - 1;
+        grid.entry([x, y, z]).or_default().push(i);
+        i
+    };
+    let mut polys = polygons
+        .into_iter()
+        .filter_map(|p| {
+            let mut ids = p
+                .verts
+                .into_iter()
+                .map(|v| index(v, &mut verts))
+                .collect::<Vec<_>>();
+            ids.dedup();
+            while ids.len() > 1 && ids.first() == ids.last() {
+                ids.pop();
+            }
+            (ids.len() >= 3).then_some(ids)
+        })
+        .collect::<Vec<_>>();
+
+    for _ in 0..MAX_JUNCTION_ROUNDS {
+        let edges = polys
+            .iter()
+            .flat_map(|p| (0..p.len()).map(|i| [p[i], p[(i + 1) % p.len()]]))
+            .collect::<HashSet<_>>();
+        let open = edges
+            .iter()
+            .filter(|&&[a, b]| !edges.contains(&[b, a]))
+            .copied()
+            .collect::<HashSet<_>>();
+        if open.is_empty() {
+            break;
+        }
+        let candidates = open.iter().flatten().copied().collect::<HashSet<_>>();
+        let mut changed = false;
+        for poly in &mut polys {
+            let mut out = Vec::with_capacity(poly.len());
+            for i in 0..poly.len() {
+                let [a, b] = [poly[i], poly[(i + 1) % poly.len()]];
+                out.push(a);
+                if !open.contains(&[a, b]) {
+                    continue;
+                }
+                let (pa, pb) = (verts[a as usize], verts[b as usize]);
+                let dir = pb - pa;
+                let len2 = dir.length_squared();
+                let mut between = candidates
+                    .iter()
+                    .filter(|&&c| c != a && c != b)
+                    .filter_map(|&c| {
+                        let pc = verts[c as usize];
+                        let t = (pc - pa).dot(dir) / len2;
+                        (t > 0.0 && t < 1.0 && (pa + dir * t).distance(pc) <= epsilon)
+                            .then_some((t, c))
+                    })
+                    .collect::<Vec<_>>();
+                between.sort_by(|x, y| x.0.total_cmp(&y.0));
+                changed |= !between.is_empty();
+                out.extend(between.into_iter().map(|(_, c)| c));
+            }
+            *poly = out;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut faces = Vec::new();
+    for mut poly in polys {
+        // clip off the fattest corner each time, which never makes flat triangles out of the vertices added along the
+        // edges, and avoids thin ones where it can
+        while poly.len() >= 3 {
+            let n = poly.len();
+            let ear = |i: usize| {
+                let [ia, ib, ic] = [(i + n - 1) % n, i, (i + 1) % n];
+                let [a, b, c] = [ia, ib, ic].map(|k| verts[poly[k] as usize]);
+                // the new edge can't run through any of the other vertices
+                let blocked = (0..n).filter(|k| ![ia, ib, ic].contains(k)).any(|k| {
+                    let p = verts[poly[k] as usize];
+                    let t = ((p - a).dot(c - a) / (c - a).length_squared()).clamp(0.0, 1.0);
+                    a.lerp(c, t).distance(p) <= epsilon
+                });
+                if blocked {
+                    return 0.0;
+                }
+                let area = (b - a).cross(c - b).length();
+                area / ((b - a).length_squared()
+                    + (c - b).length_squared()
+                    + (a - c).length_squared())
+            };
+            let Some((i, quality)) = (0..n)
+                .map(|i| (i, ear(i)))
+                .max_by(|x, y| x.1.total_cmp(&y.1))
+            else {
+                break;
+            };
+            if quality <= f32::EPSILON {
+                break;
+            }
+            faces.push([poly[(i + n - 1) % n], poly[i], poly[(i + 1) % n]]);
+            poly.remove(i);
+        }
+    }
+    TriangleSurface::new(verts, faces)
+}
+
+/// Combine two meshes with a boolean operation.
+///
+/// Each tetrahedron of the result takes its data from the tetrahedron of the inputs containing its center, preferring
+/// the first mesh when both do. Both meshes have to be closed solids, and the result has to be one too, so this fails
+/// if the result would have edges shared by more than two faces, like two cubes touching along an edge.
+pub fn csg<M: TetraMeshMut + Default>(a: &M, b: &M, op: CsgOp) -> Result<M, SurfaceError>
+where
+    M::Vertex: Clone + From<Vec3>,
+    M::Tetra: Clone,
+{
+    let [amin, amax] = bounds_of(a.verts().map(|(_, v)| v.as_vec3()));
+    let [bmin, bmax] = bounds_of(b.verts().map(|(_, v)| v.as_vec3()));
+    let disjoint = a.tetras().next().is_none()
+        || b.tetras().next().is_none()
+        || amax.cmplt(bmin).any()
+        || bmax.cmplt(amin).any();
+    if disjoint {
+        let mut out = M::default();
+        match op {
+            CsgOp::Union => {
+                copy_into(&mut out, a);
+                copy_into(&mut out, b);
+            }
+            CsgOp::Difference => {
+                copy_into(&mut out, a);
+            }
+            CsgOp::Intersection => {}
+        }
+        return Ok(out);
+    }
+
+    let epsilon = (amax.max(bmax) - amin.min(bmin)).length() * EPSILON_FRACTION;
+    let mut ta = Bsp::new(boundary(a), epsilon);
+    let mut tb = Bsp::new(boundary(b), epsilon);
+    // the same steps as csg.js, where inverting turns a solid inside out so that clipping keeps the inside instead
+    match op {
+        CsgOp::Union => {
+            ta.clip_to(&tb);
+            tb.clip_to(&ta);
+            tb.invert();
+            tb.clip_to(&ta);
+            tb.invert();
+        }
+        CsgOp::Difference => {
+            ta.invert();
+            ta.clip_to(&tb);
+            tb.clip_to(&ta);
+            tb.invert();
+            tb.clip_to(&ta);
+            tb.invert();
+        }
+        CsgOp::Intersection => {
+            ta.invert();
+            tb.clip_to(&ta);
+            tb.invert();
+            ta.clip_to(&tb);
+            tb.clip_to(&ta);
+        }
+    }
+    ta.build(tb.into_polygons());
+    if op != CsgOp::Union {
+        ta.invert();
+    }
+    let polygons = ta.into_polygons();
+    if polygons.is_empty() {
+        return Ok(M::default());
+    }
+    let filled = weld(polygons, epsilon).tetrahedralize()?;
+
+    let sources = match op {
+        CsgOp::Union => vec![(a, Bvh::build(a)), (b, Bvh::build(b))],
+        _ => vec![(a, Bvh::build(a))],
+    };
+    let mut out = M::default();
+    let ids = filled
+        .verts
+        .iter()
+        .map(|&p| out.add_vertex(p.into()))
+        .collect::<Vec<_>>();
+    let mut tetras = Vec::with_capacity(filled.tetras.len());
+    for &tet in &filled.tetras {
+        let mut points = tet.map(|i| filled.verts[i as usize]);
+        let mut verts = tet.map(|i| ids[i as usize]);
+        if signed_volume(points) < 0.0 {
+            points.swap(2, 3);
+            verts.swap(2, 3);
+        }
+        let center = points.iter().sum::<Vec3>() / 4.0;
+        let source = sources
+            .iter()
+            .find_map(|(mesh, bvh)| bvh.locate(*mesh, center).map(|id| (*mesh, id)))
+            .or_else(|| {
+                sources
+                    .iter()
+                    .filter_map(|(mesh, bvh)| {
+                        let (id, p) = bvh.nearest(*mesh, center)?;
+                        Some((p.distance_squared(center), *mesh, id))
+                    })
+                    .min_by(|x, y| x.0.total_cmp(&y.0))
+                    .map(|(_, mesh, id)| (mesh, id))
+            });
+        let Some(mut tet) = source.and_then(|(mesh, id)| mesh.get_tetra(id)).cloned() else {
+            continue;
+        };
+        for (i, v) in VertexIdx::VALS.into_iter().zip(verts) {
+            tet.set_vertex(i, v);
+            tet.set_face(i, None);
+        }
+        tetras.push(tet);
+    }
+    attach(&mut out, HashMap::new(), tetras);
+    Ok(out)
+}
+
+/// Combine two meshes into one containing everything in either of them.
+pub fn union<M: TetraMeshMut + Default>(a: &M, b: &M) -> Result<M, SurfaceError>
+where
+    M::Vertex: Clone + From<Vec3>,
+    M::Tetra: Clone,
+{
+    csg(a, b, CsgOp::Union)
+}
+
+/// Cut one mesh out of another.
+pub fn difference<M: TetraMeshMut + Default>(a: &M, b: &M) -> Result<M, SurfaceError>
+where
+    M::Vertex: Clone + From<Vec3>,
+    M::Tetra: Clone,
+{
+    csg(a, b, CsgOp::Difference)
+}
+
+/// Get the overlap between two meshes.
+pub fn intersection<M: TetraMeshMut + Default>(a: &M, b: &M) -> Result<M, SurfaceError>
+where
+    M::Vertex: Clone + From<Vec3>,
+    M::Tetra: Clone,
+{
+    csg(a, b, CsgOp::Intersection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Cuboid, MeshBuilder};
+    use crate::mass::mass_properties;
+    use crate::slab_mesh::SlabMesh;
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32, BasicFace<u32>, u8>>;
+
+    /// Two unit cubes overlapping in a cube with half of their side length, tagging the tetrahedra of each with its own
+    /// value.
+    fn cubes() -> (Mesh, Mesh) {
+        let mut a = Cuboid::UNIT_CUBE.build::<Mesh>();
+        let mut b = Cuboid::UNIT_CUBE
+            .translate(Vec3::splat(0.5))
+            .build::<Mesh>();
+        for (mesh, data) in [(&mut a, 1), (&mut b, 2)] {
+            let ids = mesh.tetras().map(|(id, _)| id).collect::<Vec<_>>();
+            for id in ids {
+                mesh.get_tetra_mut(id).unwrap().data = data;
+            }
+        }
+        (a, b)
+    }
+
+    fn check(mesh: &Mesh, volume: f32) {
+        let report = validate(mesh);
+        assert!(report.is_valid(), "{report}");
+        let found = mass_properties(mesh).volume;
+        assert!(
+            (found - volume).abs() < 1e-4,
+            "expected {volume}, found {found}"
+        );
+    }
+
+    #[test]
+    fn union_of_cubes() {
+        let (a, b) = cubes();
+        check(&union(&a, &b).unwrap(), 1.875);
+    }
+
+    #[test]
+    fn difference_of_cubes() {
+        let (a, b) = cubes();
+        check(&difference(&a, &b).unwrap(), 0.875);
+    }
+
+    #[test]
+    fn intersection_of_cubes() {
+        let (a, b) = cubes();
+        check(&intersection(&a, &b).unwrap(), 0.125);
+    }
+
+    #[test]
+    fn data_comes_from_source() {
+        let (a, b) = cubes();
+        let out = union(&a, &b).unwrap();
+        for (_, tet) in out.tetras() {
+            let center = out.tetra_points(tet).unwrap().iter().sum::<Vec3>() / 4.0;
+            // the first mesh is preferred where they overlap
+            let expected = if center.cmple(Vec3::ONE).all() { 1 } else { 2 };
+            assert_eq!(tet.data, expected, "tetrahedron centered at {center}");
+        }
+    }
+}
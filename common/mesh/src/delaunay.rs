@@ -0,0 +1,362 @@
+//! Delaunay tetrahedralization of point clouds.
+//!
+//! Points are inserted one at a time with the Bowyer-Watson algorithm: every tetrahedron whose circumsphere contains
+//! the new point is removed, and the resulting cavity is filled by connecting its boundary to the point. All of the
+//! decisions are made with the exact predicates from [`crate::predicates`], so inputs like points on a grid don't
+//! break the connectivity.
+
+use crate::predicates::{insphere_perturbed, orient3d};
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// The maximum number of steps [`locate`] takes before giving up.
+const MAX_WALK_STEPS: usize = 1 << 16;
+
+/// Where a point was found by [`locate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Location<K> {
+    /// The point is inside of this tetrahedron, or on its boundary.
+    Inside(TetraId<K>),
+    /// The point is outside of the mesh, past this boundary face.
+    Outside(TetraId<K>, VertexIdx),
+}
+
+/// Find the tetrahedron containing a point by walking over the adjacency graph.
+///
+/// Starting at `start`, this repeatedly steps through a face that has the point on its far side, until it reaches a
+/// tetrahedron containing the point or a boundary face. This only finds points in the same connected component as
+/// `start`, and is fastest when `start` is close to the point. Faces are tried in a rotating order, so this terminates
+/// even in meshes that aren't Delaunay.
+///
+/// This returns `None` if `start` isn't in the mesh, if the walk reaches a tetrahedron with missing vertices, or if it
+/// takes too many steps.
+pub fn locate<M: TetraMesh>(
+    mesh: &M,
+    start: TetraId<M::Key>,
+    point: Vec3,
+) -> Option<Location<M::Key>> {
+    let mut current = start;
+    for step in 0..MAX_WALK_STEPS {
+        let tet = mesh.get_tetra(current)?;
+        let points = mesh.tetra_points(tet)?;
+        let next = (0..4).find_map(|offset| {
+            let face = VertexIdx::VALS[(step + offset) % 4];
+            let mut replaced = points;
+            *face.in_arr_mut(&mut replaced) = point;
+            let [a, b, c, d] = replaced;
+            (orient3d(a, b, c, d) == Ordering::Less).then(|| (face, tet.face(face)))
+        });
+        match next {
+            None => return Some(Location::Inside(current)),
+            Some((face, None)) => return Some(Location::Outside(current, face)),
+            Some((_, Some((n, _)))) => current = n,
+        }
+    }
+    None
+}
+
+/// An error from inserting a point into a [`Delaunay`] tetrahedralization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DelaunayError<K> {
+    /// The point is outside of the bounds that the tetrahedralization was created with, or isn't finite.
+    OutOfBounds,
+    /// There's already a vertex at this position.
+    Duplicate(VertexId<K>),
+}
+impl<K: Debug> Display for DelaunayError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfBounds => f.write_str("point is outside of the tetrahedralization's bounds"),
+            Self::Duplicate(id) => write!(f, "point is already in the mesh as {id:?}"),
+        }
+    }
+}
+impl<K: Debug> std::error::Error for DelaunayError<K> {}
+
+/// An incremental Delaunay tetrahedralization.
+///
+/// This starts with a single large tetrahedron enclosing the given bounds, which is split up as points are inserted.
+/// [`Self::finish`] removes it again, leaving the tetrahedralization of the inserted points. Since the enclosing
+/// tetrahedron is finite, tetrahedra on the convex hull that are nearly flat can be missing from the result.
+pub struct Delaunay<M: TetraMesh> {
+    mesh: M,
+    super_verts: [VertexId<M::Key>; 4],
+    hint: TetraId<M::Key>,
+}
+impl<M: TetraMeshMut> Delaunay<M>
+where
+    M::Vertex: From<Vec3>,
+    M::Tetra: From<TetraPrimitive<M::Key>>,
+{
+    /// Start a new tetrahedralization in a mesh, which can hold points within the given bounds.
+    ///
+    /// Any tetrahedra already in the mesh are left alone, and won't be connected to the new ones.
+    pub fn new(mut mesh: M, [min, max]: [Vec3; 2]) -> Self {
+        let center = (min + max) / 2.0;
+        let radius = ((max - min).length() / 2.0).max(1.0);
+        // a regular tetrahedron inscribed in a cube contains a ball with a third of the cube's size, and going much
+        // further than that keeps the hull from being distorted by the enclosing vertices
+        let scale = radius * 1024.0;
+        let [a, b, c, d] = [
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+        ]
+        .map(|dir| mesh.add_vertex((center + dir * scale).into()));
+        let super_verts = [a, b, d, c];
+        let hint = mesh.add_tetra(super_verts.map(|v| (v, None)).into());
+        Self {
+            mesh,
+            super_verts,
+            hint,
+        }
+    }
+    /// Get the mesh being built.
+    ///
+    /// Until [`Self::finish`] is called, this includes the enclosing tetrahedron.
+    pub const fn mesh(&self) -> &M {
+        &self.mesh
+    }
+    /// Find the tetrahedron containing a point.
+    pub fn locate(&self, point: Vec3) -> Option<Location<M::Key>> {
+        locate(&self.mesh, self.hint, point)
+    }
+    /// Insert a vertex, and restore the Delaunay property around it.
+    pub fn insert(&mut self, vert: M::Vertex) -> Result<VertexId<M::Key>, DelaunayError<M::Key>> {
+        let point = vert.as_vec3();
+        if !point.is_finite() {
+            return Err(DelaunayError::OutOfBounds);
+        }
+        let Some(Location::Inside(start)) = self.locate(point) else {
+            return Err(DelaunayError::OutOfBounds);
+        };
+        let tet = self.mesh.get_tetra(start).unwrap();
+        for i in VertexIdx::VALS {
+            let v = tet.vertex(i);
+            if self.mesh.get_vertex(v).map(VertexData::as_vec3) == Some(point) {
+                return Err(DelaunayError::Duplicate(v));
+            }
+        }
+        let new = self.mesh.add_vertex(vert);
+
+        // find every tetrahedron whose circumsphere contains the new point, which is always a connected region
+        let mut cavity = HashSet::from([start]);
+        let mut checked = HashSet::from([start]);
+        let mut stack = vec![start];
+        let mut boundary = Vec::new();
+        while let Some(id) = stack.pop() {
+            let tet = self.mesh.get_tetra(id).unwrap();
+            for face in VertexIdx::VALS {
+                let adj = tet.face(face);
+                if let Some((n, _)) = adj {
+                    if cavity.contains(&n) {
+                        continue;
+                    }
+                    if checked.insert(n) && self.in_conflict(n, point, new) {
+                        cavity.insert(n);
+                        stack.push(n);
+                        continue;
+                    }
+                }
+                boundary.push((face.face_order().map(|i| tet.vertex(i)), adj));
+            }
+        }
+        for id in cavity {
+            self.mesh.remove_tetra(id);
+        }
+
+        let mut edges = HashMap::with_capacity(boundary.len() * 2);
+        for ([a, b, c], adj) in boundary {
+            let id = self
+                .mesh
+                .add_tetra([(new, adj), (a, None), (b, None), (c, None)].into());
+            if let Some((n, i)) = adj
+                && let Some(t) = self.mesh.get_tetra_mut(n)
+            {
+                t.set_face(i, Some((id, VertexIdx::V0)));
+            }
+            for (face, mut edge) in [
+                (VertexIdx::V1, [b, c]),
+                (VertexIdx::V2, [c, a]),
+                (VertexIdx::V3, [a, b]),
+            ] {
+                edge.sort_unstable();
+                if let Some((other, i)) = edges.remove(&edge) {
+                    if let Some(t) = self.mesh.get_tetra_mut(id) {
+                        t.set_face(face, Some((other, i)));
+                    }
+                    if let Some(t) = self.mesh.get_tetra_mut(other) {
+                        t.set_face(i, Some((id, face)));
+                    }
+                } else {
+                    edges.insert(edge, (id, face));
+                }
+            }
+            self.hint = id;
+        }
+        Ok(new)
+    }
+    /// Check if a point is inside of a tetrahedron's circumsphere.
+    fn in_conflict(&self, id: TetraId<M::Key>, point: Vec3, vert: VertexId<M::Key>) -> bool {
+        let Some(tet) = self.mesh.get_tetra(id) else {
+            return false;
+        };
+        let Some(points) = self.mesh.tetra_points(tet) else {
+            return false;
+        };
+        let [a, b, c, d] = VertexIdx::VALS.map(|i| (*i.in_arr(&points), tet.vertex(i)));
+        insphere_perturbed([a, b, c, d, (point, vert)]) == Ordering::Greater
+    }
+    /// Get the mesh without removing the enclosing tetrahedron.
+    ///
+    /// This is useful to classify the tetrahedra starting from the outside, since every tetrahedron touching one of the
+    /// enclosing vertices is outside of the convex hull of the inserted points.
+    pub fn into_mesh(self) -> M {
+        self.mesh
+    }
+    /// Remove the enclosing tetrahedron, and get the final mesh.
+    pub fn finish(mut self) -> M {
+        let outer = self
+            .mesh
+            .tetras()
+            .filter(|(_, t)| {
+                VertexIdx::VALS
+                    .iter()
+                    .any(|&i| self.super_verts.contains(&t.vertex(i)))
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in outer {
+            self.mesh.remove_tetra(id);
+        }
+        for v in self.super_verts {
+            self.mesh.remove_vertex(v);
+        }
+        self.mesh
+    }
+}
+impl<M: TetraMesh + Debug> Debug for Delaunay<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Delaunay")
+            .field("mesh", &self.mesh)
+            .field("super_verts", &self.super_verts)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Build the Delaunay tetrahedralization of a set of vertices.
+///
+/// Vertices at the same position as an earlier one are skipped.
+pub fn tetrahedralize<M: TetraMeshMut + Default>(verts: impl IntoIterator<Item = M::Vertex>) -> M
+where
+    M::Vertex: From<Vec3>,
+    M::Tetra: From<TetraPrimitive<M::Key>>,
+{
+    let verts = verts
+        .into_iter()
+        .filter(|v| v.as_vec3().is_finite())
+        .collect::<Vec<_>>();
+    let bounds = verts
+        .iter()
+        .fold([Vec3::INFINITY, Vec3::NEG_INFINITY], |[min, max], v| {
+            let p = v.as_vec3();
+            [min.min(p), max.max(p)]
+        });
+    if verts.is_empty() {
+        return M::default();
+    }
+    let mut delaunay = Delaunay::new(M::default(), bounds);
+    for v in verts {
+        let _ = delaunay.insert(v);
+    }
+    delaunay.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::signed_volume;
+    use crate::predicates::insphere;
+    use crate::slab_mesh::SlabMesh;
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    fn volume(mesh: &Mesh) -> f32 {
+        mesh.tetras()
+            .map(|(_, tet)| signed_volume(mesh.tetra_points(tet).unwrap()))
+            .sum()
+    }
+
+    #[test]
+    fn grid() {
+        for n in 2..=5 {
+            let mut verts = Vec::new();
+            for i in 0..n {
+                for j in 0..n {
+                    for k in 0..n {
+                        verts.push(Vertex::from(Vec3::new(i as f32, j as f32, k as f32)));
+                    }
+                }
+            }
+            let mesh = tetrahedralize::<Mesh>(verts);
+            assert!(validate(&mesh).is_valid(), "{n}: {}", validate(&mesh));
+            assert_eq!(mesh.verts().count(), n * n * n);
+            let expected = ((n - 1) * (n - 1) * (n - 1)) as f32;
+            assert!(
+                (volume(&mesh) - expected).abs() < 1e-3,
+                "{n}: volume {} instead of {expected}",
+                volume(&mesh)
+            );
+        }
+    }
+
+    #[test]
+    fn insert() {
+        let mut seed = 7u32;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) as f32 / (1 << 24) as f32
+        };
+        let mut delaunay = Delaunay::new(Mesh::new(), [Vec3::ZERO, Vec3::ONE]);
+        let mut points = Vec::new();
+        for _ in 0..64 {
+            let point = Vec3::new(random(), random(), random());
+            let id = delaunay.insert(point.into()).unwrap();
+            points.push((id, point));
+        }
+        let (id, point) = points[10];
+        assert_eq!(
+            delaunay.insert(point.into()),
+            Err(DelaunayError::Duplicate(id))
+        );
+        assert_eq!(
+            delaunay.insert(Vec3::NAN.into()),
+            Err(DelaunayError::OutOfBounds)
+        );
+        assert!(matches!(
+            delaunay.locate(Vec3::splat(0.5)),
+            Some(Location::Inside(_))
+        ));
+        let mesh = delaunay.finish();
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+        assert_eq!(mesh.verts().count(), points.len());
+        // no point is inside of the circumsphere of any tetrahedron
+        for (id, tet) in mesh.tetras() {
+            let [a, b, c, d] = mesh.tetra_points(tet).unwrap();
+            for &(v, p) in &points {
+                if VertexIdx::VALS.iter().all(|&i| tet.vertex(i) != v) {
+                    assert_ne!(
+                        insphere(a, b, c, d, p),
+                        Ordering::Greater,
+                        "{v:?} is inside of {id:?}"
+                    );
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,544 @@
+//! Diffusion of scalar values through a mesh, for heat and anything else that spreads out.
+//!
+//! Values are stored either per vertex, where they're linear inside of each tetrahedron and spread with the finite
+//! element Laplacian (the 3D version of the cotangent Laplacian), or per tetrahedron, where they're constant inside of
+//! each one and spread through the faces between neighbors. Either way, how quickly they spread comes from the data of
+//! each tetrahedron through [`Conductivity`].
+//!
+//! Steps are implicit, so they're stable with any timestep, which matters since diffusion through small tetrahedra is
+//! much faster than anything else in a game. Each step solves a sparse linear system with the conjugate gradient method.
+
+use crate::geometry::{barycentric_gradients, signed_volume};
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// The largest number of conjugate gradient iterations in a step.
+const MAX_ITERATIONS: usize = 200;
+/// How small the residual has to get to stop iterating, relative to the right hand side.
+const TOLERANCE: f32 = 1e-5;
+
+/// Something that conducts, used to weight tetrahedra in a [`Laplacian`].
+///
+/// This is implemented for [`Tetra`] when its data has a conductivity, so heat can spread differently through
+/// different materials.
+pub trait Conductivity {
+    /// Get how quickly values spread, in area per unit of time.
+    fn conductivity(&self) -> f32;
+}
+/// Without any data, everything has a conductivity of 1.
+impl Conductivity for () {
+    #[inline(always)]
+    fn conductivity(&self) -> f32 {
+        1.0
+    }
+}
+impl Conductivity for f32 {
+    #[inline(always)]
+    fn conductivity(&self) -> f32 {
+        *self
+    }
+}
+impl<K, F, T: Conductivity> Conductivity for Tetra<K, F, T> {
+    #[inline(always)]
+    fn conductivity(&self) -> f32 {
+        self.data.conductivity()
+    }
+}
+
+/// The Laplacian of a mesh, over either its vertices or its tetrahedra.
+///
+/// This is the stiffness matrix, which is positive semi-definite, along with the lumped mass matrix. A mesh's
+/// Laplacian has to be rebuilt when its shape or its conductivities change.
+#[derive(Debug, Clone)]
+pub struct Laplacian<I> {
+    ids: Vec<I>,
+    indices: HashMap<I, usize>,
+    /// How much of the mesh each element stands for, which is the volume around it.
+    mass: Vec<f32>,
+    diagonal: Vec<f32>,
+    /// The off-diagonal entries, in compressed rows.
+    offsets: Vec<usize>,
+    columns: Vec<usize>,
+    values: Vec<f32>,
+}
+impl<I: Copy + Hash + Eq> Laplacian<I> {
+    fn from_entries(ids: Vec<I>, mass: Vec<f32>, entries: HashMap<[usize; 2], f32>) -> Self {
+        let indices = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+        let mut diagonal = vec![0.0; ids.len()];
+        let mut rows = vec![Vec::new(); ids.len()];
+        for ([i, j], w) in entries {
+            // each entry pulls the two elements towards each other
+            rows[i].push((j, -w));
+            rows[j].push((i, -w));
+            diagonal[i] += w;
+            diagonal[j] += w;
+        }
+        let mut offsets = Vec::with_capacity(ids.len() + 1);
+        let mut columns = Vec::new();
+        let mut values = Vec::new();
+        offsets.push(0);
+        for mut row in rows {
+            row.sort_unstable_by_key(|e| e.0);
+            columns.extend(row.iter().map(|e| e.0));
+            values.extend(row.iter().map(|e| e.1));
+            offsets.push(columns.len());
+        }
+        Self {
+            ids,
+            indices,
+            mass,
+            diagonal,
+            offsets,
+            columns,
+            values,
+        }
+    }
+
+    /// Get the number of elements.
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+    /// Check if there aren't any elements.
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+    /// Get the IDs of the elements, in the order their values are stored.
+    #[inline(always)]
+    pub fn ids(&self) -> &[I] {
+        &self.ids
+    }
+    /// Get the index of an element's value.
+    #[inline(always)]
+    pub fn index_of(&self, id: I) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+    /// Get the volume each element stands for, which sums to the volume of the mesh.
+    #[inline(always)]
+    pub fn mass(&self) -> &[f32] {
+        &self.mass
+    }
+
+    /// Multiply the values by the Laplacian, giving how much each one is losing to its neighbors.
+    pub fn apply(&self, values: &[f32], out: &mut [f32]) {
+        for (i, out) in out.iter_mut().enumerate().take(self.len()) {
+            let range = self.offsets[i]..self.offsets[i + 1];
+            *out = self.diagonal[i] * values[i]
+                + self.columns[range.clone()]
+                    .iter()
+                    .zip(&self.values[range])
+                    .map(|(&j, &w)| w * values[j])
+                    .sum::<f32>();
+        }
+    }
+
+    /// Diffuse values for some amount of time, with a backward Euler step.
+    ///
+    /// The boundary is insulated, so the total amount, weighted by [`Self::mass`], stays the same.
+    pub fn diffuse(&self, values: &mut [f32], dt: f32) {
+        let n = self.len();
+        if n == 0 || dt <= 0.0 {
+            return;
+        }
+        // solve (M + dt L) x = M b, with a Jacobi preconditioner
+        let apply = |x: &[f32], out: &mut [f32]| {
+            self.apply(x, out);
+            for i in 0..n {
+                out[i] = self.mass[i] * x[i] + dt * out[i];
+            }
+        };
+        let precondition = (0..n)
+            .map(|i| (self.mass[i] + dt * self.diagonal[i]).recip())
+            .map(|d| if d.is_finite() { d } else { 0.0 })
+            .collect::<Vec<_>>();
+        let rhs = (0..n).map(|i| self.mass[i] * values[i]).collect::<Vec<_>>();
+        let mut ax = vec![0.0; n];
+        apply(values, &mut ax);
+        let mut r = (0..n).map(|i| rhs[i] - ax[i]).collect::<Vec<_>>();
+        let mut z = (0..n).map(|i| r[i] * precondition[i]).collect::<Vec<_>>();
+        let mut p = z.clone();
+        let mut rz = dot(&r, &z);
+        let limit = dot(&rhs, &rhs) * TOLERANCE * TOLERANCE;
+        let mut ap = vec![0.0; n];
+        for _ in 0..MAX_ITERATIONS {
+            if dot(&r, &r) <= limit {
+                break;
+            }
+            apply(&p, &mut ap);
+            let pap = dot(&p, &ap);
+            if pap <= 0.0 {
+                break;
+            }
+            let alpha = rz / pap;
+            for i in 0..n {
+                values[i] += alpha * p[i];
+                r[i] -= alpha * ap[i];
+                z[i] = r[i] * precondition[i];
+            }
+            let next = dot(&r, &z);
+            let beta = next / rz;
+            rz = next;
+            for i in 0..n {
+                p[i] = z[i] + beta * p[i];
+            }
+        }
+    }
+}
+impl<K: Copy + Hash + Eq> Laplacian<VertexId<K>> {
+    /// Build the finite element Laplacian over the vertices of a mesh.
+    ///
+    /// Values are linear inside of each tetrahedron. Vertices that aren't part of any tetrahedron are left out, and so
+    /// are degenerate tetrahedra.
+    pub fn vertices<M: TetraMesh<Key = K>>(mesh: &M) -> Self
+    where
+        M::Tetra: Conductivity,
+    {
+        let mut ids = Vec::new();
+        let mut indices = HashMap::new();
+        let mut mass = Vec::new();
+        let mut entries = HashMap::new();
+        for (_, tet) in mesh.tetras() {
+            let Some(points) = mesh.tetra_points(tet) else {
+                continue;
+            };
+            let volume = signed_volume(points);
+            if volume <= 0.0 {
+                continue;
+            }
+            let corners = VertexIdx::VALS.map(|i| {
+                *indices.entry(tet.vertex(i)).or_insert_with(|| {
+                    ids.push(tet.vertex(i));
+                    mass.push(0.0);
+                    ids.len() - 1
+                })
+            });
+            let grads = barycentric_gradients(points);
+            let k = tet.conductivity() * volume;
+            for a in 0..4 {
+                mass[corners[a]] += volume / 4.0;
+                for b in a + 1..4 {
+                    // the negated off-diagonal entry, which is positive for well shaped tetrahedra
+                    let w = -k * grads[a].dot(grads[b]);
+                    let key = [corners[a].min(corners[b]), corners[a].max(corners[b])];
+                    *entries.entry(key).or_insert(0.0) += w;
+                }
+            }
+        }
+        Self::from_entries(ids, mass, entries)
+    }
+}
+impl<K: Copy + Hash + Eq> Laplacian<TetraId<K>> {
+    /// Build the Laplacian over the tetrahedra of a mesh, where values flow through the faces between neighbors.
+    ///
+    /// Values are constant inside of each tetrahedron. The flow through a face goes with its area, and against the
+    /// distances from the centers of the tetrahedra to it, divided by their conductivities.
+    pub fn tetras<M: TetraMesh<Key = K>>(mesh: &M) -> Self
+    where
+        M::Tetra: Conductivity,
+    {
+        let mut ids = Vec::new();
+        let mut mass = Vec::new();
+        let mut indices = HashMap::new();
+        for (id, tet) in mesh.tetras() {
+            let Some(points) = mesh.tetra_points(tet) else {
+                continue;
+            };
+            indices.insert(id, ids.len());
+            ids.push(id);
+            mass.push(signed_volume(points).max(0.0));
+        }
+        // how hard it is to get from the center of a tetrahedron to one of its faces
+        let resistance = |tet: &M::Tetra, face: VertexIdx| -> Option<(f32, f32)> {
+            let points = mesh.tetra_points(tet)?;
+            let [a, b, c] = face.face_order().map(|i| *i.in_arr(&points));
+            let normal = (b - a).cross(c - a);
+            let area = normal.length() / 2.0;
+            let center = points.iter().sum::<Vec3>() / 4.0;
+            let distance = (a - center).dot(normal.normalize_or_zero()).abs();
+            Some((area, distance / tet.conductivity()))
+        };
+        let mut entries = HashMap::new();
+        for (id, tet) in mesh.tetras() {
+            for face in VertexIdx::VALS {
+                let Some((n, j)) = tet.face(face) else {
+                    continue;
+                };
+                let (Some(&i), Some(&k)) = (indices.get(&id), indices.get(&n)) else {
+                    continue;
+                };
+                let Some(other) = mesh.get_tetra(n) else {
+                    continue;
+                };
+                if i > k {
+                    continue;
+                }
+                let (Some((area, r0)), Some((_, r1))) =
+                    (resistance(tet, face), resistance(other, j))
+                else {
+                    continue;
+                };
+                let w = area / (r0 + r1);
+                if w.is_finite() {
+                    entries.insert([i, k], w);
+                }
+            }
+        }
+        Self::from_entries(ids, mass, entries)
+    }
+}
+
+#[inline(always)]
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Which way a value has to cross a [`Threshold`] to trigger it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Crossing {
+    /// When the value goes from below the limit to at or above it.
+    Rising,
+    /// When the value goes from at or above the limit to below it.
+    Falling,
+    /// Both ways.
+    Either,
+}
+
+/// A limit to watch values for, like a melting point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Threshold {
+    pub limit: f32,
+    pub crossing: Crossing,
+}
+
+/// A value crossing a threshold during a step of a [`Channel`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThresholdEvent<I> {
+    /// The element whose value crossed the threshold.
+    pub id: I,
+    /// The index of the threshold, in the order they were added.
+    pub threshold: usize,
+    /// The value after the step.
+    pub value: f32,
+    /// Whether the value went up through the limit, rather than down.
+    pub rising: bool,
+}
+
+/// A scalar value on each element of a mesh, like a temperature, which diffuses over time.
+#[derive(Debug, Clone)]
+pub struct Channel<I> {
+    laplacian: Laplacian<I>,
+    values: Vec<f32>,
+    thresholds: Vec<Threshold>,
+}
+impl<I: Copy + Hash + Eq> Channel<I> {
+    /// Create a channel with the same value everywhere.
+    pub fn new(laplacian: Laplacian<I>, value: f32) -> Self {
+        Self {
+            values: vec![value; laplacian.len()],
+            laplacian,
+            thresholds: Vec::new(),
+        }
+    }
+    /// Get the Laplacian values diffuse with.
+    #[inline(always)]
+    pub fn laplacian(&self) -> &Laplacian<I> {
+        &self.laplacian
+    }
+    /// Replace the Laplacian after the mesh changed, keeping the values of elements that are still there.
+    ///
+    /// New elements start with the given value.
+    pub fn rebuild(&mut self, laplacian: Laplacian<I>, value: f32) {
+        self.values = laplacian
+            .ids()
+            .iter()
+            .map(|&id| self.get(id).unwrap_or(value))
+            .collect();
+        self.laplacian = laplacian;
+    }
+    /// Get the value of an element.
+    #[inline(always)]
+    pub fn get(&self, id: I) -> Option<f32> {
+        Some(self.values[self.laplacian.index_of(id)?])
+    }
+    /// Set the value of an element, returning whether it's in the channel.
+    ///
+    /// Sources like lava can be kept at a fixed value by setting it before every step.
+    #[inline(always)]
+    pub fn set(&mut self, id: I, value: f32) -> bool {
+        let Some(i) = self.laplacian.index_of(id) else {
+            return false;
+        };
+        self.values[i] = value;
+        true
+    }
+    /// Get every value, in the order of [`Laplacian::ids`].
+    #[inline(always)]
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+    /// Mutably get every value, in the order of [`Laplacian::ids`].
+    #[inline(always)]
+    pub fn values_mut(&mut self) -> &mut [f32] {
+        &mut self.values
+    }
+    /// Watch for values crossing a threshold, returning its index for telling events apart.
+    pub fn add_threshold(&mut self, threshold: Threshold) -> usize {
+        self.thresholds.push(threshold);
+        self.thresholds.len() - 1
+    }
+    /// Get the thresholds being watched.
+    #[inline(always)]
+    pub fn thresholds(&self) -> &[Threshold] {
+        &self.thresholds
+    }
+
+    /// Diffuse the values for some amount of time, returning every threshold that was crossed.
+    pub fn step(&mut self, dt: f32) -> Vec<ThresholdEvent<I>> {
+        let before = (!self.thresholds.is_empty()).then(|| self.values.clone());
+        self.laplacian.diffuse(&mut self.values, dt);
+        let Some(before) = before else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        for (i, (&old, &new)) in before.iter().zip(&self.values).enumerate() {
+            for (threshold, t) in self.thresholds.iter().enumerate() {
+                let rising = old < t.limit && new >= t.limit;
+                let falling = old >= t.limit && new < t.limit;
+                let fired = match t.crossing {
+                    Crossing::Rising => rising,
+                    Crossing::Falling => falling,
+                    Crossing::Either => rising || falling,
+                };
+                if fired {
+                    events.push(ThresholdEvent {
+                        id: self.laplacian.ids[i],
+                        threshold,
+                        value: new,
+                        rising,
+                    });
+                }
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    fn total(laplacian: &Laplacian<impl Copy + Hash + Eq>, values: &[f32]) -> f32 {
+        laplacian
+            .mass()
+            .iter()
+            .zip(values)
+            .map(|(m, v)| m * v)
+            .sum()
+    }
+
+    /// Diffuse a hot spot, checking that the total stays the same while the values even out.
+    fn check_conservation<I: Copy + Hash + Eq>(laplacian: &Laplacian<I>) {
+        assert!((laplacian.mass().iter().sum::<f32>() - 1.0).abs() < 1e-4);
+        let mut values = (0..laplacian.len())
+            .map(|i| if i == 0 { 100.0 } else { (i % 3) as f32 })
+            .collect::<Vec<_>>();
+        let before = total(laplacian, &values);
+        let spread = |values: &[f32]| {
+            let [min, max] = values
+                .iter()
+                .fold([f32::INFINITY, f32::NEG_INFINITY], |[lo, hi], &v| {
+                    [lo.min(v), hi.max(v)]
+                });
+            max - min
+        };
+        let mut last_spread = spread(&values);
+        let dts = [0.001, 0.01, 0.1].into_iter().chain([0.5; 20]);
+        for dt in dts {
+            laplacian.diffuse(&mut values, dt);
+            let after = total(laplacian, &values);
+            assert!(
+                (after - before).abs() < before * 1e-3,
+                "{before} became {after}"
+            );
+            let now = spread(&values);
+            assert!(now <= last_spread);
+            last_spread = now;
+        }
+        // after long enough, everything is at the average
+        assert!(last_spread < 0.1, "{last_spread}");
+    }
+
+    #[test]
+    fn vertex_diffusion_conserves_total() {
+        let mesh: Mesh = lattice(4);
+        let laplacian = Laplacian::vertices(&mesh);
+        assert_eq!(laplacian.len(), mesh.verts().count());
+        check_conservation(&laplacian);
+    }
+
+    #[test]
+    fn tetra_diffusion_conserves_total() {
+        let mesh: Mesh = lattice(4);
+        let laplacian = Laplacian::tetras(&mesh);
+        assert_eq!(laplacian.len(), mesh.tetras().count());
+        check_conservation(&laplacian);
+    }
+
+    #[test]
+    fn thresholds_fire_once_per_crossing() {
+        let mesh: Mesh = lattice(3);
+        let mut channel = Channel::new(Laplacian::tetras(&mesh), 0.0);
+        let limit = 1.0;
+        let kinds = [Crossing::Rising, Crossing::Falling, Crossing::Either];
+        for crossing in kinds {
+            channel.add_threshold(Threshold { limit, crossing });
+        }
+        let hot = channel.laplacian().ids()[0];
+        let mut counts = [[0; 2]; 3];
+        for step in 0..60 {
+            // heat one tetrahedron for a while, then cool it, so values cross the limit both ways
+            match step {
+                0..20 => channel.set(hot, 20.0),
+                20..40 => channel.set(hot, -20.0),
+                _ => true,
+            };
+            let before = channel.values().to_vec();
+            let events = channel.step(0.01);
+            let after = channel.values();
+
+            let mut expected = Vec::new();
+            for (i, (&old, &new)) in before.iter().zip(after).enumerate() {
+                let rising = old < limit && new >= limit;
+                let falling = old >= limit && new < limit;
+                for (threshold, fired) in
+                    [rising, falling, rising || falling].into_iter().enumerate()
+                {
+                    if fired {
+                        expected.push((channel.laplacian().ids()[i], threshold, new, rising));
+                    }
+                }
+            }
+            let mut found = events
+                .iter()
+                .map(|e| (e.id, e.threshold, e.value, e.rising))
+                .collect::<Vec<_>>();
+            found.sort_by_key(|e| (e.0, e.1));
+            expected.sort_by_key(|e| (e.0, e.1));
+            assert_eq!(found, expected, "step {step}");
+            for e in events {
+                counts[e.threshold][usize::from(e.rising)] += 1;
+            }
+        }
+        // rising only fires going up, falling only going down, and either both ways
+        assert!(counts[0][1] > 0 && counts[0][0] == 0, "{counts:?}");
+        assert!(counts[1][0] > 0 && counts[1][1] == 0, "{counts:?}");
+        assert_eq!(counts[2], [counts[1][0], counts[0][1]]);
+    }
+}
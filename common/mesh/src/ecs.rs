@@ -30,8 +30,12 @@ pub trait TetraMeshDyn {
     );
     /// See [`TetraMesh::append_external_points`].
     fn append_external_points(&self, points: &mut Vec<Vec3>);
+    /// Get the mesh as [`Any`], so it can be downcast to its concrete type.
+    fn as_any(&self) -> &dyn Any;
+    /// Mutably get the mesh as [`Any`], so it can be downcast to its concrete type.
+    fn as_any_mut(&mut self) -> &mut dyn Any;
 }
-impl<T: TetraMesh> TetraMeshDyn for T {
+impl<T: TetraMesh + 'static> TetraMeshDyn for T {
     fn append_all(&self, verts: &mut Vec<Vec3>, faces: &mut Vec<[u32; 3]>) {
         let mut lookup = std::collections::HashMap::new();
         let mut i = verts.len() as u32;
@@ -76,6 +80,12 @@ impl<T: TetraMesh> TetraMeshDyn for T {
     fn append_external_points(&self, verts: &mut Vec<Vec3>) {
         TetraMesh::append_external_points(self, verts);
     }
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
 }
 
 /// An ECS component for a tetrahedral mesh.
@@ -86,6 +96,16 @@ impl Debug for DynMesh {
         f.write_str("DynMesh(..)")
     }
 }
+impl DynMesh {
+    /// Get the mesh as its concrete type, if it has that type.
+    pub fn downcast_ref<M: 'static>(&self) -> Option<&M> {
+        self.0.as_any().downcast_ref()
+    }
+    /// Mutably get the mesh as its concrete type, if it has that type.
+    pub fn downcast_mut<M: 'static>(&mut self) -> Option<&mut M> {
+        self.0.as_any_mut().downcast_mut()
+    }
+}
 impl<M: TetraMeshDyn + Send + Sync + 'static> From<M> for DynMesh {
     fn from(value: M) -> Self {
         Self(Box::new(value))
@@ -0,0 +1,964 @@
+//! Local edits that keep a mesh's connectivity consistent.
+//!
+//! [`TetraMeshMut`] only provides raw insertion and removal, which leaves it up to the caller to keep the face links
+//! in sync. Each of the operations here replaces a small group of tetrahedra with new ones filling exactly the same
+//! space, relinks every face, and checks that none of the new tetrahedra would be inverted before touching the mesh.
+
+use crate::geometry::{is_degenerate, tetra_contains};
+use crate::predicates::orient3d;
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// The tetrahedra affected by an edit.
+///
+/// The removed IDs are no longer in the mesh, and since keys can be reused, some of them may show up again in
+/// `created`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit<K> {
+    pub created: Vec<TetraId<K>>,
+    pub removed: Vec<TetraId<K>>,
+}
+
+/// The result of splitting a simplex.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split<K> {
+    /// The new vertex.
+    pub vertex: VertexId<K>,
+    pub edit: Edit<K>,
+}
+
+/// The tetrahedra that a flip would replace, and the ones it would replace them with.
+pub(crate) struct FlipPlan<K, const N: usize> {
+    pub old: Vec<TetraId<K>>,
+    pub new: [[VertexId<K>; 4]; N],
+}
+
+/// Links from the open faces of a region to the tetrahedra outside of it, keyed by their sorted vertices.
+pub(crate) type OpenFaces<K> = HashMap<[VertexId<K>; 3], (TetraId<K>, VertexIdx)>;
+
+/// An error from an edit. The mesh is left unchanged whenever one of these is returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditError<K> {
+    /// The tetrahedron isn't in the mesh.
+    MissingTetra(TetraId<K>),
+    /// A vertex of the tetrahedron isn't in the mesh.
+    MissingVertex(VertexId<K>),
+    /// The two corners given for an edge are the same.
+    InvalidEdge,
+    /// The face or edge is on the boundary of the mesh, where the operation isn't possible.
+    Boundary,
+    /// A 3-2 flip was attempted on an edge that isn't shared by exactly three tetrahedra.
+    WrongDegree { found: usize },
+    /// Some of the new tetrahedra would be flat or inverted.
+    Inverted,
+    /// Collapsing the edge would change the topology of the mesh, such as by pinching two parts of the surface
+    /// together.
+    LinkCondition,
+}
+impl<K: Debug> Display for EditError<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingTetra(id) => write!(f, "tetrahedron {id:?} isn't in the mesh"),
+            Self::MissingVertex(id) => write!(f, "vertex {id:?} isn't in the mesh"),
+            Self::InvalidEdge => f.write_str("an edge needs two different corners"),
+            Self::Boundary => f.write_str("the operation isn't possible on the boundary"),
+            Self::WrongDegree { found } => {
+                write!(
+                    f,
+                    "a 3-2 flip needs an edge with 3 tetrahedra, found {found}"
+                )
+            }
+            Self::Inverted => f.write_str("the edit would create inverted tetrahedra"),
+            Self::LinkCondition => f.write_str("collapsing the edge would change the topology"),
+        }
+    }
+}
+impl<K: Debug> std::error::Error for EditError<K> {}
+
+/// Get the vertices of a tetrahedron.
+fn corners<M: TetraMesh>(
+    mesh: &M,
+    id: TetraId<M::Key>,
+) -> Result<[VertexId<M::Key>; 4], EditError<M::Key>> {
+    let tet = mesh.get_tetra(id).ok_or(EditError::MissingTetra(id))?;
+    Ok(VertexIdx::VALS.map(|i| tet.vertex(i)))
+}
+
+/// Get the position of a vertex.
+fn position<M: TetraMesh>(mesh: &M, id: VertexId<M::Key>) -> Result<Vec3, EditError<M::Key>> {
+    mesh.get_vertex(id)
+        .map(VertexData::as_vec3)
+        .ok_or(EditError::MissingVertex(id))
+}
+
+/// Check that a set of tetrahedra are all positively oriented and not too flat, with some vertices moved.
+fn check_orientation<M: TetraMesh>(
+    mesh: &M,
+    tetras: &[[VertexId<M::Key>; 4]],
+    moved: &[(VertexId<M::Key>, Vec3)],
+) -> Result<(), EditError<M::Key>> {
+    for verts in tetras {
+        let mut points = [Vec3::ZERO; 4];
+        for (p, &v) in points.iter_mut().zip(verts) {
+            *p = match moved.iter().find(|m| m.0 == v) {
+                Some(&(_, pos)) => pos,
+                None => position(mesh, v)?,
+            };
+        }
+        let [a, b, c, d] = points;
+        if orient3d(a, b, c, d) != Ordering::Greater || is_degenerate(points) {
+            return Err(EditError::Inverted);
+        }
+    }
+    Ok(())
+}
+
+/// Get every tetrahedron containing a vertex, starting from one that contains it.
+///
+/// This only finds tetrahedra that can be reached through faces containing the vertex, so if the mesh is pinched at
+/// the vertex, only one side is found.
+pub fn vertex_star<M: TetraMesh>(
+    mesh: &M,
+    start: TetraId<M::Key>,
+    vertex: VertexId<M::Key>,
+) -> Vec<TetraId<M::Key>> {
+    star(mesh, start, &[vertex])
+}
+
+/// Get every tetrahedron containing an edge, starting from one that contains it.
+pub fn edge_star<M: TetraMesh>(
+    mesh: &M,
+    start: TetraId<M::Key>,
+    edge: [VertexId<M::Key>; 2],
+) -> Vec<TetraId<M::Key>> {
+    star(mesh, start, &edge)
+}
+
+fn star<M: TetraMesh>(
+    mesh: &M,
+    start: TetraId<M::Key>,
+    shared: &[VertexId<M::Key>],
+) -> Vec<TetraId<M::Key>> {
+    let mut seen = HashSet::from([start]);
+    let mut stack = vec![start];
+    let mut out = Vec::new();
+    while let Some(id) = stack.pop() {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        out.push(id);
+        for face in VertexIdx::VALS {
+            // the face contains all of the shared vertices unless it's opposite one of them
+            if shared.contains(&tet.vertex(face)) {
+                continue;
+            }
+            if let Some((n, _)) = tet.face(face)
+                && seen.insert(n)
+            {
+                stack.push(n);
+            }
+        }
+    }
+    out
+}
+
+/// Remove some tetrahedra, returning their values along with the links from their faces to the rest of the mesh.
+pub(crate) fn detach<M: TetraMeshMut>(
+    mesh: &mut M,
+    old: &[TetraId<M::Key>],
+) -> (OpenFaces<M::Key>, Vec<M::Tetra>) {
+    let mut open = HashMap::new();
+    let old_set = old.iter().copied().collect::<HashSet<_>>();
+    for &id in old {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        for face in VertexIdx::VALS {
+            if let Some(adj) = tet.face(face)
+                && !old_set.contains(&adj.0)
+            {
+                open.insert(tet.sorted_face(face), adj);
+            }
+        }
+    }
+    let values = old.iter().filter_map(|&id| mesh.remove_tetra(id)).collect();
+    (open, values)
+}
+
+/// Add new tetrahedra, linking their faces to each other and to the open faces left by [`detach`].
+pub(crate) fn attach<M: TetraMeshMut>(
+    mesh: &mut M,
+    mut open: OpenFaces<M::Key>,
+    new: impl IntoIterator<Item = M::Tetra>,
+) -> Vec<TetraId<M::Key>> {
+    let mut created = Vec::new();
+    for tet in new {
+        let keys = VertexIdx::VALS.map(|i| tet.sorted_face(i));
+        let id = mesh.add_tetra(tet);
+        for (face, key) in VertexIdx::VALS.into_iter().zip(keys) {
+            if let Some((n, i)) = open.remove(&key) {
+                if let Some(t) = mesh.get_tetra_mut(n) {
+                    t.set_face(i, Some((id, face)));
+                }
+                if let Some(t) = mesh.get_tetra_mut(id) {
+                    t.set_face(face, Some((n, i)));
+                }
+            } else {
+                open.insert(key, (id, face));
+            }
+        }
+        created.push(id);
+    }
+    // anything left over from outside is now on the boundary
+    let created_set = created.iter().copied().collect::<HashSet<_>>();
+    for (n, i) in open.into_values() {
+        if !created_set.contains(&n)
+            && let Some(t) = mesh.get_tetra_mut(n)
+        {
+            t.set_face(i, None);
+        }
+    }
+    created
+}
+
+/// Replace some tetrahedra with new ones, linking up all of the faces.
+///
+/// The new tetrahedra should fill exactly the same space as the old ones, so that every face on the outside of the
+/// old tetrahedra is matched by a face of a new one. This doesn't check the geometry at all, so it's up to the caller
+/// to make sure the new tetrahedra are positively oriented.
+///
+/// Each new tetrahedron keeps the data of the old one containing its centroid, or of the first old one if none of them
+/// do. Nothing is added if none of the old tetrahedra are in the mesh, since there's no data to give the new ones.
+pub fn replace_tetras<M: TetraMeshMut>(
+    mesh: &mut M,
+    old: &[TetraId<M::Key>],
+    new: impl IntoIterator<Item = [VertexId<M::Key>; 4]>,
+) -> Edit<M::Key>
+where
+    M::Tetra: Clone,
+{
+    // these line up with the values taken out by `detach`, which skips the same missing tetrahedra
+    let points = old
+        .iter()
+        .filter_map(|&id| mesh.get_tetra(id))
+        .map(|t| mesh.tetra_points(t))
+        .collect::<Vec<_>>();
+    let (open, values) = detach(mesh, old);
+    let new = new
+        .into_iter()
+        .filter_map(|verts| {
+            let centroid = verts
+                .iter()
+                .filter_map(|&v| mesh.get_vertex(v))
+                .map(VertexData::as_vec3)
+                .sum::<Vec3>()
+                / 4.0;
+            let source = points
+                .iter()
+                .position(|p| p.is_some_and(|p| tetra_contains(p, centroid)))
+                .unwrap_or(0);
+            let mut tet = values.get(source)?.clone();
+            for i in VertexIdx::VALS {
+                tet.set_vertex(i, *i.in_arr(&verts));
+                tet.set_face(i, None);
+            }
+            Some(tet)
+        })
+        .collect::<Vec<_>>();
+    let created = attach(mesh, open, new);
+    Edit {
+        created,
+        removed: old.to_vec(),
+    }
+}
+
+/// Split a tetrahedron into four by adding a vertex inside of it.
+pub fn split_tetra<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    vert: M::Vertex,
+) -> Result<Split<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let verts = corners(mesh, tetra)?;
+    split(mesh, &[tetra], &verts, vert)
+}
+
+/// Split a face into three by adding a vertex on it, which also splits the tetrahedra on either side.
+///
+/// The face is the one opposite the given corner.
+pub fn split_face<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    face: VertexIdx,
+    vert: M::Vertex,
+) -> Result<Split<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let verts = corners(mesh, tetra)?;
+    let mut affected = vec![tetra];
+    affected.extend(
+        mesh.get_tetra(tetra)
+            .and_then(|t| t.face(face))
+            .map(|n| n.0),
+    );
+    let shared = face.others().map(|i| *i.in_arr(&verts));
+    split(mesh, &affected, &shared, vert)
+}
+
+/// Split an edge in two by adding a vertex on it, which also splits every tetrahedron around it.
+///
+/// This works for edges on the boundary too.
+pub fn split_edge<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    [i, j]: [VertexIdx; 2],
+    vert: M::Vertex,
+) -> Result<Split<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    if i == j {
+        return Err(EditError::InvalidEdge);
+    }
+    let verts = corners(mesh, tetra)?;
+    let edge = [*i.in_arr(&verts), *j.in_arr(&verts)];
+    let affected = edge_star(mesh, tetra, edge);
+    split(mesh, &affected, &edge, vert)
+}
+
+/// Add a vertex on a simplex, splitting every tetrahedron containing it.
+///
+/// Each affected tetrahedron is replaced by copies of itself with one of the simplex's vertices swapped for the new
+/// one, which keeps the orientation and covers the same space as long as the vertex is inside the simplex.
+fn split<M: TetraMeshMut>(
+    mesh: &mut M,
+    affected: &[TetraId<M::Key>],
+    simplex: &[VertexId<M::Key>],
+    vert: M::Vertex,
+) -> Result<Split<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let pos = vert.as_vec3();
+    let mut new = Vec::with_capacity(affected.len() * simplex.len());
+    for &id in affected {
+        let verts = corners(mesh, id)?;
+        for (k, v) in verts.iter().enumerate() {
+            if simplex.contains(v) {
+                new.push((k, verts));
+            }
+        }
+    }
+    for &(k, verts) in &new {
+        // the new vertex doesn't have an ID yet, so check it in place of the one it replaces
+        check_orientation(mesh, &[verts], &[(verts[k], pos)])?;
+    }
+
+    let id = mesh.add_vertex(vert);
+    let edit = replace_tetras(
+        mesh,
+        affected,
+        new.into_iter().map(|(k, mut verts)| {
+            verts[k] = id;
+            verts
+        }),
+    );
+    Ok(Split { vertex: id, edit })
+}
+
+/// Flip the face between two tetrahedra into an edge between their opposite vertices, making three tetrahedra.
+///
+/// The face is the one opposite the given corner. This is only possible if the new edge passes through the face.
+pub fn flip_2_3<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    face: VertexIdx,
+) -> Result<Edit<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let plan = plan_flip_2_3(mesh, tetra, face)?;
+    Ok(replace_tetras(mesh, &plan.old, plan.new))
+}
+
+/// Get the tetrahedra that [`flip_2_3`] would remove and create, without changing the mesh.
+pub(crate) fn plan_flip_2_3<M: TetraMesh>(
+    mesh: &M,
+    tetra: TetraId<M::Key>,
+    face: VertexIdx,
+) -> Result<FlipPlan<M::Key, 3>, EditError<M::Key>> {
+    let verts = corners(mesh, tetra)?;
+    let Some((other, i)) = mesh.get_tetra(tetra).and_then(|t| t.face(face)) else {
+        return Err(EditError::Boundary);
+    };
+    let p = *face.in_arr(&verts);
+    let q = *i.in_arr(&corners(mesh, other)?);
+    // replacing each corner of the face with the far vertex keeps the orientation of the near tetrahedron
+    let [x, y, z] = face.face_order().map(|i| *i.in_arr(&verts));
+    let new = [[p, x, y, q], [p, y, z, q], [p, z, x, q]];
+    check_orientation(mesh, &new, &[])?;
+    Ok(FlipPlan {
+        old: vec![tetra, other],
+        new,
+    })
+}
+
+/// Flip an edge shared by exactly three tetrahedra into a face, making two tetrahedra.
+///
+/// This is the inverse of [`flip_2_3`], and is only possible if the edge passes through the new face.
+pub fn flip_3_2<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    edge: [VertexIdx; 2],
+) -> Result<Edit<M::Key>, EditError<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let plan = plan_flip_3_2(mesh, tetra, edge)?;
+    Ok(replace_tetras(mesh, &plan.old, plan.new))
+}
+
+/// Get the tetrahedra that [`flip_3_2`] would remove and create, without changing the mesh.
+pub(crate) fn plan_flip_3_2<M: TetraMesh>(
+    mesh: &M,
+    tetra: TetraId<M::Key>,
+    [i, j]: [VertexIdx; 2],
+) -> Result<FlipPlan<M::Key, 2>, EditError<M::Key>> {
+    if i == j {
+        return Err(EditError::InvalidEdge);
+    }
+    let verts = corners(mesh, tetra)?;
+    let [a, b] = [*i.in_arr(&verts), *j.in_arr(&verts)];
+    let ring = edge_star(mesh, tetra, [a, b]);
+    if ring.len() != 3 {
+        return Err(EditError::WrongDegree { found: ring.len() });
+    }
+    let mut link = Vec::with_capacity(3);
+    for &id in &ring {
+        let tet = mesh.get_tetra(id).ok_or(EditError::MissingTetra(id))?;
+        for face in VertexIdx::VALS {
+            let v = tet.vertex(face);
+            if v != a && v != b {
+                if !link.contains(&v) {
+                    link.push(v);
+                }
+                // the faces opposite the link vertices are the ones around the edge
+                if tet.face(face).is_none() {
+                    return Err(EditError::Boundary);
+                }
+            }
+        }
+    }
+    let [x, y, z] = link[..] else {
+        return Err(EditError::WrongDegree { found: ring.len() });
+    };
+    let (y, z) = if orient3d(
+        position(mesh, a)?,
+        position(mesh, x)?,
+        position(mesh, y)?,
+        position(mesh, z)?,
+    ) == Ordering::Greater
+    {
+        (y, z)
+    } else {
+        (z, y)
+    };
+    let new = [[a, x, y, z], [b, x, z, y]];
+    check_orientation(mesh, &new, &[])?;
+    Ok(FlipPlan { old: ring, new })
+}
+
+/// Collapse an edge by merging its second vertex into its first, which is moved to `pos`.
+///
+/// Every tetrahedron around the edge is removed, and the others using the removed vertex are rebuilt with the kept
+/// vertex instead, keeping their data. The removed vertex is removed from the mesh as well.
+///
+/// This fails if the collapse would invert a tetrahedron, or if it would break the topology of the mesh (for example,
+/// collapsing an interior edge whose vertices are both on the boundary would pinch the boundary together).
+pub fn collapse_edge<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    [i, j]: [VertexIdx; 2],
+    pos: Vec3,
+) -> Result<Edit<M::Key>, EditError<M::Key>> {
+    if i == j {
+        return Err(EditError::InvalidEdge);
+    }
+    let verts = corners(mesh, tetra)?;
+    let [keep, remove] = [*i.in_arr(&verts), *j.in_arr(&verts)];
+    let ring = edge_star(mesh, tetra, [keep, remove]);
+    let keep_star = vertex_star(mesh, tetra, keep);
+    let remove_star = vertex_star(mesh, tetra, remove);
+
+    if !link_condition(mesh, &keep_star, &remove_star, &ring, [keep, remove]) {
+        return Err(EditError::LinkCondition);
+    }
+    let survivors = keep_star
+        .iter()
+        .chain(&remove_star)
+        .filter(|id| !ring.contains(id))
+        .map(|&id| corners(mesh, id))
+        .collect::<Result<Vec<_>, _>>()?;
+    check_orientation(mesh, &survivors, &[(keep, pos), (remove, pos)])?;
+
+    let (open, mut values) = detach(mesh, &remove_star);
+    values.retain_mut(|tet| {
+        let Some(corner) = VertexIdx::VALS
+            .into_iter()
+            .find(|&i| tet.vertex(i) == remove)
+        else {
+            return true;
+        };
+        if VertexIdx::VALS.iter().any(|&i| tet.vertex(i) == keep) {
+            return false;
+        }
+        tet.set_vertex(corner, keep);
+        for face in VertexIdx::VALS {
+            tet.set_face(face, None);
+        }
+        true
+    });
+    let created = attach(mesh, open, values);
+    mesh.remove_vertex(remove);
+    if let Some(v) = mesh.get_vertex_mut(keep) {
+        v.set_vec3(pos);
+    }
+    Ok(Edit {
+        created,
+        removed: remove_star,
+    })
+}
+
+/// Try to remove a flat tetrahedron by merging it into the tetrahedra on one side of it.
+///
+/// The faces of a flat tetrahedron come in two groups, one on each side of the plane it lies in. If the tetrahedra
+/// across one group all share the same opposite vertex, they can be replaced by cones from that vertex to the other
+/// group, which fill the same space without the flat tetrahedron. If one group is entirely on the boundary, the flat
+/// tetrahedron is just removed.
+///
+/// Otherwise, if the flat tetrahedron is a quadrilateral whose diagonals cross, a vertex made by `vert` is added where
+/// they cross, splitting every tetrahedron around both diagonals and leaving nothing for the flat one to fill. Faces that
+/// `keep` returns true for are never removed.
+pub(crate) fn remove_flat<M: TetraMeshMut>(
+    mesh: &mut M,
+    tetra: TetraId<M::Key>,
+    keep: impl Fn(&M, [VertexId<M::Key>; 3]) -> bool,
+    vert: impl FnOnce(Vec3) -> M::Vertex,
+) -> Option<Edit<M::Key>>
+where
+    M::Tetra: Clone,
+{
+    let verts = corners(mesh, tetra).ok()?;
+    let points = verts.map(|v| position(mesh, v).ok());
+    if points.contains(&None) {
+        return None;
+    }
+    let points = points.map(|p| p.unwrap_or_default());
+    if !is_degenerate(points) {
+        return None;
+    }
+    let sides = flat_sides(points)?;
+    let tet = mesh.get_tetra(tetra)?;
+    for (side, other) in [(&sides[0], &sides[1]), (&sides[1], &sides[0])] {
+        let across = side
+            .iter()
+            .map(|&i| tet.face(i).filter(|(n, _)| mesh.get_tetra(*n).is_some()))
+            .collect::<Vec<_>>();
+        let mut old = vec![tetra];
+        let mut new = Vec::new();
+        if across.iter().any(Option::is_some) {
+            let Some(across) = across.into_iter().collect::<Option<Vec<_>>>() else {
+                continue;
+            };
+            let apexes = across
+                .iter()
+                .filter_map(|&(n, j)| Some(mesh.get_tetra(n)?.vertex(j)))
+                .collect::<Vec<_>>();
+            let Some(&apex) = apexes.first() else {
+                continue;
+            };
+            if apexes.len() != across.len() || apexes.iter().any(|&p| p != apex) {
+                continue;
+            }
+            old.extend(across.iter().map(|&(n, _)| n));
+            old.sort_unstable();
+            old.dedup();
+            for &i in other {
+                let [a, b, c] = i.face_order().map(|j| *j.in_arr(&verts));
+                let flipped = [apex, a, c, b];
+                let candidate = [apex, a, b, c];
+                if check_orientation(mesh, &[candidate], &[]).is_ok() {
+                    new.push(candidate);
+                } else if check_orientation(mesh, &[flipped], &[]).is_ok() {
+                    new.push(flipped);
+                } else {
+                    break;
+                }
+            }
+            if new.len() != other.len() {
+                continue;
+            }
+        }
+        let kept = new.iter().flat_map(|verts| {
+            VertexIdx::VALS.map(|i| {
+                let mut face = i.others().map(|j| *j.in_arr(verts));
+                face.sort_unstable();
+                face
+            })
+        });
+        if removes_kept(mesh, &old, kept, &keep) {
+            continue;
+        }
+        return Some(replace_tetras(mesh, &old, new));
+    }
+
+    // the diagonals of a quadrilateral are opposite edges, and they're the ones that cross
+    let (edges, pos) = [[0, 1, 2, 3], [0, 2, 1, 3], [0, 3, 1, 2]]
+        .into_iter()
+        .find_map(|[i, j, k, l]| {
+            let pos = crossing([points[i], points[j]], [points[k], points[l]])?;
+            Some(([[verts[i], verts[j]], [verts[k], verts[l]]], pos))
+        })?;
+    let mut old = vec![tetra];
+    let mut new = Vec::new();
+    for edge in edges {
+        for id in edge_star(mesh, tetra, edge) {
+            if id == tetra {
+                continue;
+            }
+            old.push(id);
+            let verts = corners(mesh, id).ok()?;
+            for (k, v) in verts.iter().enumerate() {
+                if edge.contains(v) {
+                    new.push((k, verts));
+                }
+            }
+        }
+    }
+    for &(k, verts) in &new {
+        check_orientation(mesh, &[verts], &[(verts[k], pos)]).ok()?;
+    }
+    // the only faces of the copies without the new vertex are the ones opposite to it
+    let kept = new.iter().map(|&(k, verts)| {
+        let mut face = VertexIdx::VALS[k].others().map(|j| *j.in_arr(&verts));
+        face.sort_unstable();
+        face
+    });
+    if removes_kept(mesh, &old, kept, &keep) {
+        return None;
+    }
+    let id = mesh.add_vertex(vert(pos));
+    Some(replace_tetras(
+        mesh,
+        &old,
+        new.into_iter().map(|(k, mut verts)| {
+            verts[k] = id;
+            verts
+        }),
+    ))
+}
+
+/// Split the faces of a flat tetrahedron into the two sides of the plane it lies in.
+///
+/// Returns `None` if a face is too thin to tell which side it's on.
+pub(crate) fn flat_sides(points: [Vec3; 4]) -> Option<[Vec<VertexIdx>; 2]> {
+    let normals = VertexIdx::VALS.map(|i| {
+        let [a, b, c] = i.face_order().map(|j| *j.in_arr(&points));
+        (b - a).cross(c - a)
+    });
+    let reference = normals
+        .into_iter()
+        .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))?;
+    let mut sides = [Vec::new(), Vec::new()];
+    for (i, normal) in VertexIdx::VALS.into_iter().zip(normals) {
+        match normal.dot(reference) {
+            d if d > 0.0 => sides[0].push(i),
+            d if d < 0.0 => sides[1].push(i),
+            _ => return None,
+        }
+    }
+    Some(sides)
+}
+
+/// Find where two segments cross, if they do somewhere away from their ends.
+fn crossing([a, b]: [Vec3; 2], [c, d]: [Vec3; 2]) -> Option<Vec3> {
+    let [u, v, w] = [b - a, d - c, a - c];
+    let [uu, uv, vv, uw, vw] = [u.dot(u), u.dot(v), v.dot(v), u.dot(w), v.dot(w)];
+    let det = uu * vv - uv * uv;
+    if det <= f32::EPSILON * uu * vv {
+        return None;
+    }
+    let s = (uv * vw - vv * uw) / det;
+    let t = (uu * vw - uv * uw) / det;
+    let range = 1e-3..=1.0 - 1e-3;
+    if !range.contains(&s) || !range.contains(&t) {
+        return None;
+    }
+    let [p, q] = [a + u * s, c + v * t];
+    (p.distance_squared(q) <= 1e-6 * uu.max(vv)).then(|| (p + q) / 2.0)
+}
+
+/// Check if replacing some tetrahedra would remove any faces that have to be kept, given the faces of the new ones.
+fn removes_kept<M: TetraMesh>(
+    mesh: &M,
+    old: &[TetraId<M::Key>],
+    new: impl IntoIterator<Item = [VertexId<M::Key>; 3]>,
+    keep: impl Fn(&M, [VertexId<M::Key>; 3]) -> bool,
+) -> bool {
+    let mut faces = HashMap::new();
+    for &id in old {
+        let Some(t) = mesh.get_tetra(id) else {
+            continue;
+        };
+        for i in VertexIdx::VALS {
+            *faces.entry(t.sorted_face(i)).or_insert(0) += 1;
+        }
+    }
+    for face in new {
+        *faces.entry(face).or_insert(0) -= 1;
+    }
+    faces
+        .into_iter()
+        .any(|(face, count)| count > 0 && keep(mesh, face))
+}
+
+/// Check the link condition for collapsing an edge, which guarantees that the collapse keeps the topology.
+///
+/// Every simplex linked to both endpoints has to also be linked to the edge itself. Boundary faces are coned to a
+/// virtual outside vertex (`None`), which stops interior edges between boundary vertices from being collapsed.
+fn link_condition<M: TetraMesh>(
+    mesh: &M,
+    keep_star: &[TetraId<M::Key>],
+    remove_star: &[TetraId<M::Key>],
+    ring: &[TetraId<M::Key>],
+    edge: [VertexId<M::Key>; 2],
+) -> bool {
+    let link = |tets: &[TetraId<M::Key>], center: &[VertexId<M::Key>]| {
+        let mut out = HashSet::new();
+        for tet in tets.iter().filter_map(|&id| mesh.get_tetra(id)) {
+            let others = VertexIdx::VALS
+                .into_iter()
+                .filter(|&i| !center.contains(&tet.vertex(i)))
+                .collect::<Vec<_>>();
+            insert_subsets(&mut out, others.iter().map(|&i| Some(tet.vertex(i))));
+            for face in others.iter().copied() {
+                if tet.face(face).is_none() {
+                    let cone = others
+                        .iter()
+                        .filter(|&&i| i != face)
+                        .map(|&i| Some(tet.vertex(i)))
+                        .chain([None]);
+                    insert_subsets(&mut out, cone);
+                }
+            }
+        }
+        out
+    };
+    let keep = link(keep_star, &edge[..1]);
+    let remove = link(remove_star, &edge[1..]);
+    let shared = link(ring, &edge);
+    keep.intersection(&remove).all(|s| shared.contains(s))
+}
+
+/// Insert every nonempty subset of a simplex's vertices, sorted so that they can be compared.
+fn insert_subsets<K: Copy + Ord + std::hash::Hash>(
+    out: &mut HashSet<Vec<Option<VertexId<K>>>>,
+    verts: impl IntoIterator<Item = Option<VertexId<K>>>,
+) {
+    let verts = verts.into_iter().collect::<Vec<_>>();
+    for mask in 1..(1u32 << verts.len()) {
+        let mut subset = verts
+            .iter()
+            .enumerate()
+            .filter(|(n, _)| mask & (1 << n) != 0)
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>();
+        subset.sort_unstable();
+        out.insert(subset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{IndexedMesh, MeshBuilder};
+    use crate::geometry::signed_volume;
+    use crate::slab_mesh::SlabMesh;
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    /// Two tetrahedra sharing the face between the origin, X, and Y, with the line between their far corners passing
+    /// through it.
+    fn pair() -> Mesh {
+        IndexedMesh::new(
+            [
+                Vec3::ZERO,
+                Vec3::X,
+                Vec3::Y,
+                Vec3::Z,
+                Vec3::new(0.25, 0.25, -1.0),
+            ],
+            [[0, 1, 2, 3], [0, 2, 1, 4]],
+        )
+        .build()
+    }
+
+    fn volume(mesh: &Mesh) -> f32 {
+        mesh.tetras()
+            .map(|(_, tet)| signed_volume(mesh.tetra_points(tet).unwrap()))
+            .sum()
+    }
+
+    fn sorted_ids(mut ids: Vec<TetraId<u32>>) -> Vec<TetraId<u32>> {
+        ids.sort_unstable();
+        ids
+    }
+
+    /// Check that the mesh is valid and its volume is unchanged, and that the edit removed exactly `removed` and
+    /// created everything but `kept`.
+    fn check(
+        mesh: &Mesh,
+        edit: &Edit<u32>,
+        removed: &[TetraId<u32>],
+        kept: &[TetraId<u32>],
+        volume_before: f32,
+    ) {
+        let report = validate(mesh);
+        assert!(report.is_valid(), "{report}");
+        assert!((volume(mesh) - volume_before).abs() < 1e-6);
+        assert_eq!(
+            sorted_ids(edit.removed.clone()),
+            sorted_ids(removed.to_vec())
+        );
+        assert_eq!(
+            sorted_ids(edit.created.clone()),
+            sorted_ids(
+                mesh.tetras()
+                    .map(|(id, _)| id)
+                    .filter(|id| !kept.contains(id))
+                    .collect()
+            )
+        );
+    }
+
+    /// Find the corners of a tetrahedron that are at the given vertices.
+    fn indices(mesh: &Mesh, id: TetraId<u32>, verts: [VertexId<u32>; 2]) -> [VertexIdx; 2] {
+        let tet = mesh.get_tetra(id).unwrap();
+        verts.map(|v| {
+            VertexIdx::VALS
+                .into_iter()
+                .find(|&i| tet.vertex(i) == v)
+                .unwrap()
+        })
+    }
+
+    fn contains(mesh: &Mesh, id: TetraId<u32>, vertex: VertexId<u32>) -> bool {
+        let tet = mesh.get_tetra(id).unwrap();
+        VertexIdx::VALS.iter().any(|&i| tet.vertex(i) == vertex)
+    }
+
+    #[test]
+    fn split_tetra_into_four() {
+        let mut mesh = pair();
+        let before = volume(&mesh);
+        let split = split_tetra(&mut mesh, TetraId(0), Vec3::splat(0.2).into()).unwrap();
+        assert_eq!(split.edit.created.len(), 4);
+        check(&mesh, &split.edit, &[TetraId(0)], &[TetraId(1)], before);
+        for &id in &split.edit.created {
+            assert!(contains(&mesh, id, split.vertex));
+        }
+        assert_eq!(mesh.tetras().count(), 5);
+    }
+
+    #[test]
+    fn split_face_into_three() {
+        let mut mesh = pair();
+        let before = volume(&mesh);
+        let face = indices(&mesh, TetraId(0), [VertexId(3), VertexId(0)])[0];
+        let split = split_face(
+            &mut mesh,
+            TetraId(0),
+            face,
+            Vec3::new(0.25, 0.25, 0.0).into(),
+        )
+        .unwrap();
+        assert_eq!(split.edit.created.len(), 6);
+        check(&mesh, &split.edit, &[TetraId(0), TetraId(1)], &[], before);
+        for &id in &split.edit.created {
+            assert!(contains(&mesh, id, split.vertex));
+        }
+    }
+
+    #[test]
+    fn split_edge_in_two() {
+        let mut mesh = pair();
+        let before = volume(&mesh);
+        let edge = indices(&mesh, TetraId(0), [VertexId(0), VertexId(1)]);
+        let split =
+            split_edge(&mut mesh, TetraId(0), edge, Vec3::new(0.5, 0.0, 0.0).into()).unwrap();
+        assert_eq!(split.edit.created.len(), 4);
+        check(&mesh, &split.edit, &[TetraId(0), TetraId(1)], &[], before);
+        for &id in &split.edit.created {
+            assert!(contains(&mesh, id, split.vertex));
+        }
+    }
+
+    #[test]
+    fn flip_2_3_and_back() {
+        let mut mesh = pair();
+        let before = volume(&mesh);
+        let face = indices(&mesh, TetraId(0), [VertexId(3), VertexId(0)])[0];
+        let edit = flip_2_3(&mut mesh, TetraId(0), face).unwrap();
+        assert_eq!(edit.created.len(), 3);
+        check(&mesh, &edit, &[TetraId(0), TetraId(1)], &[], before);
+        // every new tetrahedron is around the edge between the far corners
+        for &id in &edit.created {
+            assert!(contains(&mesh, id, VertexId(3)) && contains(&mesh, id, VertexId(4)));
+        }
+
+        let start = edit.created[0];
+        let edge = indices(&mesh, start, [VertexId(3), VertexId(4)]);
+        let back = flip_3_2(&mut mesh, start, edge).unwrap();
+        assert_eq!(back.created.len(), 2);
+        check(&mesh, &back, &edit.created, &[], before);
+        for &id in &back.created {
+            assert!(!(contains(&mesh, id, VertexId(3)) && contains(&mesh, id, VertexId(4))));
+        }
+    }
+
+    #[test]
+    fn collapse_edge_undoes_split() {
+        let mut mesh = pair();
+        let split = split_tetra(&mut mesh, TetraId(0), Vec3::splat(0.2).into()).unwrap();
+        let start = *split
+            .edit
+            .created
+            .iter()
+            .find(|&&id| contains(&mesh, id, VertexId(0)))
+            .unwrap();
+        let edge = indices(&mesh, start, [VertexId(0), split.vertex]);
+        let edit = collapse_edge(&mut mesh, start, edge, Vec3::ZERO).unwrap();
+        // the three tetrahedra around the edge are gone, and the last one is rebuilt on the origin
+        assert_eq!(
+            sorted_ids(edit.removed.clone()),
+            sorted_ids(split.edit.created.clone())
+        );
+        assert_eq!(edit.created.len(), 1);
+        assert!(mesh.get_vertex(split.vertex).is_none());
+        let report = validate(&mesh);
+        assert!(report.is_valid(), "{report}");
+        assert_eq!(mesh.tetras().count(), 2);
+        assert!((volume(&mesh) - 1.0 / 3.0).abs() < 1e-6);
+        let rebuilt = mesh.get_tetra(edit.created[0]).unwrap();
+        let mut corners = VertexIdx::VALS.map(|i| rebuilt.vertex(i));
+        corners.sort_unstable();
+        assert_eq!(corners, [0, 1, 2, 3].map(VertexId));
+    }
+}
@@ -0,0 +1,223 @@
+//! Sampling fields stored on the vertices of a mesh.
+//!
+//! A field is any value stored in [`Vertex::data`] that can be combined linearly, like a temperature or a density. It's
+//! linear inside of each tetrahedron, so sampling it at a point is a weighted sum of the values at the corners of the
+//! tetrahedron containing it, weighted by the barycentric coordinates of the point, and its gradient is constant inside
+//! of each tetrahedron.
+
+use crate::bvh::Bvh;
+use crate::geometry::{barycentric, barycentric_gradients, is_degenerate, tetra_contains};
+use crate::traits::*;
+use bevy_math::{Vec2, Vec3, Vec3A, Vec4};
+
+/// A value that can be combined linearly, so that it can be interpolated between vertices.
+pub trait Linear: Sized {
+    /// Get the sum of some values, each scaled by a weight.
+    ///
+    /// The weights sum to one when interpolating, and to zero when taking gradients.
+    fn weighted_sum(values: [&Self; 4], weights: [f32; 4]) -> Self;
+}
+/// Nothing combines into nothing, so meshes without any data can still be sampled.
+impl Linear for () {
+    #[inline(always)]
+    fn weighted_sum(_: [&Self; 4], _: [f32; 4]) -> Self {}
+}
+impl Linear for f64 {
+    #[inline(always)]
+    fn weighted_sum(values: [&Self; 4], weights: [f32; 4]) -> Self {
+        (0..4).map(|k| values[k] * weights[k] as f64).sum()
+    }
+}
+macro_rules! impl_linear {
+    ($($ty:ty),*) => {
+        $(
+            impl Linear for $ty {
+                #[inline(always)]
+                fn weighted_sum(values: [&Self; 4], weights: [f32; 4]) -> Self {
+                    (0..4).map(|k| *values[k] * weights[k]).sum()
+                }
+            }
+        )*
+    };
+}
+impl_linear!(f32, Vec2, Vec3, Vec3A, Vec4);
+macro_rules! impl_linear_tuple {
+    ($($name:ident: $idx:tt),*) => {
+        /// Each field in a tuple is combined separately, so vertices can store more than one field.
+        impl<$($name: Linear),*> Linear for ($($name,)*) {
+            #[inline(always)]
+            fn weighted_sum(values: [&Self; 4], weights: [f32; 4]) -> Self {
+                ($($name::weighted_sum(values.map(|v| &v.$idx), weights),)*)
+            }
+        }
+    };
+}
+impl_linear_tuple!(A: 0, B: 1);
+impl_linear_tuple!(A: 0, B: 1, C: 2);
+impl_linear_tuple!(A: 0, B: 1, C: 2, D: 3);
+impl<T: Linear, const N: usize> Linear for [T; N] {
+    fn weighted_sum(values: [&Self; 4], weights: [f32; 4]) -> Self {
+        std::array::from_fn(|i| T::weighted_sum(values.map(|v| &v[i]), weights))
+    }
+}
+
+/// A point located in a mesh, from [`locate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location<K> {
+    /// The tetrahedron containing the point.
+    pub tetra: TetraId<K>,
+    /// The barycentric coordinates of the point, for each corner of the tetrahedron in order.
+    pub weights: [f32; 4],
+}
+
+/// Find the tetrahedron containing a point, along with the point's barycentric coordinates in it.
+///
+/// If the point is on a face shared by multiple tetrahedra, any of them can be returned. If a BVH is given, it has to be
+/// up to date with the mesh, and otherwise every tetrahedron is checked.
+pub fn locate<M: TetraMesh>(
+    mesh: &M,
+    bvh: Option<&Bvh<M::Key>>,
+    point: Vec3,
+) -> Option<Location<M::Key>> {
+    let tetra = match bvh {
+        Some(bvh) => bvh.locate(mesh, point)?,
+        None => mesh.tetras().find_map(|(id, tet)| {
+            let points = mesh.tetra_points(tet)?;
+            (!is_degenerate(points) && tetra_contains(points, point)).then_some(id)
+        })?,
+    };
+    let points = mesh.tetra_points(mesh.get_tetra(tetra)?)?;
+    if is_degenerate(points) {
+        return None;
+    }
+    Some(Location {
+        tetra,
+        weights: barycentric(points, point),
+    })
+}
+
+/// Check if a point is inside of a mesh, or on its boundary.
+pub fn contains_point<M: TetraMesh>(mesh: &M, bvh: Option<&Bvh<M::Key>>, point: Vec3) -> bool {
+    locate(mesh, bvh, point).is_some()
+}
+
+/// Interpolate the field at a located point.
+///
+/// Returns `None` if the tetrahedron or any of its vertices are missing.
+pub fn interpolate<M: TetraMesh<Vertex = Vertex<V>>, V: Linear>(
+    mesh: &M,
+    location: &Location<M::Key>,
+) -> Option<V> {
+    let tet = mesh.get_tetra(location.tetra)?;
+    let verts = VertexIdx::VALS.map(|i| mesh.get_vertex(tet.vertex(i)));
+    let [Some(a), Some(b), Some(c), Some(d)] = verts else {
+        return None;
+    };
+    Some(V::weighted_sum(
+        [&a.data, &b.data, &c.data, &d.data],
+        location.weights,
+    ))
+}
+
+/// Sample the field at a point, returning `None` if the point is outside of the mesh.
+///
+/// This is [`locate`] followed by [`interpolate`], so the same rules about the BVH apply.
+pub fn sample<M: TetraMesh<Vertex = Vertex<V>>, V: Linear>(
+    mesh: &M,
+    bvh: Option<&Bvh<M::Key>>,
+    point: Vec3,
+) -> Option<V> {
+    interpolate(mesh, &locate(mesh, bvh, point)?)
+}
+
+/// Get the gradient of the field inside of a tetrahedron, as its derivatives along the X, Y, and Z axes.
+///
+/// For a scalar field, this points the way the field increases fastest, and [`Vec3::from_array`] turns it into a
+/// vector. Returns `None` if the tetrahedron or any of its vertices are missing, or if it's degenerate.
+pub fn gradient<M: TetraMesh<Vertex = Vertex<V>>, V: Linear>(
+    mesh: &M,
+    tetra: TetraId<M::Key>,
+) -> Option<[V; 3]> {
+    let tet = mesh.get_tetra(tetra)?;
+    let points = mesh.tetra_points(tet)?;
+    if is_degenerate(points) {
+        return None;
+    }
+    let verts = VertexIdx::VALS.map(|i| mesh.get_vertex(tet.vertex(i)));
+    let [Some(a), Some(b), Some(c), Some(d)] = verts else {
+        return None;
+    };
+    let values = [&a.data, &b.data, &c.data, &d.data];
+    let grads = barycentric_gradients(points);
+    Some([0, 1, 2].map(|axis| V::weighted_sum(values, grads.map(|g| g[axis]))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+
+    type Mesh = SlabMesh<u32, Vertex<(f32, Vec3)>, Tetra<u32>>;
+
+    const COEFFS: Vec3 = Vec3::new(2.0, -3.0, 0.5);
+
+    /// A scalar and a vector field that are both linear in the position.
+    fn field(pos: Vec3) -> (f32, Vec3) {
+        (COEFFS.dot(pos) + 1.0, pos * 4.0 - Vec3::ONE)
+    }
+
+    fn mesh() -> Mesh {
+        let mut mesh: Mesh = lattice(4);
+        let verts = mesh.verts().map(|(id, _)| id).collect::<Vec<_>>();
+        for v in verts {
+            let vert = mesh.get_vertex_mut(v).unwrap();
+            vert.data = field(vert.pos);
+        }
+        mesh
+    }
+
+    #[test]
+    fn linear_fields_are_exact() {
+        let mesh = mesh();
+        let bvh = Bvh::build(&mesh);
+        for i in 0..125 {
+            let point =
+                Vec3::new((i % 5) as f32, (i / 5 % 5) as f32, (i / 25) as f32) * 0.23 + 0.04;
+            let (scalar, vector) = field(point);
+            for bvh in [None, Some(&bvh)] {
+                let (s, v) = sample(&mesh, bvh, point).unwrap();
+                assert!((s - scalar).abs() < 1e-4, "{point}: {s} != {scalar}");
+                assert!(v.distance(vector) < 1e-4, "{point}: {v} != {vector}");
+            }
+        }
+        for (id, _) in mesh.tetras() {
+            let [dx, dy, dz] = gradient(&mesh, id).unwrap();
+            let scalar = Vec3::new(dx.0, dy.0, dz.0);
+            assert!(scalar.distance(COEFFS) < 1e-3, "{id:?}: {scalar}");
+            for (axis, d) in [dx, dy, dz].into_iter().enumerate() {
+                assert!(d.1.distance(Vec3::AXES[axis] * 4.0) < 1e-3, "{id:?}: {d:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn outside_points_are_not_located() {
+        let mesh = mesh();
+        let bvh = Bvh::build(&mesh);
+        for point in [
+            Vec3::splat(-0.1),
+            Vec3::new(0.5, 0.5, 1.01),
+            Vec3::new(2.0, 0.5, 0.5),
+        ] {
+            for bvh in [None, Some(&bvh)] {
+                assert_eq!(locate(&mesh, bvh, point), None);
+                assert_eq!(sample(&mesh, bvh, point), None);
+                assert!(!contains_point(&mesh, bvh, point));
+            }
+        }
+        let location = locate(&mesh, Some(&bvh), Vec3::splat(0.5)).unwrap();
+        assert!((location.weights.iter().sum::<f32>() - 1.0).abs() < 1e-5);
+        assert!(location.weights.iter().all(|&w| w >= 0.0));
+    }
+}
@@ -0,0 +1,190 @@
+//! Shattering meshes into Voronoi cells.
+//!
+//! Each fragment is everything in the mesh that's closer to its seed point than to any other, which is the mesh
+//! clipped by the planes halfway between its seed and each of the others. The clipping is done with [`slice`], so the
+//! tetrahedra that are cut keep their data, and the vertices added along the cuts copy the data of one end of the edge
+//! they're on.
+
+use crate::components::{IdMap, copy_into};
+use crate::geometry::{bounds_of, is_degenerate};
+use crate::slice::slice;
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::{HashMap, HashSet};
+
+/// Clip a mesh to the side of a plane behind its normal, removing everything in front of it.
+///
+/// Anything that's removed, including the tetrahedra that are cut, is removed from the map too. This returns whether
+/// anything was removed.
+fn clip<M: TetraMeshMut>(mesh: &mut M, map: &mut IdMap<M::Key>, point: Vec3, normal: Vec3) -> bool
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let (mut below, mut above) = (false, false);
+    for (_, vert) in mesh.verts() {
+        let d = (vert.as_vec3() - point).dot(normal);
+        below |= d < 0.0;
+        above |= d > 0.0;
+    }
+    if !above {
+        return false;
+    }
+    let mut removed = HashSet::new();
+    let tetras = if below {
+        let slice = slice(mesh, point, normal);
+        removed.extend(slice.edit.removed);
+        slice.above
+    } else {
+        mesh.tetras().map(|(id, _)| id).collect()
+    };
+    // the two sides don't share any vertices after slicing, so every vertex of the side in front goes too
+    let mut verts = HashSet::new();
+    for id in tetras {
+        if let Some(tet) = mesh.remove_tetra(id) {
+            verts.extend(VertexIdx::VALS.map(|i| tet.vertex(i)));
+            removed.insert(id);
+        }
+    }
+    for &v in &verts {
+        mesh.remove_vertex(v);
+    }
+    // IDs can be reused by anything added later, so they're dropped from the map as soon as they're removed
+    map.tetras.retain(|_, t| !removed.contains(t));
+    map.verts.retain(|_, v| !verts.contains(v));
+    true
+}
+
+/// Remove degenerate tetrahedra on the boundary, which are left behind where several cuts pass close to each other.
+///
+/// They have next to no volume, so removing them doesn't visibly change the shape, and it can uncover more of them.
+fn remove_slivers<M: TetraMeshMut>(mesh: &mut M, map: &mut IdMap<M::Key>) {
+    loop {
+        let slivers = mesh
+            .tetras()
+            .filter(|(_, t)| {
+                VertexIdx::VALS.iter().any(|&i| t.face(i).is_none())
+                    && mesh.tetra_points(t).is_some_and(is_degenerate)
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        if slivers.is_empty() {
+            return;
+        }
+        for id in slivers {
+            mesh.remove_tetra(id);
+        }
+        map.tetras.retain(|_, t| mesh.get_tetra(*t).is_some());
+    }
+}
+
+/// Shatter a mesh into the Voronoi cells around a set of seed points.
+///
+/// There's one fragment for each seed, in the same order, and a fragment is empty if the mesh doesn't reach into its
+/// cell, or if its seed is a repeat of an earlier one. A fragment of a concave mesh can have multiple pieces, which
+/// [`split_components`](crate::components::split_components) can separate. Each fragment comes with the mapping from the
+/// IDs in the original mesh to the ones that survived into it, which leaves out tetrahedra that were cut.
+pub fn fracture<M: TetraMeshMut + Default>(mesh: &M, seeds: &[Vec3]) -> Vec<(M, IdMap<M::Key>)>
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let mut fragments = Vec::with_capacity(seeds.len());
+    for (i, &seed) in seeds.iter().enumerate() {
+        let mut fragment = M::default();
+        if seeds[..i].contains(&seed) {
+            let map = IdMap {
+                verts: HashMap::new(),
+                tetras: HashMap::new(),
+            };
+            fragments.push((fragment, map));
+            continue;
+        }
+        let mut map = copy_into(&mut fragment, mesh);
+        let mut bounds = bounds_of(fragment.verts().map(|(_, v)| v.as_vec3()));
+        // the nearest seeds cut off the most, so going through them first lets the bounds skip more of the rest
+        let mut others = seeds.iter().filter(|&&s| s != seed).collect::<Vec<_>>();
+        others.sort_by(|a, b| {
+            a.distance_squared(seed)
+                .total_cmp(&b.distance_squared(seed))
+        });
+        for &other in others {
+            let (point, normal) = ((seed + other) / 2.0, other - seed);
+            // the corner of the bounds furthest in front of the plane
+            let [min, max] = bounds;
+            let corner = Vec3::select(normal.cmpgt(Vec3::ZERO), max, min);
+            if (corner - point).dot(normal) <= 0.0 {
+                continue;
+            }
+            if clip(&mut fragment, &mut map, point, normal) {
+                bounds = bounds_of(fragment.verts().map(|(_, v)| v.as_vec3()));
+            }
+        }
+        remove_slivers(&mut fragment, &mut map);
+        // vertices that nothing uses anymore, or never did, aren't part of the fragment
+        let used = fragment
+            .tetras()
+            .flat_map(|(_, t)| VertexIdx::VALS.map(|i| t.vertex(i)))
+            .collect::<HashSet<_>>();
+        let unused = fragment
+            .verts()
+            .map(|(id, _)| id)
+            .filter(|id| !used.contains(id))
+            .collect::<Vec<_>>();
+        for v in &unused {
+            fragment.remove_vertex(*v);
+        }
+        map.verts.retain(|_, v| used.contains(v));
+        fragments.push((fragment, map));
+    }
+    fragments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mass::mass_properties;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::{assert_map_resolves, lattice};
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    #[test]
+    fn fragments_fill_the_mesh() {
+        let mesh: Mesh = lattice(4);
+        let seeds = [
+            Vec3::new(0.2, 0.3, 0.25),
+            Vec3::new(0.8, 0.25, 0.3),
+            Vec3::new(0.3, 0.75, 0.7),
+            Vec3::new(0.7, 0.7, 0.8),
+        ];
+        let fragments = fracture(&mesh, &seeds);
+        assert_eq!(fragments.len(), seeds.len());
+        let mut volume = 0.0;
+        for (i, (fragment, map)) in fragments.iter().enumerate() {
+            let report = validate(fragment);
+            assert!(report.is_valid(), "fragment {i}: {report}");
+            assert!(fragment.tetras().next().is_some(), "fragment {i} is empty");
+            assert_map_resolves(&mesh, fragment, map);
+            // everything in the fragment is in its seed's cell
+            for (_, vert) in fragment.verts() {
+                let pos = vert.as_vec3();
+                let dist = pos.distance(seeds[i]);
+                assert!(seeds.iter().all(|s| dist <= pos.distance(*s) + 1e-4));
+            }
+            volume += mass_properties(fragment).volume;
+        }
+        assert!((volume - 1.0).abs() < 1e-3, "fragments add up to {volume}");
+    }
+
+    #[test]
+    fn repeated_seeds_are_empty() {
+        let mesh: Mesh = lattice(3);
+        let seeds = [Vec3::splat(0.25), Vec3::splat(0.25), Vec3::splat(0.75)];
+        let fragments = fracture(&mesh, &seeds);
+        assert!(fragments[0].0.tetras().next().is_some());
+        assert!(fragments[1].0.tetras().next().is_none());
+        assert!(fragments[1].1.verts.is_empty() && fragments[1].1.tetras.is_empty());
+    }
+}
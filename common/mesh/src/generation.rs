@@ -1,68 +1,123 @@
 use std::fmt::{self, Debug, Display, Formatter};
-use std::mem::MaybeUninit;
+use std::mem::ManuallyDrop;
 use std::ops::{Index, IndexMut};
 
+/// The end of a free list.
+const END: u32 = u32::MAX;
+
 /// A type that acts like `Option<T>`, but with a generation count.
+///
+/// Empty slots also store the index of the next empty slot, so that a [`Slab`] can thread a free list through them.
 pub trait OptWithGeneration: Default {
     type Generation: Generation;
     type Value;
 
     fn has_value(&self) -> bool;
     fn has_gen(&self, generation: Self::Generation) -> bool;
-    fn drop_value_unchecked(&mut self) -> Option<Self::Value>;
+    /// Take the value out, leaving a link to the next empty slot behind.
+    fn drop_value_unchecked(&mut self, next_free: u32) -> Option<Self::Value>;
     fn insert_value(&mut self, val: Self::Value) -> Self::Generation;
     fn get_unchecked(&self) -> Option<&Self::Value>;
     fn get_unchecked_mut(&mut self) -> Option<&mut Self::Value>;
     fn get(&self, generation: Self::Generation) -> Option<&Self::Value>;
     fn get_mut(&mut self, generation: Self::Generation) -> Option<&mut Self::Value>;
     fn generation(&self) -> Option<Self::Generation>;
-    fn drop_value_checked(&mut self, generation: Self::Generation) -> Option<Self::Value> {
+    /// Get the index of the next empty slot, if this one is empty.
+    fn next_free(&self) -> Option<u32>;
+    fn drop_value_checked(
+        &mut self,
+        generation: Self::Generation,
+        next_free: u32,
+    ) -> Option<Self::Value> {
         self.has_gen(generation)
-            .then(|| self.drop_value_unchecked())
+            .then(|| self.drop_value_unchecked(next_free))
             .flatten()
     }
+    /// Split the slot into its generation count, and either its value or the index of the next empty slot.
+    fn into_parts(self) -> (Self::Generation, Result<Self::Value, u32>);
+    /// Put a slot back together from [`Self::into_parts`].
+    fn from_parts(generation: Self::Generation, payload: Result<Self::Value, u32>) -> Self;
     fn with_value(val: Self::Value) -> (Self, Self::Generation) {
         let mut this = Self::default();
         let g = this.insert_value(val);
         (this, g)
     }
 }
-impl<T> OptWithGeneration for Option<T> {
+
+/// A slot without a generation count, which is either a value or a link in the free list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Slot<T> {
+    Occupied(T),
+    Vacant(u32),
+}
+impl<T> Default for Slot<T> {
+    fn default() -> Self {
+        Self::Vacant(END)
+    }
+}
+impl<T> OptWithGeneration for Slot<T> {
     type Generation = ();
     type Value = T;
 
     fn has_value(&self) -> bool {
-        self.is_some()
+        matches!(self, Self::Occupied(_))
     }
     fn has_gen(&self, _generation: Self::Generation) -> bool {
-        self.is_some()
-    }
-    fn drop_value_unchecked(&mut self) -> Option<Self::Value> {
-        self.take()
+        self.has_value()
     }
-    fn drop_value_checked(&mut self, _generation: Self::Generation) -> Option<Self::Value> {
-        self.take()
+    fn drop_value_unchecked(&mut self, next_free: u32) -> Option<Self::Value> {
+        match std::mem::replace(self, Self::Vacant(next_free)) {
+            Self::Occupied(val) => Some(val),
+            vacant => {
+                *self = vacant;
+                None
+            }
+        }
     }
     fn insert_value(&mut self, val: Self::Value) {
-        *self = Some(val);
+        *self = Self::Occupied(val);
     }
     fn get_unchecked(&self) -> Option<&Self::Value> {
-        self.as_ref()
+        match self {
+            Self::Occupied(val) => Some(val),
+            Self::Vacant(_) => None,
+        }
     }
     fn get_unchecked_mut(&mut self) -> Option<&mut Self::Value> {
-        self.as_mut()
+        match self {
+            Self::Occupied(val) => Some(val),
+            Self::Vacant(_) => None,
+        }
     }
     fn get(&self, _generation: Self::Generation) -> Option<&Self::Value> {
-        self.as_ref()
+        self.get_unchecked()
     }
     fn get_mut(&mut self, _generation: Self::Generation) -> Option<&mut Self::Value> {
-        self.as_mut()
+        self.get_unchecked_mut()
     }
     fn generation(&self) -> Option<Self::Generation> {
-        self.as_ref().map(drop)
+        self.has_value().then_some(())
+    }
+    fn next_free(&self) -> Option<u32> {
+        match self {
+            Self::Occupied(_) => None,
+            &Self::Vacant(next) => Some(next),
+        }
+    }
+    fn into_parts(self) -> (Self::Generation, Result<Self::Value, u32>) {
+        match self {
+            Self::Occupied(val) => ((), Ok(val)),
+            Self::Vacant(next) => ((), Err(next)),
+        }
+    }
+    fn from_parts(_generation: Self::Generation, payload: Result<Self::Value, u32>) -> Self {
+        match payload {
+            Ok(val) => Self::Occupied(val),
+            Err(next) => Self::Vacant(next),
+        }
     }
     fn with_value(val: Self::Value) -> (Self, Self::Generation) {
-        (Some(val), ())
+        (Self::Occupied(val), ())
     }
 }
 
@@ -123,7 +178,13 @@ where
 }
 impl HasGeneration for BitMarker<0> {
     type Generation = ();
-    type Type<T> = Option<T>;
+    type Type<T> = Slot<T>;
+}
+
+/// The contents of a [`Generational`], which is a value when it's occupied, and the next empty slot when it isn't.
+union Payload<T> {
+    value: ManuallyDrop<T>,
+    next_free: u32,
 }
 
 pub struct Generational<T, const BITS: usize>
@@ -131,7 +192,7 @@ where
     BitMarker<BITS>: HasIntegerSize,
 {
     generation: <BitMarker<BITS> as HasIntegerSize>::Integer,
-    value: MaybeUninit<T>,
+    payload: Payload<T>,
 }
 impl<T, const BITS: usize> Generational<T, BITS>
 where
@@ -140,7 +201,7 @@ where
     pub const fn new() -> Self {
         Self {
             generation: <BitMarker<BITS> as HasIntegerSize>::Integer::ZERO,
-            value: MaybeUninit::uninit(),
+            payload: Payload { next_free: END },
         }
     }
 }
@@ -151,7 +212,7 @@ where
     fn drop(&mut self) {
         if self.has_value() {
             unsafe {
-                self.value.assume_init_drop();
+                ManuallyDrop::drop(&mut self.payload.value);
             }
         }
     }
@@ -172,10 +233,8 @@ where
         let mut s = f.debug_struct("Generational");
         let has_value = self.has_value();
         s.field("has_value", &has_value);
-        if has_value {
-            unsafe {
-                s.field("value", self.value.assume_init_ref());
-            }
+        if let Some(value) = self.get_unchecked() {
+            s.field("value", value);
         }
         s.finish_non_exhaustive()
     }
@@ -187,10 +246,13 @@ where
     fn clone(&self) -> Self {
         Self {
             generation: self.generation,
-            value: if self.has_value() {
-                unsafe { MaybeUninit::new(self.value.assume_init_ref().clone()) }
-            } else {
-                MaybeUninit::uninit()
+            payload: match self.get_unchecked() {
+                Some(value) => Payload {
+                    value: ManuallyDrop::new(value.clone()),
+                },
+                None => Payload {
+                    next_free: unsafe { self.payload.next_free },
+                },
             },
         }
     }
@@ -206,58 +268,93 @@ where
         self.generation.is_odd()
     }
     fn has_gen(&self, generation: Self::Generation) -> bool {
-        self.generation == generation
+        self.has_value() && self.generation == generation
     }
-    fn drop_value_unchecked(&mut self) -> Option<Self::Value> {
+    fn drop_value_unchecked(&mut self, next_free: u32) -> Option<Self::Value> {
         self.has_value().then(|| unsafe {
             self.generation.make_even();
-            self.value.assume_init_read()
-        })
-    }
-    fn drop_value_checked(&mut self, generation: Self::Generation) -> Option<Self::Value> {
-        self.has_gen(generation).then(|| unsafe {
-            self.generation.make_even();
-            self.value.assume_init_read()
+            let val = ManuallyDrop::take(&mut self.payload.value);
+            self.payload.next_free = next_free;
+            val
         })
     }
     fn insert_value(&mut self, val: Self::Value) -> Self::Generation {
-        self.generation
-            .add_2_mask(<BitMarker<BITS> as HasIntegerSize>::MASK);
-        let g = self.generation;
         if self.generation.is_odd() {
             unsafe {
-                self.value.assume_init_drop();
+                ManuallyDrop::drop(&mut self.payload.value);
             }
-            self.value.write(val);
-        } else {
-            self.generation.make_odd();
         }
-        g
+        self.generation
+            .add_2_mask(<BitMarker<BITS> as HasIntegerSize>::MASK);
+        self.generation.make_odd();
+        self.payload = Payload {
+            value: ManuallyDrop::new(val),
+        };
+        self.generation
     }
     fn get_unchecked(&self) -> Option<&Self::Value> {
-        self.has_value()
-            .then(|| unsafe { self.value.assume_init_ref() })
+        self.has_value().then(|| unsafe { &*self.payload.value })
     }
     fn get_unchecked_mut(&mut self) -> Option<&mut Self::Value> {
         self.has_value()
-            .then(|| unsafe { self.value.assume_init_mut() })
+            .then(|| unsafe { &mut *self.payload.value })
     }
     fn get(&self, generation: Self::Generation) -> Option<&Self::Value> {
         self.has_gen(generation)
-            .then(|| unsafe { self.value.assume_init_ref() })
+            .then(|| unsafe { &*self.payload.value })
     }
     fn get_mut(&mut self, generation: Self::Generation) -> Option<&mut Self::Value> {
         self.has_gen(generation)
-            .then(|| unsafe { self.value.assume_init_mut() })
+            .then(|| unsafe { &mut *self.payload.value })
     }
     fn generation(&self) -> Option<Self::Generation> {
         self.generation.is_odd().then_some(self.generation)
     }
+    fn next_free(&self) -> Option<u32> {
+        // the link can only be read from empty slots, since the bytes of a value might not be initialized
+        if self.has_value() {
+            None
+        } else {
+            Some(unsafe { self.payload.next_free })
+        }
+    }
+    fn into_parts(self) -> (Self::Generation, Result<Self::Value, u32>) {
+        let mut this = ManuallyDrop::new(self);
+        let payload = if this.has_value() {
+            Ok(unsafe { ManuallyDrop::take(&mut this.payload.value) })
+        } else {
+            Err(unsafe { this.payload.next_free })
+        };
+        (this.generation, payload)
+    }
+    fn from_parts(mut generation: Self::Generation, payload: Result<Self::Value, u32>) -> Self {
+        // the parity of the generation is what says whether there's a value, so it has to match the payload
+        match payload {
+            Ok(val) => {
+                generation.make_odd();
+                Self {
+                    generation,
+                    payload: Payload {
+                        value: ManuallyDrop::new(val),
+                    },
+                }
+            }
+            Err(next_free) => {
+                generation.make_even();
+                Self {
+                    generation,
+                    payload: Payload { next_free },
+                }
+            }
+        }
+    }
     fn with_value(val: Self::Value) -> (Self, Self::Generation) {
         (
             Self {
                 generation: <BitMarker<BITS> as HasIntegerSize>::Integer::ONE,
-                value: MaybeUninit::new(val),
+                payload: Payload {
+                    value: ManuallyDrop::new(val),
+                },
             },
             <BitMarker<BITS> as HasIntegerSize>::Integer::ONE,
         )
@@ -293,7 +390,7 @@ macro_rules! impl_for {
 }
 impl_for!(u8 u16 u32 u64 usize);
 
-pub trait Generation: Copy {
+pub trait Generation: Copy + Ord {
     fn fmt_gen_idx(&self, index: usize, f: &mut Formatter) -> fmt::Result;
 }
 impl Generation for () {
@@ -318,13 +415,42 @@ impl<M: Generation> Display for GenerationIndex<M> {
     }
 }
 
-#[derive(Default)]
+/// An error from inserting into a [`Slab`] without any indices left, which gives the value back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlabFull<T> {
+    pub value: T,
+    /// The number of indices the slab was limited to.
+    pub capacity: usize,
+}
+impl<T> Display for SlabFull<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "slab is limited to {} values", self.capacity)
+    }
+}
+impl<T: Debug> std::error::Error for SlabFull<T> {}
+
+/// A collection of values with stable indices.
+///
+/// Empty slots form a linked list, so inserting and removing take constant time, and slots are reused most recently
+/// emptied first.
 pub struct Slab<T, const BITS: usize>
 where
     BitMarker<BITS>: HasGeneration,
 {
     elems: Vec<<BitMarker<BITS> as HasGeneration>::Type<T>>,
-    first_free: usize,
+    /// The first empty slot in the free list, or [`END`] if there aren't any.
+    free: u32,
+    len: usize,
+    /// The highest generation of the slots dropped by [`Self::compact`], which new slots start above.
+    floor: Option<<BitMarker<BITS> as HasGeneration>::Generation>,
+}
+impl<T, const BITS: usize> Default for Slab<T, BITS>
+where
+    BitMarker<BITS>: HasGeneration,
+{
+    fn default() -> Self {
+        Self::new()
+    }
 }
 impl<T, const BITS: usize> Slab<T, BITS>
 where
@@ -333,39 +459,73 @@ where
     pub const fn new() -> Self {
         Self {
             elems: Vec::new(),
-            first_free: 0,
+            free: END,
+            len: 0,
+            floor: None,
         }
     }
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
             elems: Vec::with_capacity(capacity),
-            first_free: 0,
+            free: END,
+            len: 0,
+            floor: None,
         }
     }
+    /// Get the number of values in the slab.
+    #[inline(always)]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+    /// Check if there aren't any values in the slab.
+    #[inline(always)]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
     pub fn insert(
         &mut self,
         val: T,
     ) -> GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation> {
-        let index = self.first_free;
-        let g;
-        if self.first_free == self.elems.len() {
-            self.first_free += 1;
-            let v;
-            (v, g) = <BitMarker<BITS> as HasGeneration>::Type::<T>::with_value(val);
-            self.elems.push(v);
+        self.len += 1;
+        if self.free != END {
+            let index = self.free as usize;
+            let slot = &mut self.elems[index];
+            self.free = slot.next_free().unwrap_or(END);
+            GenerationIndex::new(index, slot.insert_value(val))
         } else {
-            let mut slice = &mut self.elems[self.first_free..];
-            let head = slice.split_off_first_mut().unwrap();
-            g = head.insert_value(val);
-            self.first_free += slice
-                .iter()
-                .position(|v| !v.has_value())
-                .unwrap_or(slice.len());
+            let (slot, generation) = match self.floor {
+                Some(floor) => {
+                    let mut slot = OptWithGeneration::from_parts(floor, Err(END));
+                    let generation = OptWithGeneration::insert_value(&mut slot, val);
+                    (slot, generation)
+                }
+                None => <BitMarker<BITS> as HasGeneration>::Type::<T>::with_value(val),
+            };
+            self.elems.push(slot);
+            GenerationIndex::new(self.elems.len() - 1, generation)
         }
-        GenerationIndex {
-            index,
-            generation: g,
+    }
+    /// Insert a value, unless it would get an index of `capacity` or more.
+    ///
+    /// Empty slots are reused before new ones are made, so as long as the capacity stays the same, this only fails once
+    /// every index below it is taken.
+    pub fn try_insert(
+        &mut self,
+        val: T,
+        capacity: usize,
+    ) -> Result<GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation>, SlabFull<T>> {
+        let next = if self.free != END {
+            self.free as usize
+        } else {
+            self.elems.len()
+        };
+        if next >= capacity {
+            return Err(SlabFull {
+                value: val,
+                capacity,
+            });
         }
+        Ok(self.insert(val))
     }
     pub fn remove(
         &mut self,
@@ -374,11 +534,15 @@ where
         let res = self
             .elems
             .get_mut(index.index)?
-            .drop_value_checked(index.generation);
-        if res.is_some() && index.index < self.first_free {
-            self.first_free = index.index;
+            .drop_value_checked(index.generation, self.free)?;
+        self.len -= 1;
+        // slots past what the free list can link to are never reused, which only matters past four billion of them
+        if let Ok(free) = u32::try_from(index.index)
+            && free != END
+        {
+            self.free = free;
         }
-        res
+        Some(res)
     }
     pub fn contains(
         &self,
@@ -408,22 +572,119 @@ where
         Iter {
             iter: self.elems.iter(),
             index: 0,
+            remaining: self.len,
         }
     }
     pub fn iter_mut(&mut self) -> IterMut<'_, <BitMarker<BITS> as HasGeneration>::Type<T>> {
         IterMut {
             iter: self.elems.iter_mut(),
             index: 0,
+            remaining: self.len,
         }
     }
     pub fn values(&self) -> Values<'_, <BitMarker<BITS> as HasGeneration>::Type<T>> {
         Values {
             iter: self.elems.iter(),
+            remaining: self.len,
         }
     }
     pub fn values_mut(&mut self) -> ValuesMut<'_, <BitMarker<BITS> as HasGeneration>::Type<T>> {
         ValuesMut {
             iter: self.elems.iter_mut(),
+            remaining: self.len,
+        }
+    }
+    /// Get the number of slots the slab can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.elems.capacity()
+    }
+    /// Reserve room for at least `additional` more values, counting the empty slots that will be reused.
+    pub fn reserve(&mut self, additional: usize) {
+        let empty = self.elems.len() - self.len;
+        self.elems.reserve(additional.saturating_sub(empty));
+    }
+    /// Free any extra capacity past the last slot.
+    ///
+    /// Empty slots are kept, so [`Self::compact`] should be called first to free those too.
+    pub fn shrink_to_fit(&mut self) {
+        self.elems.shrink_to_fit();
+    }
+    /// Move every value to the front of the slab, so there aren't any empty slots left, and free the rest.
+    ///
+    /// This returns the old and new index of every value that was moved, in no particular order. Moved values get a
+    /// new generation for the slot they're moved into. The slots past the end are dropped, but the slab remembers the
+    /// highest generation they had, and slots added later start above it, so indices of values that were removed
+    /// before compacting don't match new values afterwards, until the generation count wraps around.
+    #[allow(clippy::type_complexity)]
+    pub fn compact(
+        &mut self,
+    ) -> Vec<(
+        GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation>,
+        GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation>,
+    )> {
+        let holes = (0..self.len)
+            .filter(|&i| !self.elems[i].has_value())
+            .collect::<Vec<_>>();
+        let mut moves = Vec::with_capacity(holes.len());
+        let mut from = self.elems.len();
+        for to in holes {
+            // there are as many values past the end as there are holes before it
+            let (generation, value) = loop {
+                from -= 1;
+                let slot = &mut self.elems[from];
+                if let Some(generation) = slot.generation()
+                    && let Some(value) = slot.drop_value_unchecked(END)
+                {
+                    break (generation, value);
+                }
+            };
+            let new = self.elems[to].insert_value(value);
+            moves.push((
+                GenerationIndex::new(from, generation),
+                GenerationIndex::new(to, new),
+            ));
+        }
+        let dropped = self
+            .elems
+            .drain(self.len..)
+            .map(|slot| slot.into_parts().0)
+            .max();
+        self.floor = self.floor.max(dropped);
+        self.free = END;
+        moves
+    }
+    /// Convert every value, keeping their indices and generations, along with the empty slots.
+    pub fn map<U>(self, mut f: impl FnMut(T) -> U) -> Slab<U, BITS> {
+        Slab {
+            elems: self
+                .elems
+                .into_iter()
+                .map(|slot| {
+                    let (generation, payload) = slot.into_parts();
+                    OptWithGeneration::from_parts(generation, payload.map(&mut f))
+                })
+                .collect(),
+            free: self.free,
+            len: self.len,
+            floor: self.floor,
+        }
+    }
+}
+impl<T, const BITS: usize> IntoIterator for Slab<T, BITS>
+where
+    BitMarker<BITS>: HasGeneration,
+{
+    type Item = (
+        GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation>,
+        T,
+    );
+    type IntoIter = IntoIter<<BitMarker<BITS> as HasGeneration>::Type<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            iter: self.elems.into_iter(),
+            index: 0,
+            remaining: self.len,
         }
     }
 }
@@ -442,12 +703,16 @@ where
     fn clone(&self) -> Self {
         Self {
             elems: self.elems.clone(),
-            first_free: self.first_free,
+            free: self.free,
+            len: self.len,
+            floor: self.floor,
         }
     }
     fn clone_from(&mut self, source: &Self) {
         self.elems.clone_from(&source.elems);
-        self.first_free = source.first_free;
+        self.free = source.free;
+        self.len = source.len;
+        self.floor = source.floor;
     }
 }
 impl<T, const BITS: usize> Index<GenerationIndex<<BitMarker<BITS> as HasGeneration>::Generation>>
@@ -479,14 +744,21 @@ where
     }
 }
 
+// the iterators count down the values left, so they stop without scanning the empty slots at the end
+
 #[derive(Debug, Clone)]
 pub struct Iter<'a, G> {
     iter: std::slice::Iter<'a, G>,
     index: usize,
+    remaining: usize,
 }
 impl<'a, G: OptWithGeneration> Iterator for Iter<'a, G> {
     type Item = (GenerationIndex<G::Generation>, &'a G::Value);
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
         self.iter.find_map(|v| {
             let index = self.index;
             self.index += 1;
@@ -498,18 +770,24 @@ impl<'a, G: OptWithGeneration> Iterator for Iter<'a, G> {
         })
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.iter.len()))
+        (self.remaining, Some(self.remaining))
     }
 }
+impl<G: OptWithGeneration> ExactSizeIterator for Iter<'_, G> {}
 
 #[derive(Debug)]
 pub struct IterMut<'a, G> {
     iter: std::slice::IterMut<'a, G>,
     index: usize,
+    remaining: usize,
 }
 impl<'a, G: OptWithGeneration> Iterator for IterMut<'a, G> {
     type Item = (GenerationIndex<G::Generation>, &'a mut G::Value);
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
         self.iter.find_map(|v| {
             let index = self.index;
             self.index += 1;
@@ -521,28 +799,215 @@ impl<'a, G: OptWithGeneration> Iterator for IterMut<'a, G> {
         })
     }
     fn size_hint(&self) -> (usize, Option<usize>) {
-        (0, Some(self.iter.len()))
+        (self.remaining, Some(self.remaining))
     }
 }
+impl<G: OptWithGeneration> ExactSizeIterator for IterMut<'_, G> {}
 
 #[derive(Debug, Clone)]
 pub struct Values<'a, G> {
     iter: std::slice::Iter<'a, G>,
+    remaining: usize,
 }
 impl<'a, G: OptWithGeneration> Iterator for Values<'a, G> {
     type Item = &'a G::Value;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
         self.iter.find_map(G::get_unchecked)
     }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+impl<G: OptWithGeneration> ExactSizeIterator for Values<'_, G> {}
+
+#[derive(Debug)]
+pub struct IntoIter<G> {
+    iter: std::vec::IntoIter<G>,
+    index: usize,
+    remaining: usize,
+}
+impl<G: OptWithGeneration> Iterator for IntoIter<G> {
+    type Item = (GenerationIndex<G::Generation>, G::Value);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        self.iter.find_map(|v| {
+            let index = self.index;
+            self.index += 1;
+            let (generation, value) = v.into_parts();
+            Some((GenerationIndex::new(index, generation), value.ok()?))
+        })
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
 }
+impl<G: OptWithGeneration> ExactSizeIterator for IntoIter<G> {}
 
 #[derive(Debug)]
 pub struct ValuesMut<'a, G> {
     iter: std::slice::IterMut<'a, G>,
+    remaining: usize,
 }
 impl<'a, G: OptWithGeneration> Iterator for ValuesMut<'a, G> {
     type Item = &'a mut G::Value;
     fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
         self.iter.find_map(G::get_unchecked_mut)
     }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+impl<G: OptWithGeneration> ExactSizeIterator for ValuesMut<'_, G> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_keeps_removed_ids_stale() {
+        let mut slab = Slab::<&str, 8>::new();
+        let a = slab.insert("a");
+        let b = slab.insert("b");
+        let c = slab.insert("c");
+        assert_eq!(slab.remove(c), Some("c"));
+        assert!(slab.compact().is_empty());
+        // the new value goes in the same place that was dropped
+        let d = slab.insert("d");
+        assert_eq!(d.index, c.index);
+        assert_ne!(d.generation, c.generation);
+        assert_eq!(slab.get(c), None);
+        assert_eq!(slab.get(d), Some(&"d"));
+
+        assert_eq!(slab.remove(a), Some("a"));
+        assert_eq!(slab.compact(), [(d, GenerationIndex::new(0, 3))]);
+        let e = slab.insert("e");
+        assert_eq!(e.index, 2);
+        for old in [a, c, d] {
+            assert_eq!(slab.get(old), None);
+        }
+        assert_eq!(slab.get(b), Some(&"b"));
+        assert_eq!(slab.get(e), Some(&"e"));
+        assert_eq!(slab.len(), 3);
+    }
+
+    #[test]
+    fn generations_start_above_every_dropped_slot() {
+        let mut slab = Slab::<u32, 8>::new();
+        let ids = (0..4).map(|i| slab.insert(i)).collect::<Vec<_>>();
+        // churn the last slot so that its generation is ahead of the others
+        let mut last = ids[3];
+        for i in 0..5 {
+            slab.remove(last);
+            last = slab.insert(10 + i);
+        }
+        slab.remove(last);
+        slab.remove(ids[2]);
+        slab.compact();
+        let new = [slab.insert(20), slab.insert(21)];
+        assert!(new.iter().all(|id| id.generation > last.generation));
+        assert_eq!(slab.get(last), None);
+        assert_eq!(slab.get(ids[2]), None);
+    }
+
+    #[test]
+    fn try_insert_stops_at_capacity() {
+        let mut slab = Slab::<&str, 0>::new();
+        let a = slab.try_insert("a", 2).unwrap();
+        slab.try_insert("b", 2).unwrap();
+        let err = slab.try_insert("c", 2).unwrap_err();
+        assert_eq!((err.value, err.capacity), ("c", 2));
+        assert_eq!(slab.len(), 2);
+
+        // a freed slot below the capacity can be used again
+        slab.remove(a);
+        assert_eq!(slab.try_insert("c", 2).unwrap().index, a.index);
+        assert!(slab.try_insert("d", 2).is_err());
+        assert!(slab.try_insert("d", 3).is_ok());
+    }
+
+    /// Run random inserts and removes against a `HashMap` of what should be in the slab.
+    fn check_against_model<const BITS: usize>()
+    where
+        BitMarker<BITS>: HasGeneration<Generation: Debug>,
+    {
+        use std::collections::HashMap;
+        use std::rc::Rc;
+
+        let mut seed = 7u32;
+        let mut random = move |n: u32| {
+            seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+            (seed >> 8) % n
+        };
+        // every value holds onto this, so leaked or doubly dropped values show up in its count
+        let tracker = Rc::new(());
+        let mut slab = Slab::<(u32, Rc<()>), BITS>::new();
+        let mut live = HashMap::new();
+        let mut stale = Vec::new();
+        // emptied slots, which should be reused most recently emptied first
+        let mut free = Vec::new();
+        let mut slots = 0;
+        for value in 0..2000 {
+            if live.is_empty() || random(5) < 3 {
+                let id = slab.insert((value, tracker.clone()));
+                let expected = free.pop().unwrap_or_else(|| {
+                    slots += 1;
+                    slots - 1
+                });
+                assert_eq!(id.index, expected);
+                live.insert(id.index, (id, value));
+            } else {
+                let mut indices = live.keys().copied().collect::<Vec<_>>();
+                indices.sort_unstable();
+                let index = indices[random(indices.len() as u32) as usize];
+                let (id, value) = live.remove(&index).unwrap();
+                assert_eq!(slab.remove(id).map(|v| v.0), Some(value));
+                assert_eq!(slab.remove(id).map(|v| v.0), None);
+                free.push(index);
+                stale.push(id);
+            }
+
+            assert_eq!(slab.len(), live.len());
+            assert_eq!(slab.iter().len(), live.len());
+            assert_eq!(slab.iter().count(), live.len());
+            assert_eq!(Rc::strong_count(&tracker), live.len() + 1);
+            for (&index, &(id, value)) in &live {
+                assert_eq!(slab.get(id).map(|v| v.0), Some(value), "{index}");
+            }
+            for (id, (value, _)) in slab.iter() {
+                assert_eq!(live.get(&id.index), Some(&(id, *value)));
+            }
+        }
+        // old IDs only see a new value in the same slot when the generations match, which is always without them
+        for id in stale {
+            let expected = live
+                .get(&id.index)
+                .filter(|(live, _)| live.generation == id.generation)
+                .map(|&(_, value)| value);
+            assert_eq!(slab.get(id).map(|v| v.0), expected);
+        }
+        drop(slab);
+        assert_eq!(Rc::strong_count(&tracker), 1);
+    }
+
+    #[test]
+    fn matches_model_without_generations() {
+        check_against_model::<0>();
+    }
+
+    #[test]
+    fn matches_model_with_generations() {
+        check_against_model::<8>();
+        check_against_model::<20>();
+    }
 }
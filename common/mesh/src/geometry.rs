@@ -0,0 +1,140 @@
+//! Geometric primitives shared by the mesh algorithms.
+
+use crate::predicates::orient3d;
+use crate::traits::VertexIdx;
+use bevy_math::Vec3;
+use std::cmp::Ordering;
+
+/// Get a hashable key for a position, for finding points at exactly the same place.
+///
+/// Positive and negative zero are treated as the same position.
+#[inline(always)]
+pub fn position_key(pos: Vec3) -> [u32; 3] {
+    (pos + Vec3::ZERO).to_array().map(f32::to_bits)
+}
+
+/// Get the signed volume of a tetrahedron.
+///
+/// This is positive when the points are in the order expected by [`VertexIdx::face_order`](crate::traits::VertexIdx::face_order),
+/// zero for a flat tetrahedron, and negative for an inverted one.
+#[inline(always)]
+pub fn signed_volume([a, b, c, d]: [Vec3; 4]) -> f32 {
+    (b - a).dot((c - a).cross(d - a)) / 6.0
+}
+
+/// Get the length of the longest edge of a tetrahedron.
+pub fn max_edge_length([a, b, c, d]: [Vec3; 4]) -> f32 {
+    [a - b, a - c, a - d, b - c, b - d, c - d]
+        .into_iter()
+        .map(Vec3::length_squared)
+        .fold(0.0, f32::max)
+        .sqrt()
+}
+
+/// Check if a tetrahedron is flat enough that its volume can't be trusted.
+///
+/// The threshold scales with the size of the tetrahedron, so this works the same for tiny and huge elements.
+pub fn is_degenerate(points: [Vec3; 4]) -> bool {
+    let scale = max_edge_length(points);
+    signed_volume(points).abs() <= scale * scale * scale * f32::EPSILON
+}
+
+/// Get the bounding box of a set of points.
+pub fn bounds_of(points: impl IntoIterator<Item = Vec3>) -> [Vec3; 2] {
+    points
+        .into_iter()
+        .fold([Vec3::INFINITY, Vec3::NEG_INFINITY], |[min, max], p| {
+            [min.min(p), max.max(p)]
+        })
+}
+
+/// Check if a point is inside of a positively oriented tetrahedron, or on its boundary.
+///
+/// This uses exact predicates, so points on a face shared by two tetrahedra are reported as inside of both.
+pub fn tetra_contains(points: [Vec3; 4], point: Vec3) -> bool {
+    VertexIdx::VALS.into_iter().all(|i| {
+        let mut replaced = points;
+        *i.in_arr_mut(&mut replaced) = point;
+        let [a, b, c, d] = replaced;
+        orient3d(a, b, c, d) != Ordering::Less
+    })
+}
+
+/// Get the closest point on a triangle to another point.
+pub fn closest_point_on_triangle([a, b, c]: [Vec3; 3], p: Vec3) -> Vec3 {
+    // check the Voronoi regions of each vertex and edge, and then the face, from Ericson's Real-Time Collision Detection
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+/// Get the closest point in a positively oriented tetrahedron to another point.
+///
+/// Points inside of the tetrahedron are returned unchanged.
+pub fn closest_point_on_tetra(points: [Vec3; 4], p: Vec3) -> Vec3 {
+    if tetra_contains(points, p) {
+        return p;
+    }
+    VertexIdx::VALS
+        .map(|i| closest_point_on_triangle(i.others().map(|j| *j.in_arr(&points)), p))
+        .into_iter()
+        .min_by(|x, y| x.distance_squared(p).total_cmp(&y.distance_squared(p)))
+        .unwrap_or(p)
+}
+
+/// Get the barycentric coordinates of a point in a tetrahedron, for each corner in order.
+///
+/// The coordinates always sum to one, and they're all between zero and one for points inside. The tetrahedron can't be
+/// degenerate.
+pub fn barycentric(points: [Vec3; 4], p: Vec3) -> [f32; 4] {
+    let volume = signed_volume(points);
+    VertexIdx::VALS.map(|i| {
+        let mut replaced = points;
+        *i.in_arr_mut(&mut replaced) = p;
+        signed_volume(replaced) / volume
+    })
+}
+
+/// Get the gradients of the barycentric coordinates of a tetrahedron, which are the same everywhere in it.
+///
+/// Each one points from the face opposite its corner towards the corner, with a length of one over the height of the
+/// corner above that face. The tetrahedron can't be degenerate.
+pub fn barycentric_gradients(points: [Vec3; 4]) -> [Vec3; 4] {
+    let volume = signed_volume(points);
+    VertexIdx::VALS.map(|i| {
+        let [a, b, c] = i.face_order().map(|j| *j.in_arr(&points));
+        // the outward normal of the face, scaled by twice its area
+        -(b - a).cross(c - a) / (6.0 * volume)
+    })
+}
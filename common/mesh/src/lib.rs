@@ -1,14 +1,39 @@
 pub mod builder;
+pub mod bvh;
+pub mod collision;
+pub mod components;
+pub mod csg;
+pub mod delaunay;
+pub mod diffusion;
 pub mod ecs;
+pub mod edit;
+pub mod field;
+pub mod fracture;
 pub mod generation;
+pub mod geometry;
+pub mod mass;
+pub mod predicates;
+pub mod quality;
+pub mod raycast;
+pub mod region;
+pub mod rekey;
 pub mod slab_mesh;
+pub mod slice;
+pub mod soft_body;
+pub mod surface;
+#[cfg(test)]
+mod test_util;
 pub mod traits;
+pub mod validate;
+pub mod weld;
 
 pub mod prelude {
     pub use crate::ecs::{DynMesh, SurfaceSync, sync_meshes};
     pub use crate::slab_mesh::{DefaultPackedMesh, SlabMesh};
+    pub use crate::soft_body::{SoftBody, step_soft_bodies};
     pub use crate::traits::{
         Tetra, TetraData, TetraDataMut, TetraId, TetraMesh, TetraMeshMut, Vertex, VertexData,
         VertexDataMut, VertexId,
     };
+    pub use crate::validate::{ValidationReport, Violation, validate};
 }
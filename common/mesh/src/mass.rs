@@ -0,0 +1,177 @@
+//! Mass properties of tetrahedral meshes, for turning them into rigid bodies.
+//!
+//! Everything is integrated exactly over each tetrahedron and summed, in `f64` relative to a reference point inside
+//! the mesh, so large meshes far from the origin don't lose precision.
+
+use crate::traits::*;
+use bevy_math::{DMat3, DVec3, Mat3, Vec3};
+
+/// Something with a density, used to weight tetrahedra in [`weighted_mass_properties`].
+///
+/// This is implemented for [`Tetra`] when its data has a density, so meshes of different materials can be weighed by
+/// storing the material in each tetrahedron.
+pub trait Density {
+    /// Get the mass per unit volume.
+    fn density(&self) -> f32;
+}
+/// Without any data, everything has a density of 1.
+impl Density for () {
+    #[inline(always)]
+    fn density(&self) -> f32 {
+        1.0
+    }
+}
+impl Density for f32 {
+    #[inline(always)]
+    fn density(&self) -> f32 {
+        *self
+    }
+}
+impl<K, F, T: Density> Density for Tetra<K, F, T> {
+    #[inline(always)]
+    fn density(&self) -> f32 {
+        self.data.density()
+    }
+}
+
+/// The volume, mass, center of mass, and inertia of a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MassProperties {
+    pub volume: f32,
+    pub mass: f32,
+    pub center_of_mass: Vec3,
+    /// The inertia tensor around the center of mass.
+    pub inertia: Mat3,
+}
+impl MassProperties {
+    /// The mass properties of an empty mesh.
+    pub const ZERO: Self = Self {
+        volume: 0.0,
+        mass: 0.0,
+        center_of_mass: Vec3::ZERO,
+        inertia: Mat3::ZERO,
+    };
+}
+
+/// Get the mass properties of a mesh with a uniform density of 1, so the mass is the same as the volume.
+///
+/// Tetrahedra with missing vertices are skipped.
+pub fn mass_properties<M: TetraMesh>(mesh: &M) -> MassProperties {
+    accumulate(mesh, |_| 1.0)
+}
+
+/// Get the mass properties of a mesh, with each tetrahedron's density given by its data.
+///
+/// Tetrahedra with missing vertices are skipped.
+pub fn weighted_mass_properties<M: TetraMesh>(mesh: &M) -> MassProperties
+where
+    M::Tetra: Density,
+{
+    accumulate(mesh, Density::density)
+}
+
+fn accumulate<M: TetraMesh>(mesh: &M, density: impl Fn(&M::Tetra) -> f32) -> MassProperties {
+    let Some(origin) = mesh.verts().next().map(|(_, v)| v.as_vec3().as_dvec3()) else {
+        return MassProperties::ZERO;
+    };
+    let mut volume = 0.0;
+    let mut mass = 0.0;
+    let mut moment = DVec3::ZERO;
+    // the second moment, the integral of x * x^T over the mesh
+    let mut covariance = DMat3::ZERO;
+    for (_, tet) in mesh.tetras() {
+        let Some(points) = mesh.tetra_points(tet) else {
+            continue;
+        };
+        let [a, b, c, d] = points.map(|p| p.as_dvec3() - origin);
+        let vol = (b - a).dot((c - a).cross(d - a)) / 6.0;
+        let rho = density(tet) as f64;
+        let sum = a + b + c + d;
+        volume += vol;
+        mass += rho * vol;
+        moment += rho * vol * sum / 4.0;
+        let outer = |p: DVec3| DMat3::from_cols(p * p.x, p * p.y, p * p.z);
+        covariance += (outer(a) + outer(b) + outer(c) + outer(d) + outer(sum)) * (rho * vol / 20.0);
+    }
+    if mass == 0.0 {
+        return MassProperties {
+            volume: volume as f32,
+            ..MassProperties::ZERO
+        };
+    }
+    let center = moment / mass;
+    // shift the second moment to the center of mass with the parallel axis theorem
+    let covariance = covariance
+        - DMat3::from_cols(center * center.x, center * center.y, center * center.z) * mass;
+    let trace = covariance.x_axis.x + covariance.y_axis.y + covariance.z_axis.z;
+    let inertia = DMat3::from_diagonal(DVec3::splat(trace)) - covariance;
+    MassProperties {
+        volume: volume as f32,
+        mass: mass as f32,
+        center_of_mass: (center + origin).as_vec3(),
+        inertia: inertia.as_mat3(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Cuboid, MeshBuilder, Octahedron};
+    use crate::slab_mesh::SlabMesh;
+
+    fn assert_close(props: MassProperties, volume: f32, center: Vec3, inertia: f32) {
+        assert!((props.volume - volume).abs() < 1e-5, "{props:?}");
+        assert!(props.center_of_mass.abs_diff_eq(center, 1e-5), "{props:?}");
+        assert!(
+            props
+                .inertia
+                .abs_diff_eq(Mat3::from_diagonal(Vec3::splat(inertia)), 1e-5),
+            "{props:?}"
+        );
+    }
+
+    #[test]
+    fn cube() {
+        let mesh = Cuboid::UNIT_CUBE.build::<SlabMesh<u32, Vertex, Tetra<u32>>>();
+        let props = mass_properties(&mesh);
+        assert_close(props, 1.0, Vec3::splat(0.5), 1.0 / 6.0);
+        assert_eq!(props.mass, props.volume);
+    }
+
+    #[test]
+    fn octahedron() {
+        let mesh = Octahedron::CENTERED.build::<SlabMesh<u32, Vertex, Tetra<u32>>>();
+        assert_close(mass_properties(&mesh), 4.0 / 3.0, Vec3::ZERO, 4.0 / 15.0);
+    }
+
+    #[test]
+    fn weighted() {
+        type Mesh = SlabMesh<u32, Vertex, Tetra<u32, BasicFace<u32>, f32>>;
+        let mut mesh = Cuboid::UNIT_CUBE.build::<Mesh>();
+        Cuboid::UNIT_CUBE.translate(Vec3::X).append_to(&mut mesh);
+        // the cube at the origin has a density of 1, and the one next to it has a density of 3
+        let heavy = mesh
+            .tetras()
+            .map(|(id, tet)| {
+                let points = mesh.tetra_points(tet).unwrap();
+                (id, points.iter().map(|p| p.x).sum::<f32>() > 4.0)
+            })
+            .collect::<Vec<_>>();
+        for (id, heavy) in heavy {
+            mesh.get_tetra_mut(id).unwrap().data = if heavy { 3.0 } else { 1.0 };
+        }
+        let props = weighted_mass_properties(&mesh);
+        assert!((props.volume - 2.0).abs() < 1e-5, "{props:?}");
+        assert!((props.mass - 4.0).abs() < 1e-5, "{props:?}");
+        assert!(
+            props
+                .center_of_mass
+                .abs_diff_eq(Vec3::new(1.25, 0.5, 0.5), 1e-5),
+            "{props:?}"
+        );
+        // each cube has m / 6 around its own center, and the ones across the x axis add m * d^2 for their offset
+        let across = 4.0 / 6.0 + 1.0 * 0.75 * 0.75 + 3.0 * 0.25 * 0.25;
+        let expected = Mat3::from_diagonal(Vec3::new(4.0 / 6.0, across, across));
+        assert!(props.inertia.abs_diff_eq(expected, 1e-5), "{props:?}");
+    }
+}
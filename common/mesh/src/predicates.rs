@@ -0,0 +1,317 @@
+//! Robust geometric predicates.
+//!
+//! These first evaluate the determinant in `f64` and check it against a conservative error bound. If the sign can't be
+//! trusted, it's recomputed exactly using floating-point expansions, in the style of Shewchuk's adaptive predicates.
+//! Since all inputs are `f32`, they're exactly representable as `f64`, so the exact path never loses information.
+
+use bevy_math::{DVec3, Vec3};
+use std::cmp::Ordering;
+
+const EPSILON: f64 = f64::EPSILON / 2.0;
+const ORIENT_BOUND: f64 = 16.0 * EPSILON;
+const INSPHERE_BOUND: f64 = 32.0 * EPSILON;
+
+/// Add two doubles, returning the rounded sum and the rounding error.
+#[inline(always)]
+fn two_sum(a: f64, b: f64) -> (f64, f64) {
+    let s = a + b;
+    let bv = s - a;
+    let av = s - bv;
+    (s, (a - av) + (b - bv))
+}
+
+/// Multiply two doubles, returning the rounded product and the rounding error.
+#[inline(always)]
+fn two_prod(a: f64, b: f64) -> (f64, f64) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+/// An exact sum of doubles.
+///
+/// The components are nonoverlapping and in order of increasing magnitude, with no zeros, so the sign of the whole
+/// expansion is the sign of the last component.
+#[derive(Debug, Clone, Default)]
+struct Expansion(Vec<f64>);
+impl Expansion {
+    fn diff(a: f64, b: f64) -> Self {
+        let (s, e) = two_sum(a, -b);
+        let mut out = Self::default();
+        out.grow(e);
+        out.grow(s);
+        out
+    }
+    /// Add a single double to this expansion.
+    fn grow(&mut self, b: f64) {
+        let mut q = b;
+        let mut out = Vec::with_capacity(self.0.len() + 1);
+        for &e in &self.0 {
+            let (s, err) = two_sum(q, e);
+            if err != 0.0 {
+                out.push(err);
+            }
+            q = s;
+        }
+        if q != 0.0 {
+            out.push(q);
+        }
+        self.0 = out;
+    }
+    fn add(&self, other: &Self) -> Self {
+        let mut out = self.clone();
+        for &c in &other.0 {
+            out.grow(c);
+        }
+        out
+    }
+    fn neg(&self) -> Self {
+        Self(self.0.iter().map(|c| -c).collect())
+    }
+    fn sub(&self, other: &Self) -> Self {
+        self.add(&other.neg())
+    }
+    fn mul(&self, other: &Self) -> Self {
+        let mut out = Self::default();
+        for &a in &self.0 {
+            for &b in &other.0 {
+                let (p, e) = two_prod(a, b);
+                out.grow(e);
+                out.grow(p);
+            }
+        }
+        out
+    }
+    fn sign(&self) -> Ordering {
+        self.0.last().map_or(Ordering::Equal, |c| c.total_cmp(&0.0))
+    }
+}
+
+fn diffs(a: Vec3, b: Vec3) -> [Expansion; 3] {
+    let (a, b) = (a.as_dvec3(), b.as_dvec3());
+    [
+        Expansion::diff(a.x, b.x),
+        Expansion::diff(a.y, b.y),
+        Expansion::diff(a.z, b.z),
+    ]
+}
+
+fn det3_exact([u, v, w]: [&[Expansion; 3]; 3]) -> Expansion {
+    let x = v[1].mul(&w[2]).sub(&v[2].mul(&w[1]));
+    let y = v[0].mul(&w[2]).sub(&v[2].mul(&w[0]));
+    let z = v[0].mul(&w[1]).sub(&v[1].mul(&w[0]));
+    u[0].mul(&x).sub(&u[1].mul(&y)).add(&u[2].mul(&z))
+}
+
+/// Compute a 3x3 determinant along with its permanent, for error bounds.
+#[inline(always)]
+fn det3_perm(u: DVec3, v: DVec3, w: DVec3) -> (f64, f64) {
+    let det = u.dot(v.cross(w));
+    let (u, v, w) = (u.abs(), v.abs(), w.abs());
+    let perm = u.x * (v.y * w.z + v.z * w.y)
+        + u.y * (v.x * w.z + v.z * w.x)
+        + u.z * (v.x * w.y + v.y * w.x);
+    (det, perm)
+}
+
+/// Get the orientation of a tetrahedron.
+///
+/// This is the exact sign of [`signed_volume`](crate::geometry::signed_volume), so it's
+/// [`Greater`](Ordering::Greater) for tetrahedra with the orientation expected by the rest of the crate.
+pub fn orient3d(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Ordering {
+    let da = a.as_dvec3();
+    let (det, perm) = det3_perm(b.as_dvec3() - da, c.as_dvec3() - da, d.as_dvec3() - da);
+    let bound = ORIENT_BOUND * perm;
+    if det > bound {
+        Ordering::Greater
+    } else if -det > bound {
+        Ordering::Less
+    } else {
+        orient3d_exact(a, b, c, d)
+    }
+}
+
+fn orient3d_exact(a: Vec3, b: Vec3, c: Vec3, d: Vec3) -> Ordering {
+    det3_exact([&diffs(b, a), &diffs(c, a), &diffs(d, a)]).sign()
+}
+
+/// Check if `e` is inside the circumsphere of the positively oriented tetrahedron `a`, `b`, `c`, `d`.
+///
+/// This is [`Greater`](Ordering::Greater) if the point is inside the sphere, [`Less`](Ordering::Less) if it's
+/// outside, and [`Equal`](Ordering::Equal) if it's on the sphere. For a negatively oriented tetrahedron, the result
+/// is reversed.
+pub fn insphere(a: Vec3, b: Vec3, c: Vec3, d: Vec3, e: Vec3) -> Ordering {
+    let de = e.as_dvec3();
+    let [ta, tb, tc, td] = [a, b, c, d].map(|p| p.as_dvec3() - de);
+    let lifts = [ta, tb, tc, td].map(|t| t.length_squared());
+    let minors = [
+        det3_perm(tb, tc, td),
+        det3_perm(ta, tc, td),
+        det3_perm(ta, tb, td),
+        det3_perm(ta, tb, tc),
+    ];
+    let mut det = 0.0;
+    let mut perm = 0.0;
+    for (i, (lift, (minor, minor_perm))) in lifts.into_iter().zip(minors).enumerate() {
+        if i % 2 == 0 {
+            det += lift * minor;
+        } else {
+            det -= lift * minor;
+        }
+        perm += lift * minor_perm;
+    }
+    let bound = INSPHERE_BOUND * perm;
+    if det > bound {
+        Ordering::Greater
+    } else if -det > bound {
+        Ordering::Less
+    } else {
+        insphere_exact(a, b, c, d, e)
+    }
+}
+
+fn insphere_exact(a: Vec3, b: Vec3, c: Vec3, d: Vec3, e: Vec3) -> Ordering {
+    let ts = [a, b, c, d].map(|p| diffs(p, e));
+    let lifts = ts
+        .each_ref()
+        .map(|[x, y, z]| x.mul(x).add(&y.mul(y)).add(&z.mul(z)));
+    let [ta, tb, tc, td] = ts.each_ref();
+    let minors = [
+        det3_exact([tb, tc, td]),
+        det3_exact([ta, tc, td]),
+        det3_exact([ta, tb, td]),
+        det3_exact([ta, tb, tc]),
+    ];
+    let mut det = Expansion::default();
+    for (i, (lift, minor)) in lifts.iter().zip(&minors).enumerate() {
+        let term = lift.mul(minor);
+        det = if i % 2 == 0 {
+            det.add(&term)
+        } else {
+            det.sub(&term)
+        };
+    }
+    det.sign()
+}
+
+/// Like [`insphere`], but with a symbolic perturbation so that it's never [`Equal`](Ordering::Equal) for a
+/// non-degenerate tetrahedron.
+///
+/// Each point is identified by a key, and cospherical points are treated as if the points with greater keys were
+/// infinitesimally lifted off of the sphere. The keys must be distinct, and the same point must always use the same
+/// key, which makes this consistent across calls, so Delaunay triangulations of degenerate inputs (such as points on a
+/// grid) stay well-defined.
+pub fn insphere_perturbed<I: Ord>(points: [(Vec3, I); 5]) -> Ordering {
+    let [a, b, c, d, e] = points.each_ref().map(|p| p.0);
+    let res = insphere(a, b, c, d, e);
+    if res != Ordering::Equal {
+        return res;
+    }
+    let mut order = [0, 1, 2, 3, 4];
+    order.sort_unstable_by(|&i, &j| points[j].1.cmp(&points[i].1));
+    for i in order {
+        // the cofactor of the lifted coordinate of each point is the orientation of the other four
+        let o = match i {
+            0 => orient3d(b, c, d, e),
+            1 => orient3d(a, c, d, e).reverse(),
+            2 => orient3d(a, b, d, e),
+            3 => orient3d(a, b, c, e).reverse(),
+            _ => orient3d(a, b, c, d),
+        };
+        if o != Ordering::Equal {
+            return o.reverse();
+        }
+    }
+    Ordering::Equal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The next `f32` below `x`, for positive `x`.
+    fn below(x: f32) -> f32 {
+        f32::from_bits(x.to_bits() - 1)
+    }
+    /// The next `f32` above `x`, for positive `x`.
+    fn above(x: f32) -> f32 {
+        f32::from_bits(x.to_bits() + 1)
+    }
+
+    #[test]
+    fn orient3d_sign() {
+        assert_eq!(
+            orient3d(Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z),
+            Ordering::Greater
+        );
+        assert_eq!(
+            orient3d(Vec3::ZERO, Vec3::Y, Vec3::X, Vec3::Z),
+            Ordering::Less
+        );
+        let far = Vec3::splat(1e6);
+        assert_eq!(
+            orient3d(far, far + Vec3::X, far + Vec3::Y, far + Vec3::Z),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn orient3d_degenerate() {
+        // repeated and collinear points
+        assert_eq!(
+            orient3d(Vec3::ZERO, Vec3::X, Vec3::X, Vec3::Z),
+            Ordering::Equal
+        );
+        assert_eq!(
+            orient3d(Vec3::ZERO, Vec3::X, Vec3::X * 2.0, Vec3::Y),
+            Ordering::Equal
+        );
+        // on the plane x + y + z = 1, and a single step off of it to either side
+        let [a, b, c] = [Vec3::X, Vec3::Y, Vec3::Z];
+        let on = Vec3::new(0.25, 0.25, 0.5);
+        assert_eq!(orient3d(a, b, c, on), Ordering::Equal);
+        assert_eq!(
+            orient3d(a, b, c, Vec3::new(0.25, 0.25, above(0.5))),
+            Ordering::Greater
+        );
+        assert_eq!(
+            orient3d(a, b, c, Vec3::new(0.25, 0.25, below(0.5))),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn insphere_sign() {
+        let [a, b, c, d] = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        assert_eq!(insphere(a, b, c, d, Vec3::splat(0.5)), Ordering::Greater);
+        assert_eq!(insphere(a, b, c, d, Vec3::splat(5.0)), Ordering::Less);
+        // the result flips with the orientation
+        assert_eq!(insphere(a, c, b, d, Vec3::splat(0.5)), Ordering::Less);
+        assert_eq!(insphere(a, c, b, d, Vec3::splat(5.0)), Ordering::Greater);
+    }
+
+    #[test]
+    fn insphere_degenerate() {
+        let [a, b, c, d] = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z];
+        // (1, 1, 1) is on the circumsphere, centered at (0.5, 0.5, 0.5)
+        assert_eq!(insphere(a, b, c, d, Vec3::ONE), Ordering::Equal);
+        assert_eq!(insphere(a, b, c, d, a), Ordering::Equal);
+        assert_eq!(
+            insphere(a, b, c, d, Vec3::new(1.0, 1.0, below(1.0))),
+            Ordering::Greater
+        );
+        assert_eq!(
+            insphere(a, b, c, d, Vec3::new(1.0, 1.0, above(1.0))),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn insphere_perturbed_breaks_ties() {
+        let points = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z, Vec3::ONE];
+        let keyed = |order: [usize; 5]| order.map(|i| (points[i], i));
+        let res = insphere_perturbed(keyed([0, 1, 2, 3, 4]));
+        assert_ne!(res, Ordering::Equal);
+        // swapping two points of the tetrahedron flips its orientation, and so the result
+        assert_eq!(insphere_perturbed(keyed([1, 0, 2, 3, 4])), res.reverse());
+    }
+}
@@ -0,0 +1,440 @@
+//! Element quality metrics, and an optimization pass to improve the worst elements.
+//!
+//! Most of the measures here are normalized so that a regular tetrahedron scores 1. The radius ratio is the main one
+//! used for optimization, since it goes to zero for every kind of badly shaped element, including slivers that have
+//! well-proportioned edges but almost no volume.
+
+use crate::edit::{self, vertex_star};
+use crate::geometry::{max_edge_length, signed_volume};
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::HashSet;
+use std::f32::consts::PI;
+use std::fmt::{self, Debug, Display, Formatter};
+
+/// The number of bins in [`QualityReport::histogram`].
+pub const HISTOGRAM_BINS: usize = 10;
+
+/// Quality measures for a single tetrahedron.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quality {
+    /// The signed volume, as given by [`signed_volume`].
+    pub volume: f32,
+    /// Three times the inradius divided by the circumradius.
+    ///
+    /// This is 1 for a regular tetrahedron, goes to 0 as it gets flatter, and is negative for inverted tetrahedra.
+    pub radius_ratio: f32,
+    /// The smallest angle between two faces, in radians.
+    pub min_dihedral: f32,
+    /// The largest angle between two faces, in radians.
+    pub max_dihedral: f32,
+    /// The longest edge divided by the inradius, scaled to be 1 for a regular tetrahedron.
+    ///
+    /// Unlike the other measures, this gets larger for worse tetrahedra, and is infinite for flat ones.
+    pub aspect_ratio: f32,
+}
+impl Quality {
+    /// Measure a tetrahedron from its corners.
+    pub fn new(points: [Vec3; 4]) -> Self {
+        let [a, b, c, d] = points;
+        let volume = signed_volume(points);
+        // outward face normals, with lengths of twice the face areas
+        let normals = VertexIdx::VALS.map(|i| {
+            let [p, q, r] = i.face_order().map(|j| *j.in_arr(&points));
+            (q - p).cross(r - p)
+        });
+        let area = normals.iter().map(|n| n.length()).sum::<f32>() / 2.0;
+        let inradius = 3.0 * volume / area;
+
+        let (u, v, w) = (b - a, c - a, d - a);
+        let offset = (u.length_squared() * v.cross(w)
+            + v.length_squared() * w.cross(u)
+            + w.length_squared() * u.cross(v))
+            / (12.0 * volume);
+        let circumradius = offset.length();
+
+        let mut min_dihedral = PI;
+        let mut max_dihedral = 0.0f32;
+        for i in 0..4 {
+            for j in (i + 1)..4 {
+                // the edge shared by two faces is opposite both of their vertices
+                let angle = PI - normals[i].angle_between(normals[j]);
+                min_dihedral = min_dihedral.min(angle);
+                max_dihedral = max_dihedral.max(angle);
+            }
+        }
+
+        let radius_ratio = 3.0 * inradius / circumradius;
+        Self {
+            volume,
+            radius_ratio: if radius_ratio.is_finite() {
+                radius_ratio
+            } else {
+                0.0
+            },
+            min_dihedral,
+            max_dihedral,
+            aspect_ratio: max_edge_length(points) / (2.0 * 6.0f32.sqrt() * inradius.abs()),
+        }
+    }
+}
+
+/// Measure a tetrahedron in a mesh.
+///
+/// This returns `None` if any of its vertices are missing.
+pub fn tetra_quality<M: TetraMesh>(mesh: &M, tetra: &M::Tetra) -> Option<Quality> {
+    mesh.tetra_points(tetra).map(Quality::new)
+}
+
+/// A summary of the quality of every tetrahedron in a mesh, from [`quality_report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct QualityReport<K> {
+    /// The number of tetrahedra measured.
+    pub count: usize,
+    /// The number of tetrahedra with a non-positive volume.
+    pub inverted: usize,
+    /// The number of tetrahedra in each range of radius ratios, evenly split between 0 and 1.
+    ///
+    /// Inverted tetrahedra are counted in the first bin.
+    pub histogram: [usize; HISTOGRAM_BINS],
+    /// The tetrahedron with the lowest radius ratio.
+    pub worst: Option<(TetraId<K>, Quality)>,
+    /// The average radius ratio, or 0 if there aren't any tetrahedra.
+    pub mean_radius_ratio: f32,
+    /// The smallest dihedral angle in the mesh, in radians.
+    pub min_dihedral: f32,
+    /// The largest dihedral angle in the mesh, in radians.
+    pub max_dihedral: f32,
+}
+impl<K: Debug> Display for QualityReport<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} tetrahedra, {} inverted, mean radius ratio {:.3}",
+            self.count, self.inverted, self.mean_radius_ratio
+        )?;
+        if let Some((id, q)) = &self.worst {
+            writeln!(f, "worst: {id:?} with radius ratio {:.3}", q.radius_ratio)?;
+        }
+        write!(
+            f,
+            "dihedral angles: {:.1}° to {:.1}°",
+            self.min_dihedral.to_degrees(),
+            self.max_dihedral.to_degrees()
+        )?;
+        let widest = self.histogram.iter().copied().max().unwrap_or(0).max(1);
+        for (i, &n) in self.histogram.iter().enumerate() {
+            let lo = i as f32 / HISTOGRAM_BINS as f32;
+            let hi = (i + 1) as f32 / HISTOGRAM_BINS as f32;
+            let bar = "#".repeat(n * 40 / widest);
+            write!(f, "\n{lo:.1}-{hi:.1} {n:>8} {bar}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Measure every tetrahedron in a mesh, and summarize the results.
+///
+/// Tetrahedra with missing vertices are skipped.
+pub fn quality_report<M: TetraMesh>(mesh: &M) -> QualityReport<M::Key> {
+    let mut report = QualityReport {
+        count: 0,
+        inverted: 0,
+        histogram: [0; HISTOGRAM_BINS],
+        worst: None,
+        mean_radius_ratio: 0.0,
+        min_dihedral: PI,
+        max_dihedral: 0.0,
+    };
+    let mut total = 0.0;
+    for (id, tet) in mesh.tetras() {
+        let Some(q) = tetra_quality(mesh, tet) else {
+            continue;
+        };
+        report.count += 1;
+        if q.volume <= 0.0 {
+            report.inverted += 1;
+        }
+        let bin = (q.radius_ratio * HISTOGRAM_BINS as f32) as usize;
+        report.histogram[bin.min(HISTOGRAM_BINS - 1)] += 1;
+        if report
+            .worst
+            .is_none_or(|(_, w)| q.radius_ratio < w.radius_ratio)
+        {
+            report.worst = Some((id, q));
+        }
+        total += q.radius_ratio;
+        report.min_dihedral = report.min_dihedral.min(q.min_dihedral);
+        report.max_dihedral = report.max_dihedral.max(q.max_dihedral);
+    }
+    if report.count > 0 {
+        report.mean_radius_ratio = total / report.count as f32;
+    }
+    report
+}
+
+/// Settings for [`optimize`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptimizeOptions {
+    /// The number of passes over the mesh.
+    pub iterations: usize,
+    /// Tetrahedra with a radius ratio below this are improved.
+    pub threshold: f32,
+    /// Whether to try flipping faces and edges.
+    pub flips: bool,
+    /// Whether to try moving interior vertices.
+    pub smoothing: bool,
+}
+impl Default for OptimizeOptions {
+    fn default() -> Self {
+        Self {
+            iterations: 4,
+            threshold: 0.3,
+            flips: true,
+            smoothing: true,
+        }
+    }
+}
+
+/// What [`optimize`] did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OptimizeStats {
+    /// The number of flips done.
+    pub flips: usize,
+    /// The number of vertices moved.
+    pub moves: usize,
+    /// The lowest radius ratio before optimizing.
+    pub worst_before: f32,
+    /// The lowest radius ratio after optimizing.
+    pub worst_after: f32,
+}
+
+/// Improve the worst tetrahedra in a mesh.
+///
+/// Every tetrahedron with a radius ratio below the threshold gets a chance to be improved, first by flipping the faces
+/// and edges around it, and then by moving its vertices towards the middle of their neighbors. A change is only kept if
+/// it raises the lowest radius ratio of the tetrahedra it affects, so the worst element in the mesh never gets worse.
+///
+/// The boundary is never changed: only interior faces and edges are flipped, and vertices on the boundary are never
+/// moved. Flipped tetrahedra are replaced with new ones, which take their data from the ones they replace.
+pub fn optimize<M: TetraMeshMut>(mesh: &mut M, options: OptimizeOptions) -> OptimizeStats
+where
+    M::Tetra: Clone,
+{
+    let worst = |mesh: &M| {
+        quality_report(mesh)
+            .worst
+            .map_or(1.0, |(_, q)| q.radius_ratio)
+    };
+    let mut stats = OptimizeStats {
+        flips: 0,
+        moves: 0,
+        worst_before: worst(mesh),
+        worst_after: 0.0,
+    };
+    for _ in 0..options.iterations {
+        let mut bad = mesh
+            .tetras()
+            .filter_map(|(id, t)| Some((id, tetra_quality(mesh, t)?.radius_ratio)))
+            .filter(|&(_, q)| q < options.threshold)
+            .collect::<Vec<_>>();
+        if bad.is_empty() {
+            break;
+        }
+        bad.sort_unstable_by(|a, b| a.1.total_cmp(&b.1));
+
+        let mut changed = false;
+        if options.flips {
+            for &(id, _) in &bad {
+                if try_flips(mesh, id) {
+                    stats.flips += 1;
+                    changed = true;
+                }
+            }
+        }
+        if options.smoothing {
+            let boundary = boundary_vertices(mesh);
+            let mut visited = HashSet::new();
+            for &(id, _) in &bad {
+                let Some(tet) = mesh.get_tetra(id) else {
+                    continue;
+                };
+                for v in VertexIdx::VALS.map(|i| tet.vertex(i)) {
+                    if !boundary.contains(&v) && visited.insert(v) && smooth_vertex(mesh, id, v) {
+                        stats.moves += 1;
+                        changed = true;
+                    }
+                }
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    stats.worst_after = worst(mesh);
+    stats
+}
+
+/// Get the lowest radius ratio of a group of tetrahedra.
+fn min_quality<M: TetraMesh>(mesh: &M, tetras: &[TetraId<M::Key>]) -> f32 {
+    tetras
+        .iter()
+        .filter_map(|&id| tetra_quality(mesh, mesh.get_tetra(id)?))
+        .map(|q| q.radius_ratio)
+        .fold(1.0, f32::min)
+}
+
+/// Measure a tetrahedron that isn't in the mesh yet.
+fn planned_quality<M: TetraMesh>(mesh: &M, verts: &[VertexId<M::Key>; 4]) -> f32 {
+    let [a, b, c, d] = verts.map(|v| mesh.get_vertex(v).map(VertexData::as_vec3));
+    match (a, b, c, d) {
+        (Some(a), Some(b), Some(c), Some(d)) => Quality::new([a, b, c, d]).radius_ratio,
+        _ => 0.0,
+    }
+}
+
+/// Try every flip that removes a tetrahedron, doing the first one that improves the quality around it.
+fn try_flips<M: TetraMeshMut>(mesh: &mut M, id: TetraId<M::Key>) -> bool
+where
+    M::Tetra: Clone,
+{
+    let improves = |mesh: &M, old: &[TetraId<M::Key>], new: &[[VertexId<M::Key>; 4]]| {
+        let after = new
+            .iter()
+            .map(|v| planned_quality(mesh, v))
+            .fold(1.0, f32::min);
+        after > min_quality(mesh, old)
+    };
+    for face in VertexIdx::VALS {
+        if let Ok(plan) = edit::plan_flip_2_3(mesh, id, face)
+            && improves(mesh, &plan.old, &plan.new)
+        {
+            edit::replace_tetras(mesh, &plan.old, plan.new);
+            return true;
+        }
+    }
+    for edge in [[0, 1], [0, 2], [0, 3], [1, 2], [1, 3], [2, 3]] {
+        if let Ok(plan) = edit::plan_flip_3_2(mesh, id, edge.map(|i| VertexIdx::VALS[i]))
+            && improves(mesh, &plan.old, &plan.new)
+        {
+            edit::replace_tetras(mesh, &plan.old, plan.new);
+            return true;
+        }
+    }
+    false
+}
+
+/// Get every vertex on a boundary face.
+fn boundary_vertices<M: TetraMesh>(mesh: &M) -> HashSet<VertexId<M::Key>> {
+    let mut out = HashSet::new();
+    for (_, tet) in mesh.tetras() {
+        for face in VertexIdx::VALS {
+            if tet.face(face).is_none() {
+                out.extend(face.others().map(|i| tet.vertex(i)));
+            }
+        }
+    }
+    out
+}
+
+/// Try moving a vertex towards the average of its neighbors, keeping the move if it improves the tetrahedra around it.
+fn smooth_vertex<M: TetraMeshMut>(
+    mesh: &mut M,
+    start: TetraId<M::Key>,
+    vert: VertexId<M::Key>,
+) -> bool {
+    let star = vertex_star(mesh, start, vert);
+    let Some(old) = mesh.get_vertex(vert).map(VertexData::as_vec3) else {
+        return false;
+    };
+    let mut neighbors = HashSet::new();
+    for tet in star.iter().filter_map(|&id| mesh.get_tetra(id)) {
+        neighbors.extend(VertexIdx::VALS.map(|i| tet.vertex(i)));
+    }
+    neighbors.remove(&vert);
+    let sum = neighbors
+        .iter()
+        .filter_map(|&v| mesh.get_vertex(v))
+        .map(VertexData::as_vec3)
+        .sum::<Vec3>();
+    let target = sum / neighbors.len() as f32;
+
+    let before = min_quality(mesh, &star);
+    // back off towards the old position if the full move makes things worse
+    for t in [1.0, 0.5, 0.25] {
+        let pos = old.lerp(target, t);
+        if let Some(v) = mesh.get_vertex_mut(vert) {
+            v.set_vec3(pos);
+        }
+        if min_quality(mesh, &star) > before {
+            return true;
+        }
+    }
+    if let Some(v) = mesh.get_vertex_mut(vert) {
+        v.set_vec3(old);
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mass::mass_properties;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    #[test]
+    fn regular_tetra_scores_one() {
+        let points = [
+            Vec3::new(1.0, -1.0, -1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(-1.0, 1.0, -1.0),
+            Vec3::new(-1.0, -1.0, 1.0),
+        ];
+        let q = Quality::new(points);
+        assert!(q.volume > 0.0);
+        assert!((q.radius_ratio - 1.0).abs() < 1e-5, "{q:?}");
+        assert!((q.aspect_ratio - 1.0).abs() < 1e-5, "{q:?}");
+        // every dihedral angle of a regular tetrahedron is acos(1/3)
+        let dihedral = (1.0f32 / 3.0).acos();
+        assert!((dihedral.to_degrees() - 70.53).abs() < 0.01);
+        assert!((q.min_dihedral - dihedral).abs() < 1e-4, "{q:?}");
+        assert!((q.max_dihedral - dihedral).abs() < 1e-4, "{q:?}");
+
+        // swapping two corners turns it inside out
+        let q = Quality::new([points[1], points[0], points[2], points[3]]);
+        assert!(q.volume < 0.0 && (q.radius_ratio + 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn flat_tetra_scores_zero() {
+        let q = Quality::new([Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::new(1.0, 1.0, 0.0)]);
+        assert_eq!(q.radius_ratio, 0.0);
+        assert!(q.aspect_ratio.is_infinite());
+    }
+
+    #[test]
+    fn optimize_only_improves() {
+        let mut mesh: Mesh = lattice(6);
+        let volume = mass_properties(&mesh).volume;
+        let boundary = boundary_vertices(&mesh)
+            .into_iter()
+            .map(|v| (v, mesh.get_vertex(v).unwrap().pos))
+            .collect::<Vec<_>>();
+        let stats = optimize(&mut mesh, OptimizeOptions::default());
+        assert!(stats.flips + stats.moves > 0, "{stats:?}");
+        assert!(stats.worst_after >= stats.worst_before, "{stats:?}");
+
+        let report = validate(&mesh);
+        assert!(report.is_valid(), "{report}");
+        assert!((mass_properties(&mesh).volume - volume).abs() < 1e-5);
+        for (v, pos) in boundary {
+            assert_eq!(mesh.get_vertex(v).map(|v| v.pos), Some(pos));
+        }
+        let worst = quality_report(&mesh).worst.unwrap().1.radius_ratio;
+        assert_eq!(worst, stats.worst_after);
+    }
+}
@@ -0,0 +1,362 @@
+//! Raycasting against tetrahedral meshes.
+//!
+//! [`raycast`] finds the first boundary face a ray hits, which is what a cursor pointing at a mesh is pointing at.
+//! [`walk_ray`] follows a ray through the inside of a mesh, stepping from each tetrahedron to its neighbor through the
+//! face the ray leaves by, and reports every tetrahedron along the way.
+//!
+//! Both can use a [`Bvh`] to skip most of the mesh, and otherwise fall back to checking every tetrahedron.
+
+use crate::bvh::Bvh;
+use crate::geometry::tetra_contains;
+use crate::traits::*;
+use bevy_math::{Ray3d, Vec3};
+use std::collections::HashSet;
+
+/// A ray hitting a boundary face of a mesh, from [`raycast`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit<K> {
+    /// The tetrahedron that was hit.
+    pub tetra: TetraId<K>,
+    /// The face that was hit, as the index of the vertex opposite to it.
+    pub face: VertexIdx,
+    /// The distance along the ray to the hit.
+    pub distance: f32,
+    /// The point that was hit.
+    pub point: Vec3,
+    /// The barycentric coordinates of the hit on the face, for the vertices in [`VertexIdx::face_order`].
+    pub barycentric: Vec3,
+    /// The outward normal of the face.
+    ///
+    /// This points towards the ray when it hits from outside, and away from it when it starts inside of the mesh.
+    pub normal: Vec3,
+}
+
+/// A tetrahedron that a ray passes through, from [`walk_ray`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RaySegment<K> {
+    /// The tetrahedron the ray passes through.
+    pub tetra: TetraId<K>,
+    /// The distance along the ray where it enters the tetrahedron, or zero if it starts inside.
+    pub enter: f32,
+    /// The distance along the ray where it leaves the tetrahedron, capped at the maximum distance.
+    pub exit: f32,
+    /// The face the ray leaves through, or `None` if it stops inside.
+    pub exit_face: Option<VertexIdx>,
+}
+
+/// Intersect a ray with a triangle from either side, returning the distance and barycentric coordinates.
+#[inline]
+pub(crate) fn ray_triangle(ray: Ray3d, [a, b, c]: [Vec3; 3]) -> Option<(f32, Vec3)> {
+    // Möller-Trumbore
+    let ab = b - a;
+    let ac = c - a;
+    let p = ray.direction.cross(ac);
+    let det = ab.dot(p);
+    if det.abs() <= f32::EPSILON * ab.length_squared().max(ac.length_squared()) {
+        return None;
+    }
+    let inv = det.recip();
+    let s = ray.origin - a;
+    let u = s.dot(p) * inv;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(ab);
+    let v = ray.direction.dot(q) * inv;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = ac.dot(q) * inv;
+    (t >= 0.0).then_some((t, Vec3::new(1.0 - u - v, u, v)))
+}
+
+/// Check if a face of a tetrahedron is on the boundary of a mesh.
+#[inline]
+pub(crate) fn is_boundary<M: TetraMesh>(mesh: &M, tet: &M::Tetra, face: VertexIdx) -> bool {
+    tet.face(face)
+        .is_none_or(|(n, _)| mesh.get_tetra(n).is_none())
+}
+
+/// Find the closest boundary face of a tetrahedron hit by a ray, between `min` and `max`.
+fn hit_tetra<M: TetraMesh>(
+    mesh: &M,
+    id: TetraId<M::Key>,
+    ray: Ray3d,
+    [min, max]: [f32; 2],
+    entering: bool,
+) -> Option<RayHit<M::Key>> {
+    let tet = mesh.get_tetra(id)?;
+    let points = mesh.tetra_points(tet)?;
+    let mut best: Option<RayHit<M::Key>> = None;
+    for face in VertexIdx::VALS {
+        if !is_boundary(mesh, tet, face) {
+            continue;
+        }
+        let tri = face.face_order().map(|i| *i.in_arr(&points));
+        let normal = (tri[1] - tri[0]).cross(tri[2] - tri[0]).normalize_or_zero();
+        if entering && normal.dot(*ray.direction) >= 0.0 {
+            continue;
+        }
+        let Some((distance, barycentric)) = ray_triangle(ray, tri) else {
+            continue;
+        };
+        if distance < min || distance > max || best.is_some_and(|b| b.distance <= distance) {
+            continue;
+        }
+        best = Some(RayHit {
+            tetra: id,
+            face,
+            distance,
+            point: ray.get_point(distance),
+            barycentric,
+            normal,
+        });
+    }
+    best
+}
+
+/// Find the closest boundary face hit between `min` and `max`.
+fn first_hit<M: TetraMesh>(
+    mesh: &M,
+    bvh: Option<&Bvh<M::Key>>,
+    ray: Ray3d,
+    range: [f32; 2],
+    entering: bool,
+) -> Option<RayHit<M::Key>> {
+    match bvh {
+        Some(bvh) => bvh
+            .raycast(ray.origin, *ray.direction, range[1], |id| {
+                let hit = hit_tetra(mesh, id, ray, range, entering)?;
+                Some((hit.distance, hit))
+            })
+            .map(|(_, hit)| hit),
+        None => mesh
+            .tetras()
+            .filter_map(|(id, _)| hit_tetra(mesh, id, ray, range, entering))
+            .min_by(|a, b| a.distance.total_cmp(&b.distance)),
+    }
+}
+
+/// Find the first boundary face of a mesh hit by a ray, up to a maximum distance.
+///
+/// Faces are hit from either side, so a ray starting inside of the mesh hits the face it leaves through. Faces whose
+/// neighbor is missing from the mesh count as boundary faces. If a BVH is given, it has to be up to date with the mesh.
+pub fn raycast<M: TetraMesh>(
+    mesh: &M,
+    bvh: Option<&Bvh<M::Key>>,
+    ray: Ray3d,
+    max_distance: f32,
+) -> Option<RayHit<M::Key>> {
+    first_hit(mesh, bvh, ray, [0.0, max_distance], false)
+}
+
+/// Walk a ray through the inside of a mesh, returning every tetrahedron it passes through in order.
+///
+/// If the ray starts inside of the mesh, the walk starts at the tetrahedron containing the origin, and otherwise at the
+/// first boundary face the ray enters through. From there it steps through face links, and when the ray leaves the
+/// mesh, it continues from the next boundary face it enters through, so the walk works for concave meshes and meshes
+/// with multiple pieces. If a BVH is given, it has to be up to date with the mesh.
+pub fn walk_ray<'a, M: TetraMesh>(
+    mesh: &'a M,
+    bvh: Option<&'a Bvh<M::Key>>,
+    ray: Ray3d,
+    max_distance: f32,
+) -> RayWalk<'a, M> {
+    let start = match bvh {
+        Some(bvh) => bvh.locate(mesh, ray.origin),
+        None => mesh.tetras().find_map(|(id, tet)| {
+            let points = mesh.tetra_points(tet)?;
+            tetra_contains(points, ray.origin).then_some(id)
+        }),
+    };
+    RayWalk {
+        mesh,
+        bvh,
+        ray,
+        max_distance,
+        next: start.map(|id| (id, 0.0)),
+        started: start.is_some(),
+        visited: HashSet::new(),
+    }
+}
+
+/// An iterator over the tetrahedra a ray passes through, from [`walk_ray`].
+pub struct RayWalk<'a, M: TetraMesh> {
+    mesh: &'a M,
+    bvh: Option<&'a Bvh<M::Key>>,
+    ray: Ray3d,
+    max_distance: f32,
+    next: Option<(TetraId<M::Key>, f32)>,
+    started: bool,
+    /// Tetrahedra that have already been reported, so numerical trouble at edges and vertices can't make the walk loop.
+    visited: HashSet<TetraId<M::Key>>,
+}
+impl<M: TetraMesh> RayWalk<'_, M> {
+    /// Find where the ray leaves a tetrahedron, as the distance and the face.
+    fn exit(&self, points: [Vec3; 4]) -> Option<(f32, VertexIdx)> {
+        VertexIdx::VALS
+            .into_iter()
+            .filter_map(|face| {
+                let [a, b, c] = face.face_order().map(|i| *i.in_arr(&points));
+                let normal = (b - a).cross(c - a);
+                let speed = normal.dot(*self.ray.direction);
+                (speed > 0.0).then(|| ((a - self.ray.origin).dot(normal) / speed, face))
+            })
+            .min_by(|x, y| x.0.total_cmp(&y.0))
+    }
+}
+impl<M: TetraMesh> Iterator for RayWalk<'_, M> {
+    type Item = RaySegment<M::Key>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.started {
+            self.started = true;
+            let hit = first_hit(
+                self.mesh,
+                self.bvh,
+                self.ray,
+                [0.0, self.max_distance],
+                true,
+            )?;
+            self.next = Some((hit.tetra, hit.distance));
+        }
+        let (id, enter) = self.next.take()?;
+        if !self.visited.insert(id) {
+            return None;
+        }
+        let tet = self.mesh.get_tetra(id)?;
+        let points = self.mesh.tetra_points(tet)?;
+        let Some((exit, face)) = self.exit(points) else {
+            return Some(RaySegment {
+                tetra: id,
+                enter,
+                exit: enter,
+                exit_face: None,
+            });
+        };
+        let exit = exit.max(enter);
+        if exit >= self.max_distance {
+            return Some(RaySegment {
+                tetra: id,
+                enter,
+                exit: self.max_distance,
+                exit_face: None,
+            });
+        }
+        self.next = match tet.face(face) {
+            Some((n, _)) if self.mesh.get_tetra(n).is_some() => Some((n, exit)),
+            // the ray left the mesh, so look for where it enters again
+            _ => first_hit(
+                self.mesh,
+                self.bvh,
+                self.ray,
+                [exit, self.max_distance],
+                true,
+            )
+            .filter(|hit| hit.tetra != id)
+            .map(|hit| (hit.tetra, hit.distance)),
+        };
+        Some(RaySegment {
+            tetra: id,
+            enter,
+            exit,
+            exit_face: Some(face),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+    use bevy_math::Dir3;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    fn ray(origin: Vec3, direction: Vec3) -> Ray3d {
+        Ray3d::new(origin, Dir3::new(direction).unwrap())
+    }
+
+    /// Check a hit against the face it claims to be on.
+    fn check_hit(mesh: &Mesh, hit: &RayHit<u32>, distance: f32, normal: Vec3) {
+        assert!((hit.distance - distance).abs() < 1e-5, "{hit:?}");
+        assert!(hit.normal.distance(normal) < 1e-5, "{hit:?}");
+        let tet = mesh.get_tetra(hit.tetra).unwrap();
+        assert!(tet.face(hit.face).is_none());
+        let points = mesh.tetra_points(tet).unwrap();
+        let [a, b, c] = hit.face.face_order().map(|i| *i.in_arr(&points));
+        let b_coords = hit.barycentric;
+        assert!(b_coords.min_element() >= 0.0 && (b_coords.element_sum() - 1.0).abs() < 1e-5);
+        let point = a * b_coords.x + b * b_coords.y + c * b_coords.z;
+        assert!(point.distance(hit.point) < 1e-5, "{hit:?}");
+    }
+
+    #[test]
+    fn raycast_cube() {
+        let mesh: Mesh = lattice(4);
+        let bvh = Bvh::build(&mesh);
+        for bvh in [None, Some(&bvh)] {
+            let outside = ray(Vec3::new(-1.0, 0.3, 0.6), Vec3::X);
+            let hit = raycast(&mesh, bvh, outside, 10.0).unwrap();
+            check_hit(&mesh, &hit, 1.0, Vec3::NEG_X);
+            assert!(hit.point.distance(Vec3::new(0.0, 0.3, 0.6)) < 1e-5);
+
+            // starting inside hits the face the ray leaves through, which faces away from it
+            let inside = ray(Vec3::new(0.5, 0.3, 0.6), Vec3::X);
+            let hit = raycast(&mesh, bvh, inside, 10.0).unwrap();
+            check_hit(&mesh, &hit, 0.5, Vec3::X);
+
+            let diagonal = ray(Vec3::new(0.4, -1.0, -0.5), Vec3::new(0.0, 1.0, 1.0));
+            let hit = raycast(&mesh, bvh, diagonal, 10.0).unwrap();
+            check_hit(&mesh, &hit, 2.0f32.sqrt(), Vec3::NEG_Y);
+
+            assert_eq!(raycast(&mesh, bvh, outside, 0.9), None);
+            assert_eq!(
+                raycast(&mesh, bvh, ray(outside.origin, Vec3::NEG_X), 10.0),
+                None
+            );
+        }
+    }
+
+    /// Check that a walk is contiguous from `start` to `end`, and stops at the far face if it gets there.
+    fn check_walk(mesh: &Mesh, walk: &[RaySegment<u32>], start: f32, end: f32, through: bool) {
+        assert!(walk.len() > 1);
+        assert!((walk[0].enter - start).abs() < 1e-5, "{walk:?}");
+        for pair in walk.windows(2) {
+            assert_eq!(pair[0].exit, pair[1].enter);
+            assert!(pair[0].enter <= pair[0].exit);
+            let tet = mesh.get_tetra(pair[0].tetra).unwrap();
+            let face = pair[0].exit_face.unwrap();
+            assert_eq!(tet.face(face).map(|(n, _)| n), Some(pair[1].tetra));
+        }
+        let last = walk.last().unwrap();
+        assert!((last.exit - end).abs() < 1e-5, "{last:?}");
+        match last.exit_face {
+            Some(face) => {
+                assert!(through);
+                assert!(mesh.get_tetra(last.tetra).unwrap().face(face).is_none());
+            }
+            None => assert!(!through),
+        }
+    }
+
+    #[test]
+    fn walk_ray_through_cube() {
+        let mesh: Mesh = lattice(4);
+        let bvh = Bvh::build(&mesh);
+        let outside = ray(Vec3::new(-1.0, 0.31, 0.62), Vec3::X);
+        let inside = ray(Vec3::new(0.2, 0.31, 0.62), Vec3::X);
+        let walks = [None, Some(&bvh)].map(|bvh| {
+            let from_outside = walk_ray(&mesh, bvh, outside, 10.0).collect::<Vec<_>>();
+            check_walk(&mesh, &from_outside, 1.0, 2.0, true);
+            let from_inside = walk_ray(&mesh, bvh, inside, 10.0).collect::<Vec<_>>();
+            check_walk(&mesh, &from_inside, 0.0, 0.8, true);
+            let stopped = walk_ray(&mesh, bvh, inside, 0.5).collect::<Vec<_>>();
+            check_walk(&mesh, &stopped, 0.0, 0.5, false);
+            assert_eq!(walk_ray(&mesh, bvh, outside, 0.9).next(), None);
+            [from_outside, from_inside, stopped]
+        });
+        assert_eq!(walks[0], walks[1]);
+    }
+}
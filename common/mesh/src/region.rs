@@ -0,0 +1,409 @@
+//! Removing regions of a mesh, for digging into it.
+//!
+//! A region is described by a signed distance function, which is negative inside of it. Tetrahedra inside of the
+//! region are removed, the faces they uncover become boundary faces, and vertices that nothing uses anymore are removed
+//! too. Tetrahedra straddling the edge of the region can optionally be cut along it, which gives smooth craters instead
+//! of ones made of whole tetrahedra.
+//!
+//! Cutting is done by adding a vertex wherever an edge crosses the region's surface, and filling what's left of each
+//! tetrahedron with new ones. The vertices are shared between neighboring tetrahedra, and the leftover prisms are split
+//! the same way on both sides of each face, so the result stays connected.
+
+use crate::bvh::Bvh;
+use crate::edit::{Edit, attach, detach};
+use crate::geometry::{bounds_of, signed_volume};
+use crate::traits::*;
+use bevy_math::Vec3;
+use bevy_math::bounding::{Aabb3d, BoundingSphere, BoundingVolume};
+use std::collections::{HashMap, HashSet};
+
+/// The number of steps used to find where an edge crosses the surface of a region.
+const REFINE_STEPS: usize = 4;
+
+/// A region of space, for [`remove_region`].
+pub trait Region {
+    /// Get the signed distance from a point to the surface of the region, which is negative inside of it.
+    ///
+    /// This doesn't need to be an exact distance, as long as it has the right sign and is roughly linear near the
+    /// surface.
+    fn distance(&self, point: Vec3) -> f32;
+    /// Get a box containing the region, if it's bounded.
+    ///
+    /// This lets [`remove_region`] skip most of the mesh when it's given a BVH.
+    fn bounds(&self) -> Option<[Vec3; 2]> {
+        None
+    }
+}
+impl Region for BoundingSphere {
+    fn distance(&self, point: Vec3) -> f32 {
+        point.distance(self.center.into()) - self.radius()
+    }
+    fn bounds(&self) -> Option<[Vec3; 2]> {
+        let center = Vec3::from(self.center);
+        Some([center - self.radius(), center + self.radius()])
+    }
+}
+impl Region for Aabb3d {
+    fn distance(&self, point: Vec3) -> f32 {
+        let center = Vec3::from(self.center());
+        let q = (point - center).abs() - Vec3::from(self.half_size());
+        q.max(Vec3::ZERO).length() + q.max_element().min(0.0)
+    }
+    fn bounds(&self) -> Option<[Vec3; 2]> {
+        Some([self.min.into(), self.max.into()])
+    }
+}
+/// Any function can be used as an unbounded signed distance function.
+impl<F: Fn(Vec3) -> f32> Region for F {
+    fn distance(&self, point: Vec3) -> f32 {
+        self(point)
+    }
+}
+
+/// Options for [`remove_region`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RemoveOptions {
+    /// Cut tetrahedra straddling the surface of the region, instead of removing the ones with their center inside.
+    pub split: bool,
+    /// The smallest fraction of an edge that is kept when it's cut.
+    ///
+    /// Cutting an edge right next to either of its vertices would leave slivers behind, so cuts are kept at least this
+    /// far from both ends of the edge, as a fraction of its length. This is limited to between 0 and 0.5, where every
+    /// cut is in the middle of its edge, and NaN is treated as 0.
+    pub min_fraction: f32,
+}
+impl Default for RemoveOptions {
+    fn default() -> Self {
+        Self {
+            split: false,
+            min_fraction: 0.1,
+        }
+    }
+}
+
+/// Everything changed by [`remove_region`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Removal<K> {
+    /// The tetrahedra that were removed, and the pieces of the cut ones that were added back.
+    pub edit: Edit<K>,
+    /// The vertices added where edges were cut.
+    pub added_verts: Vec<VertexId<K>>,
+    /// The vertices that were removed because nothing used them anymore.
+    pub removed_verts: Vec<VertexId<K>>,
+}
+
+/// Split a convex polytope into tetrahedra, given its faces with their vertices in order around them.
+///
+/// This cones the smallest vertex to every face that doesn't contain it, and fans each of those faces from their own
+/// smallest vertex. Since a face is always split the same way regardless of which polytope it's in, neighboring
+/// polytopes agree on how their shared faces are split.
+pub(crate) fn pull<K: Copy + Ord>(faces: &[&[VertexId<K>]]) -> Vec<[VertexId<K>; 4]> {
+    let Some(apex) = faces.iter().flat_map(|f| f.iter()).min().copied() else {
+        return Vec::new();
+    };
+    let mut out = Vec::new();
+    for face in faces {
+        if face.contains(&apex) {
+            continue;
+        }
+        let Some(start) = face.iter().enumerate().min_by_key(|f| f.1).map(|f| f.0) else {
+            continue;
+        };
+        let n = face.len();
+        for k in 1..n - 1 {
+            out.push([
+                apex,
+                face[start],
+                face[(start + k) % n],
+                face[(start + k + 1) % n],
+            ]);
+        }
+    }
+    out
+}
+
+/// Split a triangular prism into tetrahedra, given its two ends with matching corners.
+fn prism<K: Copy + Ord>(p: [VertexId<K>; 3], q: [VertexId<K>; 3]) -> Vec<[VertexId<K>; 4]> {
+    pull(&[
+        &p,
+        &q,
+        &[p[0], p[1], q[1], q[0]],
+        &[p[1], p[2], q[2], q[1]],
+        &[p[2], p[0], q[0], q[2]],
+    ])
+}
+
+/// Find where an edge crosses the surface of a region, as a fraction of the way from `a` to `b`.
+///
+/// `a` has to be outside of the region and `b` inside, which `da` and `db` are their distances for.
+fn crossing<R: Region + ?Sized>(region: &R, [a, b]: [Vec3; 2], [da, db]: [f32; 2]) -> f32 {
+    // regula falsi, which converges quickly when the distance is close to linear
+    let [mut lo, mut hi, mut dlo, mut dhi] = [0.0, 1.0, da, db];
+    let mut t = dlo / (dlo - dhi);
+    for _ in 0..REFINE_STEPS {
+        let d = region.distance(a.lerp(b, t));
+        if d == 0.0 {
+            break;
+        } else if d > 0.0 {
+            [lo, dlo] = [t, d];
+        } else {
+            [hi, dhi] = [t, d];
+        }
+        t = lo + (hi - lo) * dlo / (dlo - dhi);
+    }
+    t
+}
+
+/// Remove every tetrahedron inside of a region.
+///
+/// Without splitting, tetrahedra are removed if their center is inside of the region. With splitting, tetrahedra with
+/// every corner inside are removed, and ones with only some of them inside are cut along the surface of the region,
+/// keeping their data. The region is only sampled at the corners and along the edges, so features smaller than a
+/// tetrahedron are missed.
+///
+/// If a BVH is given, it has to be up to date with the mesh, and is kept up to date with the changes.
+pub fn remove_region<M: TetraMeshMut, R: Region + ?Sized>(
+    mesh: &mut M,
+    mut bvh: Option<&mut Bvh<M::Key>>,
+    region: &R,
+    opts: RemoveOptions,
+) -> Removal<M::Key>
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let candidates = match (&bvh, region.bounds()) {
+        (Some(bvh), Some(bounds)) => bvh.overlapping(bounds),
+        _ => mesh.tetras().map(|(id, _)| id).collect(),
+    };
+    let mut distances = HashMap::new();
+    let mut old = Vec::new();
+    // the corners of each tetrahedron that's cut, and which of them are inside
+    let mut cuts = Vec::new();
+    for id in candidates {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        let Some(points) = mesh.tetra_points(tet) else {
+            continue;
+        };
+        let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+        if !opts.split {
+            if region.distance(points.iter().sum::<Vec3>() / 4.0) < 0.0 {
+                old.push(id);
+            }
+            continue;
+        }
+        let dists = [0, 1, 2, 3].map(|k| {
+            *distances
+                .entry(verts[k])
+                .or_insert_with(|| region.distance(points[k]))
+        });
+        match dists.iter().filter(|&&d| d < 0.0).count() {
+            0 => {}
+            4 => old.push(id),
+            _ => {
+                cuts.push((old.len(), verts, points, dists));
+                old.push(id);
+            }
+        }
+    }
+    if old.is_empty() {
+        return Removal {
+            edit: Edit {
+                created: Vec::new(),
+                removed: Vec::new(),
+            },
+            added_verts: Vec::new(),
+            removed_verts: Vec::new(),
+        };
+    }
+
+    // add the vertices where edges are cut, shared by every tetrahedron around the edge
+    let min_fraction = match opts.min_fraction {
+        f if f.is_nan() => 0.0,
+        f => f.clamp(0.0, 0.5),
+    };
+    let mut added = HashMap::new();
+    let mut added_verts = Vec::new();
+    let mut pieces = Vec::new();
+    for &(idx, verts, points, dists) in &cuts {
+        let mut cut = |k: usize, r: usize| {
+            let key = [verts[k].min(verts[r]), verts[k].max(verts[r])];
+            *added.entry(key).or_insert_with(|| {
+                let t = crossing(region, [points[k], points[r]], [dists[k], dists[r]])
+                    .clamp(min_fraction, 1.0 - min_fraction);
+                let mut vert = mesh
+                    .get_vertex(verts[k])
+                    .expect("tetrahedron points to a non-existent vertex")
+                    .clone();
+                vert.set_vec3(points[k].lerp(points[r], t));
+                let id = mesh.add_vertex(vert);
+                added_verts.push(id);
+                id
+            })
+        };
+        let (kept, removed): (Vec<usize>, Vec<usize>) = (0..4).partition(|&k| dists[k] >= 0.0);
+        let new = match (kept.as_slice(), removed.as_slice()) {
+            (&[a], &[b, c, d]) => vec![[verts[a], cut(a, b), cut(a, c), cut(a, d)]],
+            (&[a, b, c], &[d]) => prism(
+                [verts[a], verts[b], verts[c]],
+                [cut(a, d), cut(b, d), cut(c, d)],
+            ),
+            (&[a, b], &[c, d]) => prism(
+                [verts[a], cut(a, c), cut(a, d)],
+                [verts[b], cut(b, c), cut(b, d)],
+            ),
+            _ => Vec::new(),
+        };
+        pieces.extend(new.into_iter().map(|v| (idx, v)));
+    }
+
+    let removed_verts = old
+        .iter()
+        .filter_map(|&id| mesh.get_tetra(id))
+        .flat_map(|t| VertexIdx::VALS.map(|i| t.vertex(i)))
+        .collect::<HashSet<_>>();
+    let (open, values) = detach(mesh, &old);
+    let new = pieces.into_iter().filter_map(|(idx, mut verts)| {
+        let points = verts.map(|v| mesh.get_vertex(v).map(|v| v.as_vec3()));
+        let volume = signed_volume(points.map(|p| p.unwrap_or_default()));
+        if volume == 0.0 || points.iter().any(Option::is_none) {
+            return None;
+        }
+        if volume < 0.0 {
+            verts.swap(2, 3);
+        }
+        let mut tet = values.get(idx)?.clone();
+        for (i, v) in VertexIdx::VALS.into_iter().zip(verts) {
+            tet.set_vertex(i, v);
+            tet.set_face(i, None);
+        }
+        Some(tet)
+    });
+    let new = new.collect::<Vec<_>>();
+    let created = attach(mesh, open, new);
+
+    if let Some(bvh) = &mut bvh {
+        for &id in &old {
+            bvh.remove(id);
+        }
+        for &id in &created {
+            bvh.update_tetra(mesh, id);
+        }
+    }
+
+    // only vertices of removed tetrahedra can have become unused
+    let used = {
+        let positions = removed_verts
+            .iter()
+            .filter_map(|&v| mesh.get_vertex(v).map(|v| v.as_vec3()));
+        let nearby = match &bvh {
+            Some(bvh) => bvh.overlapping(bounds_of(positions)),
+            None => mesh.tetras().map(|(id, _)| id).collect(),
+        };
+        nearby
+            .into_iter()
+            .filter_map(|id| mesh.get_tetra(id))
+            .flat_map(|t| VertexIdx::VALS.map(|i| t.vertex(i)))
+            .collect::<HashSet<_>>()
+    };
+    let mut removed_verts = removed_verts
+        .into_iter()
+        .filter(|v| !used.contains(v))
+        .collect::<Vec<_>>();
+    removed_verts.sort_unstable();
+    for &v in &removed_verts {
+        mesh.remove_vertex(v);
+    }
+    Removal {
+        edit: Edit {
+            created,
+            removed: old,
+        },
+        added_verts,
+        removed_verts,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mass::mass_properties;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+    use crate::validate::validate;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    /// Dig a sphere out of the middle of a lattice, and check that what's left is valid, has no unused vertices, and
+    /// lost about the volume of the sphere, within `tolerance` as a fraction of it.
+    fn dig(split: bool, tolerance: f32) -> (Mesh, Removal<u32>) {
+        let mut mesh: Mesh = lattice(9);
+        let mut bvh = Bvh::build(&mesh);
+        let before = mass_properties(&mesh).volume;
+        let sphere = BoundingSphere::new(Vec3::splat(0.5), 0.3);
+        let opts = RemoveOptions {
+            split,
+            ..Default::default()
+        };
+        let removal = remove_region(&mut mesh, Some(&mut bvh), &sphere, opts);
+
+        let report = validate(&mesh);
+        assert!(report.is_valid(), "{report}");
+        let used = mesh
+            .tetras()
+            .flat_map(|(_, t)| VertexIdx::VALS.map(|i| t.vertex(i)))
+            .collect::<HashSet<_>>();
+        for (id, _) in mesh.verts() {
+            assert!(used.contains(&id), "{id:?} is unused");
+        }
+        for &id in &removal.removed_verts {
+            assert!(mesh.get_vertex(id).is_none());
+        }
+
+        let removed = before - mass_properties(&mesh).volume;
+        let expected = 4.0 / 3.0 * std::f32::consts::PI * 0.3f32.powi(3);
+        assert!(
+            (removed / expected - 1.0).abs() < tolerance,
+            "expected to remove {expected}, removed {removed}"
+        );
+        (mesh, removal)
+    }
+
+    #[test]
+    fn remove_whole_tetras() {
+        let (_, removal) = dig(false, 0.25);
+        assert!(!removal.edit.removed.is_empty());
+        assert!(removal.edit.created.is_empty());
+        assert!(removal.added_verts.is_empty());
+        assert!(!removal.removed_verts.is_empty());
+    }
+
+    #[test]
+    fn remove_cut_tetras() {
+        let (mesh, removal) = dig(true, 0.1);
+        assert!(!removal.edit.created.is_empty());
+        assert!(!removal.added_verts.is_empty());
+        // the cut vertices are all on the surface of the sphere, so the crater is smooth
+        for &id in &removal.added_verts {
+            let pos = mesh.get_vertex(id).unwrap().pos;
+            assert!((pos.distance(Vec3::splat(0.5)) - 0.3).abs() < 0.02);
+        }
+    }
+
+    #[test]
+    fn out_of_range_min_fraction() {
+        for min_fraction in [0.6, 1.5, -1.0, f32::NAN] {
+            let mut mesh: Mesh = lattice(5);
+            let sphere = BoundingSphere::new(Vec3::splat(0.5), 0.3);
+            let opts = RemoveOptions {
+                split: true,
+                min_fraction,
+            };
+            remove_region(&mut mesh, None, &sphere, opts);
+            let report = validate(&mesh);
+            assert!(report.is_valid(), "{min_fraction}: {report}");
+        }
+    }
+}
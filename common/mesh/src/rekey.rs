@@ -0,0 +1,585 @@
+//! Converting meshes between key types.
+//!
+//! Small keys like `u8` and `u16` save memory, but they limit how big a mesh can get, as given by
+//! [`SlabMesh::max_verts`] and [`SlabMesh::max_tetras`]. A mesh that outgrows its keys can be moved to wider ones with
+//! [`SlabMesh::try_rekey`], which keeps every ID the same, or rebuilt with a different number of generation bits with
+//! [`SlabMesh::try_convert`], which gives every vertex and tetrahedron a new ID. [`GrowingMesh`] does the first one by
+//! itself whenever it fills up.
+
+use crate::builder::BuildMesh;
+use crate::components::IdMap;
+use crate::generation::*;
+use crate::slab_mesh::{DefaultPackedMesh, SlabKey, SlabMesh};
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::marker::PhantomData;
+
+/// Tetrahedron data that can be rebuilt with a different key type.
+pub trait Rekey<K, K2> {
+    /// The same kind of tetrahedron, using the new keys.
+    type Output: TetraDataMut<K2>;
+    /// Rebuild the tetrahedron with new keys for its vertices and neighbors.
+    ///
+    /// Neighbors that `tetra` doesn't give a key for are unlinked.
+    fn rekey(
+        self,
+        vertex: impl FnMut(VertexId<K>) -> VertexId<K2>,
+        tetra: impl FnMut(TetraId<K>) -> Option<TetraId<K2>>,
+    ) -> Self::Output;
+}
+
+impl<K: Copy, F: FaceData<K> + Copy, T> Tetra<K, F, T> {
+    /// Rebuild the tetrahedron with new keys, and possibly a different layout for its faces.
+    ///
+    /// Neighbors that `tetra` doesn't give a key for are unlinked.
+    pub fn rekey_with<K2, F2: FaceData<K2>>(
+        self,
+        mut vertex: impl FnMut(VertexId<K>) -> VertexId<K2>,
+        mut tetra: impl FnMut(TetraId<K>) -> Option<TetraId<K2>>,
+    ) -> Tetra<K2, F2, T> {
+        Tetra {
+            conns: self.conns.map(|(v, f)| {
+                let face = f.into_option().and_then(|(n, i)| Some((tetra(n)?, i)));
+                (vertex(v), F2::from_option(face))
+            }),
+            data: self.data,
+        }
+    }
+}
+impl<K: Copy, K2: Copy, T> Rekey<K, K2> for Tetra<K, BasicFace<K>, T> {
+    type Output = Tetra<K2, BasicFace<K2>, T>;
+
+    #[inline(always)]
+    fn rekey(
+        self,
+        vertex: impl FnMut(VertexId<K>) -> VertexId<K2>,
+        tetra: impl FnMut(TetraId<K>) -> Option<TetraId<K2>>,
+    ) -> Self::Output {
+        self.rekey_with(vertex, tetra)
+    }
+}
+impl<K: Copy, K2: Copy, T> Rekey<K, K2> for Tetra<K, PackedFace<K>, T>
+where
+    PackedFace<K>: FaceData<K>,
+    PackedFace<K2>: FaceData<K2>,
+{
+    type Output = Tetra<K2, PackedFace<K2>, T>;
+
+    #[inline(always)]
+    fn rekey(
+        self,
+        vertex: impl FnMut(VertexId<K>) -> VertexId<K2>,
+        tetra: impl FnMut(TetraId<K>) -> Option<TetraId<K2>>,
+    ) -> Self::Output {
+        self.rekey_with(vertex, tetra)
+    }
+}
+/// An error from converting a mesh to keys that can't refer to all of it, which gives the mesh back.
+#[derive(Debug)]
+pub struct RekeyError<M> {
+    pub mesh: Box<M>,
+    pub error: CapacityError,
+}
+impl<M> Display for RekeyError<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+impl<M: Debug> std::error::Error for RekeyError<M> {}
+
+impl<K: SlabKey<GEN_BITS>, V: VertexData, T: TetraData<K>, const GEN_BITS: usize>
+    SlabMesh<K, V, T, GEN_BITS>
+where
+    BitMarker<GEN_BITS>: HasGeneration,
+{
+    /// Convert the mesh to a different key type with the same number of generation bits, keeping every ID.
+    ///
+    /// IDs are only repacked, so an ID from before can be converted by passing what [`SlabKey::unpack`] gives to
+    /// [`SlabKey::pack`] for the new key. This fails if the mesh has slots past what the new keys can refer to, which
+    /// can only happen when they're narrower.
+    pub fn try_rekey<K2: SlabKey<GEN_BITS>>(
+        self,
+    ) -> Result<SlabMesh<K2, V, T::Output, GEN_BITS>, RekeyError<Self>>
+    where
+        T: Rekey<K, K2>,
+    {
+        let max_verts = SlabMesh::<K2, V, T::Output, GEN_BITS>::max_verts();
+        let max_tetras = SlabMesh::<K2, V, T::Output, GEN_BITS>::max_tetras();
+        let error = if self.verts.max_idx() > max_verts {
+            CapacityError::Vertices {
+                capacity: max_verts,
+            }
+        } else if self.tetras.max_idx() > max_tetras {
+            CapacityError::Tetras {
+                capacity: max_tetras,
+            }
+        } else {
+            let (stitching, merging) = (self.is_stitching(), self.is_merging_vertices());
+            let repack = |key: K| {
+                let (index, generation) = key.unpack();
+                K2::pack(index, generation)
+            };
+            let mut mesh = SlabMesh {
+                verts: self.verts,
+                tetras: self.tetras.map(|tet| {
+                    tet.rekey(|v| VertexId(repack(v.0)), |n| Some(TetraId(repack(n.0))))
+                }),
+                bounds: self.bounds,
+                stitch: None,
+                _marker: PhantomData,
+            };
+            if merging {
+                mesh.enable_vertex_merging();
+            } else if stitching {
+                mesh.enable_stitching();
+            }
+            return Ok(mesh);
+        };
+        Err(RekeyError {
+            mesh: Box::new(self),
+            error,
+        })
+    }
+
+    /// Rebuild the mesh with a different key type and number of generation bits, returning the new ID of everything.
+    ///
+    /// Vertices and tetrahedra are packed at the front of the new mesh in the order they're iterated in, starting over
+    /// from the first generation. Tetrahedra with a missing vertex are left out. This fails if the new keys can't refer
+    /// to every vertex and tetrahedron.
+    #[allow(clippy::type_complexity)]
+    pub fn try_convert<K2: SlabKey<GEN_BITS2>, const GEN_BITS2: usize>(
+        self,
+    ) -> Result<(SlabMesh<K2, V, T::Output, GEN_BITS2>, IdMap<K, K2>), RekeyError<Self>>
+    where
+        T: Rekey<K, K2>,
+        BitMarker<GEN_BITS2>: HasGeneration,
+    {
+        let max_verts = SlabMesh::<K2, V, T::Output, GEN_BITS2>::max_verts();
+        let max_tetras = SlabMesh::<K2, V, T::Output, GEN_BITS2>::max_tetras();
+        if self.verts.len() > max_verts {
+            let error = CapacityError::Vertices {
+                capacity: max_verts,
+            };
+            return Err(RekeyError {
+                mesh: Box::new(self),
+                error,
+            });
+        }
+        if self.tetras.len() > max_tetras {
+            let error = CapacityError::Tetras {
+                capacity: max_tetras,
+            };
+            return Err(RekeyError {
+                mesh: Box::new(self),
+                error,
+            });
+        }
+        let (stitching, merging) = (self.is_stitching(), self.is_merging_vertices());
+        let mut mesh = SlabMesh::<K2, V, T::Output, GEN_BITS2>::new();
+        mesh.bounds = self.bounds;
+        let mut map = IdMap {
+            verts: HashMap::with_capacity(self.verts.len()),
+            tetras: HashMap::with_capacity(self.tetras.len()),
+        };
+        for (idx, vert) in self.verts {
+            let new = mesh.verts.insert(vert);
+            map.verts.insert(
+                VertexId(K::pack(idx.index, idx.generation)),
+                VertexId(K2::pack(new.index, new.generation)),
+            );
+        }
+        // neighbors only have new IDs once they've been added, so the links are made afterwards
+        let mut links = Vec::with_capacity(self.tetras.len());
+        for (idx, tet) in self.tetras {
+            if VertexIdx::VALS
+                .iter()
+                .any(|&i| !map.verts.contains_key(&tet.vertex(i)))
+            {
+                continue;
+            }
+            let faces = VertexIdx::VALS.map(|i| tet.face(i));
+            let new = mesh.tetras.insert(tet.rekey(|v| map.verts[&v], |_| None));
+            map.tetras.insert(
+                TetraId(K::pack(idx.index, idx.generation)),
+                TetraId(K2::pack(new.index, new.generation)),
+            );
+            links.push((new, faces));
+        }
+        for (idx, faces) in links {
+            let Some(tet) = mesh.tetras.get_mut(idx) else {
+                continue;
+            };
+            for (i, face) in VertexIdx::VALS.into_iter().zip(faces) {
+                if let Some((n, j)) = face
+                    && let Some(&n) = map.tetras.get(&n)
+                {
+                    tet.set_face(i, Some((n, j)));
+                }
+            }
+        }
+        if merging {
+            mesh.enable_vertex_merging();
+        } else if stitching {
+            mesh.enable_stitching();
+        }
+        Ok((mesh, map))
+    }
+}
+
+/// A mesh that starts out with `u8` keys, and moves to wider ones whenever it fills up.
+///
+/// IDs are given out as `u64`s, which stay the same when the keys get wider since [`SlabMesh::try_rekey`] keeps every
+/// index. There aren't any generation bits, so like in any [`DefaultPackedMesh`] without them, the IDs of removed
+/// vertices and tetrahedra are reused.
+///
+/// Tetrahedra are stored with the narrow keys, so they're given out as copies with `u64` keys that borrow their data.
+/// Keys are only widened transparently for the methods here and for builders, through [`BuildMesh`]. This doesn't
+/// implement [`TetraMesh`], since that hands out references to tetrahedra with the mesh's own key type, so using it
+/// with anything else, like [`validate`](crate::validate::validate) or a [`DynMesh`](crate::ecs::DynMesh), is done by
+/// matching on it for the [`SlabMesh`] with the current key type. IDs from that mesh are the same as the `u64` ones,
+/// just narrower.
+#[derive(Debug)]
+pub enum GrowingMesh<V = (), T = ()> {
+    U8(DefaultPackedMesh<u8, V, T>),
+    U16(DefaultPackedMesh<u16, V, T>),
+    U32(DefaultPackedMesh<u32, V, T>),
+    U64(DefaultPackedMesh<u64, V, T>),
+}
+
+/// Run the same code on whichever mesh a [`GrowingMesh`] holds.
+macro_rules! each {
+    ($this:expr, $mesh:ident => $body:expr) => {
+        match $this {
+            GrowingMesh::U8($mesh) => $body,
+            GrowingMesh::U16($mesh) => $body,
+            GrowingMesh::U32($mesh) => $body,
+            GrowingMesh::U64($mesh) => $body,
+        }
+    };
+}
+
+/// Check if a mesh is out of room for vertices and tetrahedra.
+fn is_full<K: SlabKey<0>, V, T>(mesh: &DefaultPackedMesh<K, V, T>) -> [bool; 2]
+where
+    PackedFace<K>: FaceData<K>,
+{
+    [
+        mesh.verts.len() >= DefaultPackedMesh::<K, V, T>::max_verts(),
+        mesh.tetras.len() >= DefaultPackedMesh::<K, V, T>::max_tetras(),
+    ]
+}
+
+/// Get a tetrahedron with `u64` keys, borrowing its data.
+fn widen<K: Copy + Into<u64>, T>(tet: &Tetra<K, PackedFace<K>, T>) -> Tetra<u64, BasicFace<u64>, &T>
+where
+    PackedFace<K>: FaceData<K>,
+{
+    Tetra {
+        conns: VertexIdx::VALS.map(|i| {
+            let face = tet.face(i).map(|(n, j)| (TetraId(n.0.into()), j));
+            (VertexId(tet.vertex(i).0.into()), face)
+        }),
+        data: &tet.data,
+    }
+}
+
+impl<V, T> Default for GrowingMesh<V, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+// the arm for `u64` keys converts them to the same type
+#[allow(clippy::useless_conversion)]
+impl<V, T> GrowingMesh<V, T> {
+    /// Create a new, empty mesh with `u8` keys.
+    pub const fn new() -> Self {
+        Self::U8(SlabMesh::new())
+    }
+    /// Get the number of bits in the current keys.
+    pub const fn key_bits(&self) -> u32 {
+        match self {
+            Self::U8(_) => u8::BITS,
+            Self::U16(_) => u16::BITS,
+            Self::U32(_) => u32::BITS,
+            Self::U64(_) => u64::BITS,
+        }
+    }
+    /// Get the number of vertices.
+    pub fn vertex_count(&self) -> usize {
+        each!(self, mesh => mesh.verts.len())
+    }
+    /// Get the number of tetrahedra.
+    pub fn tetra_count(&self) -> usize {
+        each!(self, mesh => mesh.tetras.len())
+    }
+    /// Move to the next wider keys, returning false if the keys are already as wide as they get.
+    ///
+    /// This is done automatically when adding to a full mesh, but doing it ahead of time avoids the pause while it's
+    /// being done.
+    pub fn widen(&mut self) -> bool {
+        let widened = match std::mem::take(self) {
+            Self::U8(mesh) => mesh.try_rekey().ok().map(Self::U16),
+            Self::U16(mesh) => mesh.try_rekey().ok().map(Self::U32),
+            Self::U32(mesh) => mesh.try_rekey().ok().map(Self::U64),
+            Self::U64(mesh) => {
+                *self = Self::U64(mesh);
+                return false;
+            }
+        };
+        *self = widened.expect("wider keys can refer to everything narrower ones can");
+        true
+    }
+    /// Get a vertex.
+    pub fn get_vertex(&self, id: VertexId<u64>) -> Option<&Vertex<V>> {
+        each!(self, mesh => mesh.get_vertex(VertexId(id.0.try_into().ok()?)))
+    }
+    /// Mutably get a vertex.
+    pub fn get_vertex_mut(&mut self, id: VertexId<u64>) -> Option<&mut Vertex<V>> {
+        each!(self, mesh => mesh.get_vertex_mut(VertexId(id.0.try_into().ok()?)))
+    }
+    /// Get a copy of a tetrahedron with `u64` keys, borrowing its data.
+    pub fn get_tetra(&self, id: TetraId<u64>) -> Option<Tetra<u64, BasicFace<u64>, &T>> {
+        each!(self, mesh => mesh.get_tetra(TetraId(id.0.try_into().ok()?)).map(widen))
+    }
+    /// Mutably get the data of a tetrahedron.
+    pub fn get_tetra_data_mut(&mut self, id: TetraId<u64>) -> Option<&mut T> {
+        each!(self, mesh => Some(&mut mesh.get_tetra_mut(TetraId(id.0.try_into().ok()?))?.data))
+    }
+    /// Insert a vertex and get its ID, moving to wider keys first if the mesh is full.
+    pub fn add_vertex(&mut self, vert: Vertex<V>) -> VertexId<u64> {
+        if each!(self, mesh => is_full(mesh)[0]) {
+            self.widen();
+        }
+        each!(self, mesh => VertexId(mesh.add_vertex(vert).0.into()))
+    }
+    /// Insert a tetrahedron and get its ID, moving to wider keys first if the mesh is full.
+    ///
+    /// Neighbors that can't be in the mesh since their IDs don't fit in the current keys are left unlinked.
+    ///
+    /// # Panics
+    /// This panics if a vertex can't be in the mesh, since its ID doesn't fit in the current keys.
+    #[track_caller]
+    pub fn add_tetra(&mut self, tetra: Tetra<u64, BasicFace<u64>, T>) -> TetraId<u64> {
+        if each!(self, mesh => is_full(mesh)[1]) {
+            self.widen();
+        }
+        each!(self, mesh => {
+            // the panic is left until after the closure, which would report its own location instead of the caller's
+            let mut missing = None;
+            let narrow = |v: VertexId<u64>| match v.0.try_into() {
+                Ok(key) => VertexId(key),
+                Err(_) => {
+                    missing = Some(v);
+                    VertexId(Default::default())
+                }
+            };
+            let tetra = tetra.rekey_with(narrow, |n| n.0.try_into().ok().map(TetraId));
+            if let Some(v) = missing {
+                panic!("vertex {v:?} isn't in the mesh");
+            }
+            TetraId(mesh.add_tetra(tetra).0.into())
+        })
+    }
+    /// Remove a vertex.
+    pub fn remove_vertex(&mut self, id: VertexId<u64>) -> Option<Vertex<V>> {
+        each!(self, mesh => mesh.remove_vertex(VertexId(id.0.try_into().ok()?)))
+    }
+    /// Remove a tetrahedron, returning it with `u64` keys.
+    pub fn remove_tetra(&mut self, id: TetraId<u64>) -> Option<Tetra<u64, BasicFace<u64>, T>> {
+        each!(self, mesh => {
+            let tet = mesh.remove_tetra(TetraId(id.0.try_into().ok()?))?;
+            Some(tet.rekey_with(|v| VertexId(v.0.into()), |n| Some(TetraId(n.0.into()))))
+        })
+    }
+}
+impl<V: Default, T: Default> BuildMesh for &mut GrowingMesh<V, T> {
+    type Key = u64;
+
+    fn add_vertex(&mut self, vert: Vec3) -> VertexId<Self::Key> {
+        GrowingMesh::add_vertex(*self, vert.into())
+    }
+    fn add_tetra(&mut self, tetra: TetraPrimitive<Self::Key>) -> TetraId<Self::Key> {
+        GrowingMesh::add_tetra(*self, tetra.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Hexahedron, MeshBuilder};
+    use crate::geometry::signed_volume;
+    use crate::test_util::assert_map_resolves;
+    use crate::validate::validate;
+
+    type Tetras = Vec<(TetraId<u64>, [VertexId<u64>; 4])>;
+
+    /// A point on a helix, where every four in a row make a tetrahedron.
+    fn point(i: usize) -> Vec3 {
+        let angle = i as f32;
+        Vec3::new(angle.cos(), angle.sin(), i as f32 * 0.3)
+    }
+
+    /// Add the next tetrahedron in a strip along the helix, linked to the one before it.
+    fn add_tetra(mesh: &mut GrowingMesh, verts: &mut Vec<VertexId<u64>>, tetras: &mut Tetras) {
+        let n = tetras.len();
+        while verts.len() < n + 4 {
+            verts.push(mesh.add_vertex(point(verts.len()).into()));
+        }
+        let mut corners = [verts[n], verts[n + 1], verts[n + 2], verts[n + 3]];
+        if signed_volume([n, n + 1, n + 2, n + 3].map(point)) < 0.0 {
+            corners.swap(1, 2);
+        }
+        let mut tetra = Tetra::from(corners.map(|v| (v, None)));
+        if let Some(&(prev, _)) = tetras.last() {
+            tetra.set_face(VertexIdx::V3, Some((prev, VertexIdx::V0)));
+        }
+        tetras.push((mesh.add_tetra(tetra), corners));
+    }
+
+    /// Check that every vertex and tetrahedron added so far can still be found with its ID.
+    fn check(mesh: &GrowingMesh, verts: &[VertexId<u64>], tetras: &Tetras) {
+        assert_eq!(mesh.vertex_count(), verts.len());
+        assert_eq!(mesh.tetra_count(), tetras.len());
+        for (i, &id) in verts.iter().enumerate() {
+            assert_eq!(mesh.get_vertex(id).map(|v| v.pos), Some(point(i)));
+        }
+        for (n, &(id, corners)) in tetras.iter().enumerate() {
+            let tet = mesh.get_tetra(id).unwrap();
+            assert_eq!(VertexIdx::VALS.map(|i| tet.vertex(i)), corners);
+            let prev = n.checked_sub(1).map(|p| (tetras[p].0, VertexIdx::V0));
+            assert_eq!(tet.face(VertexIdx::V3), prev);
+        }
+        let valid = each!(mesh, mesh => validate(mesh).is_valid());
+        assert!(valid);
+    }
+
+    #[test]
+    fn widens_through_every_key() {
+        let mut mesh = GrowingMesh::new();
+        let mut verts = Vec::new();
+        let mut tetras = Vec::new();
+        while mesh.key_bits() == 8 {
+            add_tetra(&mut mesh, &mut verts, &mut tetras);
+        }
+        assert_eq!(tetras.len(), DefaultPackedMesh::<u8>::max_tetras() + 1);
+        check(&mesh, &verts, &tetras);
+        while mesh.key_bits() == 16 {
+            verts.push(mesh.add_vertex(point(verts.len()).into()));
+        }
+        assert_eq!(verts.len(), DefaultPackedMesh::<u16>::max_verts() + 1);
+        check(&mesh, &verts, &tetras);
+        // four billion vertices is too many for a test, so the last step is done by hand
+        assert!(mesh.widen());
+        assert_eq!(mesh.key_bits(), 64);
+        assert!(!mesh.widen());
+        check(&mesh, &verts, &tetras);
+        add_tetra(&mut mesh, &mut verts, &mut tetras);
+        check(&mesh, &verts, &tetras);
+    }
+
+    type Mesh<const GEN_BITS: usize> = SlabMesh<u32, Vertex, Tetra<u32>, GEN_BITS>;
+
+    /// A row of cubes with holes where some tetrahedra and a vertex were removed, and a vertex on a later generation.
+    fn holey() -> Mesh<8> {
+        let mut mesh = Mesh::<8>::new();
+        for x in 0..3 {
+            Hexahedron::UNIT_CUBE
+                .translate(Vec3::X * 2.0 * x as f32)
+                .append_to(&mut mesh);
+        }
+        let removed = mesh
+            .tetras()
+            .map(|(id, _)| id)
+            .step_by(3)
+            .collect::<Vec<_>>();
+        for id in removed {
+            mesh.remove_tetra(id);
+        }
+        let a = mesh.add_vertex(Vec3::splat(2.0).into());
+        mesh.add_vertex(Vec3::splat(3.0).into());
+        mesh.remove_vertex(a);
+        let b = mesh.add_vertex(Vec3::splat(4.0).into());
+        assert_ne!(SlabKey::<8>::unpack(b.0).1, SlabKey::<8>::unpack(a.0).1);
+        mesh
+    }
+
+    /// Check that neighbors are linked the same way after converting.
+    fn assert_links_kept<K: SlabKey<B>, K2: SlabKey<B2>, const B: usize, const B2: usize>(
+        from: &SlabMesh<K, Vertex, Tetra<K>, B>,
+        to: &SlabMesh<K2, Vertex, Tetra<K2>, B2>,
+        map: &IdMap<K, K2>,
+    ) where
+        BitMarker<B>: HasGeneration,
+        BitMarker<B2>: HasGeneration,
+    {
+        for (&old, &new) in &map.tetras {
+            let (old, new) = (from.get_tetra(old).unwrap(), to.get_tetra(new).unwrap());
+            for i in VertexIdx::VALS {
+                assert_eq!(old.face(i).map(|(n, j)| (map.tetras[&n], j)), new.face(i));
+            }
+        }
+    }
+
+    #[test]
+    fn convert_between_generation_bits() {
+        // the mesh isn't `Clone`, but building it again hands out the same IDs
+        let original = holey();
+        let (mesh, map) = holey().try_convert::<u32, 0>().unwrap();
+        assert!(validate(&mesh).is_valid());
+        assert_eq!(map.verts.len(), original.verts().count());
+        assert_eq!(map.tetras.len(), original.tetras().count());
+        assert_map_resolves(&original, &mesh, &map);
+        assert_links_kept(&original, &mesh, &map);
+        // everything is packed at the front
+        let mut verts = map.verts.values().map(|v| v.0).collect::<Vec<_>>();
+        verts.sort();
+        assert!(verts.into_iter().eq(0..mesh.verts().count() as u32));
+        let mut tetras = map.tetras.values().map(|t| t.0).collect::<Vec<_>>();
+        tetras.sort();
+        assert!(tetras.into_iter().eq(0..mesh.tetras().count() as u32));
+
+        // and back again, starting over from the first generation
+        let (packed, _) = holey().try_convert::<u32, 0>().unwrap();
+        let (back, back_map) = packed.try_convert::<u32, 8>().unwrap();
+        assert!(validate(&back).is_valid());
+        assert_map_resolves(&mesh, &back, &back_map);
+        assert_links_kept(&mesh, &back, &back_map);
+        let first = SlabKey::<8>::unpack(Mesh::<8>::new().add_vertex(Vec3::ZERO.into()).0).1;
+        assert!(
+            back_map
+                .verts
+                .values()
+                .all(|v| SlabKey::<8>::unpack(v.0).1 == first)
+        );
+    }
+
+    #[test]
+    fn convert_past_capacity_fails() {
+        let err = holey().try_convert::<u8, 4>().unwrap_err();
+        assert!(matches!(
+            err.error,
+            CapacityError::Vertices {
+                capacity
+            } if capacity == SlabMesh::<u8, Vertex, Tetra<u8>, 4>::max_verts()
+        ));
+        // the mesh is handed back untouched
+        assert_map_resolves(
+            &holey(),
+            &*err.mesh,
+            &IdMap {
+                verts: holey().verts().map(|(id, _)| (id, id)).collect(),
+                tetras: holey().tetras().map(|(id, _)| (id, id)).collect(),
+            },
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "isn't in the mesh")]
+    fn add_tetra_with_wide_vertex_panics() {
+        let mut mesh = GrowingMesh::<(), ()>::new();
+        let verts = [0, 1, 2, 1000].map(VertexId);
+        mesh.add_tetra(Tetra::from(verts.map(|v| (v, None))));
+    }
+}
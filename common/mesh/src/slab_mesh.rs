@@ -1,15 +1,40 @@
+use crate::components::IdMap;
 use crate::generation::*;
+use crate::geometry::position_key;
 use crate::traits::*;
 use bevy_math::Vec3;
+use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::hash::Hash;
 use std::marker::PhantomData;
+use std::num::NonZero;
 
 fn add_point([lower, upper]: &mut [Vec3; 2], point: Vec3) {
     *lower = lower.min(point);
     *upper = upper.max(point);
 }
 
+/// Lookup tables used to automatically stitch new tetrahedra onto a [`SlabMesh`].
+///
+/// These are kept up to date by [`TetraMeshMut::add_vertex`], [`TetraMeshMut::add_tetra`], and the corresponding
+/// removal methods. Changes made through [`TetraMeshMut::get_vertex_mut`] or [`TetraMeshMut::get_tetra_mut`] aren't
+/// tracked, so [`SlabMesh::enable_stitching`] should be called again after making them.
+#[derive(Debug, Clone)]
+pub struct StitchIndex<K> {
+    /// Vertices, keyed by the bits of their positions, if vertex merging is enabled.
+    pub verts: Option<HashMap<[u32; 3], VertexId<K>>>,
+    /// Faces without a neighbor, keyed by their sorted vertices.
+    pub faces: HashMap<[VertexId<K>; 3], (TetraId<K>, VertexIdx)>,
+}
+impl<K> Default for StitchIndex<K> {
+    fn default() -> Self {
+        Self {
+            verts: None,
+            faces: HashMap::new(),
+        }
+    }
+}
+
 /// A [`TetraMesh`] that's implemented using slabs.
 pub struct SlabMesh<K, V, T, const GEN_BITS: usize = 0>
 where
@@ -18,6 +43,8 @@ where
     pub verts: Slab<V, GEN_BITS>,
     pub tetras: Slab<T, GEN_BITS>,
     pub bounds: [Vec3; 2],
+    /// The stitching index, if stitching is enabled.
+    pub stitch: Option<StitchIndex<K>>,
     pub _marker: PhantomData<K>,
 }
 impl<K, V, T, const GEN_BITS: usize> SlabMesh<K, V, T, GEN_BITS>
@@ -30,10 +57,34 @@ where
             verts: Slab::new(),
             tetras: Slab::new(),
             bounds: [Vec3::INFINITY, Vec3::NEG_INFINITY],
+            stitch: None,
             _marker: PhantomData,
         }
     }
 }
+impl<K: Hash + Eq, V, T, const GEN_BITS: usize> SlabMesh<K, V, T, GEN_BITS>
+where
+    BitMarker<GEN_BITS>: HasGeneration,
+{
+    /// Reserve room for at least this many more vertices and tetrahedra.
+    pub fn reserve(&mut self, verts: usize, tetras: usize) {
+        self.verts.reserve(verts);
+        self.tetras.reserve(tetras);
+    }
+    /// Free as much unused memory as possible, without moving anything.
+    ///
+    /// [`Self::compact`] should be called first to free the empty slots left by removals.
+    pub fn shrink_to_fit(&mut self) {
+        self.verts.shrink_to_fit();
+        self.tetras.shrink_to_fit();
+        if let Some(stitch) = &mut self.stitch {
+            if let Some(verts) = &mut stitch.verts {
+                verts.shrink_to_fit();
+            }
+            stitch.faces.shrink_to_fit();
+        }
+    }
+}
 impl<K, V: VertexData, T, const GEN_BITS: usize> SlabMesh<K, V, T, GEN_BITS>
 where
     BitMarker<GEN_BITS>: HasGeneration,
@@ -51,6 +102,139 @@ where
                 });
     }
 }
+impl<K: SlabKey<GEN_BITS>, V: VertexData, T: TetraData<K>, const GEN_BITS: usize>
+    SlabMesh<K, V, T, GEN_BITS>
+where
+    BitMarker<GEN_BITS>: HasGeneration,
+{
+    /// Create a new, empty mesh with stitching enabled.
+    ///
+    /// Stitching only links faces whose vertices have the same IDs. Builders always add their own vertices, so shapes
+    /// appended separately never stitch to each other unless vertex merging is on too, as with
+    /// [`Self::with_vertex_merging`].
+    pub fn with_stitching() -> Self {
+        let mut this = Self::new();
+        this.stitch = Some(StitchIndex::default());
+        this
+    }
+    /// Create a new, empty mesh with stitching and vertex merging enabled.
+    pub fn with_vertex_merging() -> Self {
+        let mut this = Self::new();
+        this.stitch = Some(StitchIndex {
+            verts: Some(HashMap::new()),
+            faces: HashMap::new(),
+        });
+        this
+    }
+    /// Enable automatic stitching, or rebuild the index if it's already enabled.
+    ///
+    /// While stitching is enabled, any face of a new tetrahedron without a neighbor is linked to an existing face with
+    /// the same vertices. Vertex merging is kept if it was already enabled.
+    ///
+    /// Only new tetrahedra are stitched, faces that are already in the mesh aren't linked to each other.
+    pub fn enable_stitching(&mut self) {
+        self.build_stitch_index(self.is_merging_vertices());
+    }
+    /// Enable automatic stitching along with vertex merging, or rebuild the index if they're already enabled.
+    ///
+    /// While vertex merging is enabled, adding a vertex at the exact position of an existing one returns the existing
+    /// vertex instead of adding a new one. Builders always add their own vertices, so this is what lets
+    /// separately-built shapes that touch share faces, and join into a single connected volume when they're stitched.
+    ///
+    /// This is separate from stitching, since anything that adds a vertex expecting a new one, like
+    /// [`slice`](crate::slice::slice) copying the vertices on its plane, gets an existing one back instead.
+    pub fn enable_vertex_merging(&mut self) {
+        self.build_stitch_index(true);
+    }
+    fn build_stitch_index(&mut self, merge_verts: bool) {
+        let mut index = StitchIndex::default();
+        if merge_verts {
+            let verts = index.verts.insert(HashMap::new());
+            for (id, v) in self.verts() {
+                verts.entry(position_key(v.as_vec3())).or_insert(id);
+            }
+        }
+        for (id, tet) in self.tetras() {
+            for face in VertexIdx::VALS {
+                if tet.face(face).is_none() {
+                    index.faces.insert(tet.sorted_face(face), (id, face));
+                }
+            }
+        }
+        self.stitch = Some(index);
+    }
+    /// Get the most vertices the mesh can hold, which is how many indices fit in its keys.
+    pub fn max_verts() -> usize {
+        K::capacity(None)
+    }
+    /// Get the most tetrahedra the mesh can hold, which can be fewer than [`Self::max_verts`] when faces pack the keys
+    /// of neighbors along with other data.
+    pub fn max_tetras() -> usize {
+        K::capacity(T::max_key().map(|id| id.0))
+    }
+    /// Move every vertex and tetrahedron to the front of its slab, and free the empty slots they leave behind.
+    ///
+    /// This returns the new ID of everything that moved, and anything that isn't in the map kept its ID. Tetrahedra are
+    /// updated to use the new IDs, and so is the stitching index if it's enabled. See [`Slab::compact`] for how this
+    /// affects generations.
+    pub fn compact(&mut self) -> IdMap<K>
+    where
+        T: TetraDataMut<K>,
+    {
+        let verts = self
+            .verts
+            .compact()
+            .into_iter()
+            .map(|(old, new)| {
+                (
+                    VertexId(K::pack(old.index, old.generation)),
+                    VertexId(K::pack(new.index, new.generation)),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let tetras = self
+            .tetras
+            .compact()
+            .into_iter()
+            .map(|(old, new)| {
+                (
+                    TetraId(K::pack(old.index, old.generation)),
+                    TetraId(K::pack(new.index, new.generation)),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        if !verts.is_empty() || !tetras.is_empty() {
+            for tet in self.tetras.values_mut() {
+                for i in VertexIdx::VALS {
+                    if let Some(&v) = verts.get(&tet.vertex(i)) {
+                        tet.set_vertex(i, v);
+                    }
+                    if let Some((n, j)) = tet.face(i)
+                        && let Some(&n) = tetras.get(&n)
+                    {
+                        tet.set_face(i, Some((n, j)));
+                    }
+                }
+            }
+            if self.is_stitching() {
+                self.enable_stitching();
+            }
+        }
+        IdMap { verts, tetras }
+    }
+    /// Disable automatic stitching, and drop the index.
+    pub fn disable_stitching(&mut self) {
+        self.stitch = None;
+    }
+    /// Check if automatic stitching is enabled.
+    pub const fn is_stitching(&self) -> bool {
+        self.stitch.is_some()
+    }
+    /// Check if vertex merging is enabled, which also means stitching is.
+    pub const fn is_merging_vertices(&self) -> bool {
+        matches!(&self.stitch, Some(StitchIndex { verts: Some(_), .. }))
+    }
+}
 impl<K, V, T, const GEN_BITS: usize> Default for SlabMesh<K, V, T, GEN_BITS>
 where
     BitMarker<GEN_BITS>: HasGeneration,
@@ -148,37 +332,133 @@ where
         let (i, g) = id.0.unpack();
         self.tetras.get_mut(GenerationIndex::new(i, g))
     }
+    #[track_caller]
     fn add_vertex(&mut self, vert: Self::Vertex) -> VertexId<Self::Key> {
-        add_point(&mut self.bounds, vert.as_vec3());
-        let idx = self.verts.insert(vert);
-        VertexId(K::pack(idx.index, idx.generation))
+        // a closure would report its own location instead of the caller's
+        match self.try_add_vertex(vert) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
     }
+    #[track_caller]
     fn add_tetra(&mut self, tetra: Self::Tetra) -> TetraId<Self::Key> {
-        let adjs = VertexIdx::VALS.map(|v| (v, tetra.face(v)));
-        let idx = self.tetras.insert(tetra);
+        match self.try_add_tetra(tetra) {
+            Ok(id) => id,
+            Err(err) => panic!("{err}"),
+        }
+    }
+    fn try_add_vertex(
+        &mut self,
+        vert: Self::Vertex,
+    ) -> Result<VertexId<Self::Key>, AddError<Self::Vertex>> {
+        let pos = vert.as_vec3();
+        if let Some(verts) = self.stitch.as_ref().and_then(|s| s.verts.as_ref())
+            && let Some(&id) = verts.get(&position_key(pos))
+            && let (i, g) = id.0.unpack()
+            && self
+                .verts
+                .get(GenerationIndex::new(i, g))
+                .is_some_and(|v| v.as_vec3() == pos)
+        {
+            return Ok(id);
+        }
+        let idx = self
+            .verts
+            .try_insert(vert, Self::max_verts())
+            .map_err(|err| AddError {
+                value: err.value,
+                error: CapacityError::Vertices {
+                    capacity: err.capacity,
+                },
+            })?;
+        add_point(&mut self.bounds, pos);
+        let id = VertexId(K::pack(idx.index, idx.generation));
+        if let Some(verts) = self.stitch.as_mut().and_then(|s| s.verts.as_mut()) {
+            verts.insert(position_key(pos), id);
+        }
+        Ok(id)
+    }
+    fn try_add_tetra(
+        &mut self,
+        mut tetra: Self::Tetra,
+    ) -> Result<TetraId<Self::Key>, AddError<Self::Tetra>> {
+        // matching faces are only taken out of the index below, once the tetrahedron is in
+        let mut stitched = [false; 4];
+        if let Some(stitch) = &self.stitch {
+            for (v, stitched) in VertexIdx::VALS.into_iter().zip(&mut stitched) {
+                if tetra.face(v).is_none()
+                    && let Some(&adj) = stitch.faces.get(&tetra.sorted_face(v))
+                {
+                    tetra.set_face(v, Some(adj));
+                    *stitched = true;
+                }
+            }
+        }
+        let adjs = VertexIdx::VALS.map(|v| (v, tetra.face(v), tetra.sorted_face(v)));
+        let idx = match self.tetras.try_insert(tetra, Self::max_tetras()) {
+            Ok(idx) => idx,
+            Err(SlabFull {
+                value: mut tetra,
+                capacity,
+            }) => {
+                // give the tetrahedron back the way it was passed in
+                for (v, stitched) in VertexIdx::VALS.into_iter().zip(stitched) {
+                    if stitched {
+                        tetra.set_face(v, None);
+                    }
+                }
+                return Err(AddError {
+                    value: tetra,
+                    error: CapacityError::Tetras { capacity },
+                });
+            }
+        };
         let tet = TetraId(K::pack(idx.index, idx.generation));
-        for (v, adj) in adjs {
-            if let Some((n, i)) = adj
-                && let Some(t) = self.get_tetra_mut(n)
-            {
-                t.set_face(i, Some((tet, v)));
+        for (v, adj, key) in adjs {
+            if let Some((n, i)) = adj {
+                if let Some(t) = self.get_tetra_mut(n) {
+                    t.set_face(i, Some((tet, v)));
+                }
+                if let Some(stitch) = &mut self.stitch {
+                    stitch.faces.remove(&key);
+                }
+            } else if let Some(stitch) = &mut self.stitch {
+                stitch.faces.insert(key, (tet, v));
             }
         }
-        tet
+        Ok(tet)
     }
     fn remove_vertex(&mut self, id: VertexId<Self::Key>) -> Option<Self::Vertex> {
         let (i, g) = id.0.unpack();
-        self.verts.remove(GenerationIndex::new(i, g))
+        let vert = self.verts.remove(GenerationIndex::new(i, g));
+        if let Some(verts) = self.stitch.as_mut().and_then(|s| s.verts.as_mut())
+            && let Some(vert) = &vert
+        {
+            let key = position_key(vert.as_vec3());
+            if verts.get(&key) == Some(&id) {
+                verts.remove(&key);
+            }
+        }
+        vert
     }
     fn remove_tetra(&mut self, id: TetraId<Self::Key>) -> Option<Self::Tetra> {
         let (i, g) = id.0.unpack();
         let tet = self.tetras.remove(GenerationIndex::new(i, g));
         if let Some(tet) = &tet {
-            for adj in VertexIdx::VALS.map(|v| tet.face(v)) {
-                if let Some((n, i)) = adj
-                    && let Some(t) = self.get_tetra_mut(n)
+            for v in VertexIdx::VALS {
+                let key = tet.sorted_face(v);
+                if let Some((n, i)) = tet.face(v) {
+                    if let Some(t) = self.get_tetra_mut(n) {
+                        t.set_face(i, None);
+                        if let Some(stitch) = &mut self.stitch {
+                            stitch.faces.insert(key, (n, i));
+                        }
+                    }
+                } else if let Some(stitch) = &mut self.stitch
+                    && stitch.faces.get(&key) == Some(&(id, v))
                 {
-                    t.set_face(i, None);
+                    // another tetrahedron can have the same open face, and then the entry is its own
+                    stitch.faces.remove(&key);
                 }
             }
         }
@@ -207,6 +487,13 @@ where
             .next()
             .map(|(k, v)| (VertexId(K::pack(k.index, k.generation)), v))
     }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+impl<'a, K: SlabKey<BITS>, V: 'a, const BITS: usize> ExactSizeIterator for VertsIter<'a, K, V, BITS> where
+    BitMarker<BITS>: HasGeneration
+{
 }
 
 /// Iterator type for tetrahedra in a [`SlabMesh`]
@@ -230,21 +517,35 @@ where
             .next()
             .map(|(k, v)| (TetraId(K::pack(k.index, k.generation)), v))
     }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+impl<'a, K: SlabKey<BITS>, T: 'a, const BITS: usize> ExactSizeIterator
+    for TetrasIter<'a, K, T, BITS>
+where
+    BitMarker<BITS>: HasGeneration,
+{
 }
 
 /// A packed form of an index and generation count.
 ///
 /// This is implemented for all integers, with no generation being specified, and `(integer, G)` tuples with the generation
-/// being the second element.
+/// being the second element. It's also implemented for [`NonZero`] integers, which store the index plus one so that
+/// wrapping a key in an `Option` doesn't make it any bigger.
 #[diagnostic::on_unimplemented(
     message = "`{Self}` is not a valid key for a {BITS}-bit generation counter"
 )]
-pub trait SlabKey<const BITS: usize>: Copy + Hash + Eq + Debug
+pub trait SlabKey<const BITS: usize>: Copy + Hash + Eq + Ord + Debug
 where
     BitMarker<BITS>: HasGeneration,
 {
     fn pack(index: usize, generation: <BitMarker<BITS> as HasGeneration>::Generation) -> Self;
     fn unpack(self) -> (usize, <BitMarker<BITS> as HasGeneration>::Generation);
+    /// Get the number of indices that can be packed with every generation, into keys no larger than `max`.
+    ///
+    /// Without a maximum, this is limited by the largest value of `Self`, and it saturates at `usize::MAX`.
+    fn capacity(max: Option<Self>) -> usize;
 }
 macro_rules! impl_slab_key {
     ($($int:ty)*) => {
@@ -257,6 +558,9 @@ macro_rules! impl_slab_key {
                 fn unpack(self) -> (usize, ()) {
                     (self as _, ())
                 }
+                fn capacity(max: Option<Self>) -> usize {
+                    usize::try_from(max.unwrap_or(<$int>::MAX)).map_or(usize::MAX, |max| max.saturating_add(1))
+                }
             }
             #[diagnostic::do_not_recommend]
             impl<const BITS: usize> SlabKey<BITS> for $int where BitMarker<BITS>: HasIntegerSize<Integer: TryFrom<Self> + Into<Self>> {
@@ -266,10 +570,18 @@ macro_rules! impl_slab_key {
                 fn unpack(self) -> (usize, <BitMarker<BITS> as HasIntegerSize>::Integer) {
                     ((self >> BITS) as _, (self & ((1 << BITS) - 1)).try_into().unwrap_or_else(|_| unreachable!()))
                 }
+                fn capacity(max: Option<Self>) -> usize {
+                    let max = max.unwrap_or(<$int>::MAX);
+                    let mask = <$int>::MAX >> (<$int>::BITS - BITS as u32);
+                    // the index in the top bits of the maximum only fits if its last generation does too
+                    let last = usize::from(max & mask == mask);
+                    usize::try_from(max.checked_shr(BITS as u32).unwrap_or(0))
+                        .map_or(usize::MAX, |count| count.saturating_add(last))
+                }
             }
             impl<const BITS: usize> SlabKey<BITS> for ($int, <BitMarker<BITS> as HasGeneration>::Generation)
             where
-                BitMarker<BITS>: HasGeneration<Generation: Hash + Eq + Debug>,
+                BitMarker<BITS>: HasGeneration<Generation: Hash + Eq + Ord + Debug>,
             {
                 fn pack(index: usize, generation: <BitMarker<BITS> as HasGeneration>::Generation) -> Self {
                     (index as _, generation)
@@ -277,15 +589,78 @@ macro_rules! impl_slab_key {
                 fn unpack(self) -> (usize, <BitMarker<BITS> as HasGeneration>::Generation) {
                     (self.0 as _, self.1)
                 }
+                fn capacity(max: Option<Self>) -> usize {
+                    match max {
+                        // only some generations of the largest index might fit, so it's left out
+                        Some((index, _)) => usize::try_from(index).unwrap_or(usize::MAX),
+                        None => usize::try_from(<$int>::MAX).map_or(usize::MAX, |max| max.saturating_add(1)),
+                    }
+                }
             }
         )*
     };
 }
 impl_slab_key!(u8 u16 u32 u64 usize);
+macro_rules! impl_nonzero_slab_key {
+    ($($int:ty)*) => {
+        $(
+            #[diagnostic::do_not_recommend]
+            impl SlabKey<0> for NonZero<$int> {
+                fn pack(index: usize, _generation: ()) -> Self {
+                    Self::MIN.saturating_add(index as _)
+                }
+                fn unpack(self) -> (usize, ()) {
+                    (self.get() as usize - 1, ())
+                }
+                fn capacity(max: Option<Self>) -> usize {
+                    usize::try_from(max.unwrap_or(Self::MAX).get()).unwrap_or(usize::MAX)
+                }
+            }
+            #[diagnostic::do_not_recommend]
+            impl<const BITS: usize> SlabKey<BITS> for NonZero<$int> where BitMarker<BITS>: HasIntegerSize<Integer: TryFrom<$int> + Into<$int>> {
+                fn pack(index: usize, generation: <BitMarker<BITS> as HasIntegerSize>::Integer) -> Self {
+                    // this is only zero for indices that are too big to fit, which the capacity rules out
+                    Self::new(((index as $int).wrapping_add(1) << BITS) | generation.into()).unwrap_or(Self::MAX)
+                }
+                fn unpack(self) -> (usize, <BitMarker<BITS> as HasIntegerSize>::Integer) {
+                    let key = self.get();
+                    (((key >> BITS) as usize).wrapping_sub(1), (key & ((1 << BITS) - 1)).try_into().unwrap_or_else(|_| unreachable!()))
+                }
+                fn capacity(max: Option<Self>) -> usize {
+                    let max = max.unwrap_or(Self::MAX).get();
+                    let mask = <$int>::MAX >> (<$int>::BITS - BITS as u32);
+                    let last = usize::from(max & mask == mask);
+                    // same as for plain integers, except the first index is taken by zero
+                    usize::try_from(max.checked_shr(BITS as u32).unwrap_or(0))
+                        .map_or(usize::MAX, |count| count.saturating_add(last).saturating_sub(1))
+                }
+            }
+            impl<const BITS: usize> SlabKey<BITS> for (NonZero<$int>, <BitMarker<BITS> as HasGeneration>::Generation)
+            where
+                BitMarker<BITS>: HasGeneration<Generation: Hash + Eq + Ord + Debug>,
+            {
+                fn pack(index: usize, generation: <BitMarker<BITS> as HasGeneration>::Generation) -> Self {
+                    (NonZero::<$int>::MIN.saturating_add(index as _), generation)
+                }
+                fn unpack(self) -> (usize, <BitMarker<BITS> as HasGeneration>::Generation) {
+                    (self.0.get() as usize - 1, self.1)
+                }
+                fn capacity(max: Option<Self>) -> usize {
+                    match max {
+                        Some((index, _)) => usize::try_from(index.get() - 1).unwrap_or(usize::MAX),
+                        None => usize::try_from(NonZero::<$int>::MAX.get()).unwrap_or(usize::MAX),
+                    }
+                }
+            }
+        )*
+    };
+}
+impl_nonzero_slab_key!(u8 u16 u32 u64 usize);
 
 /// A mesh that uses a packed layout.
 ///
-/// The maximum number of vertices is `K::MAX >> GEN_BITS` and the maximum number of tetrahedra is `K::MAX >> (GEN_BITS + 2)`
+/// The maximum number of vertices is about `K::MAX >> GEN_BITS` and the maximum number of tetrahedra is about
+/// `K::MAX >> (GEN_BITS + 2)`, which are given exactly by [`SlabMesh::max_verts`] and [`SlabMesh::max_tetras`].
 pub type DefaultPackedMesh<K, V = (), T = (), const GEN_BITS: usize = 0> =
     SlabMesh<K, Vertex<V>, Tetra<K, PackedFace<K>, T>, GEN_BITS>;
 
@@ -300,4 +675,121 @@ mod tests {
     struct AssertValidPacked2(AssertMesh<DefaultPackedMesh<u16, (), (), 9>>);
     // more generation bits than can fit in a u8, this is a compile error
     // struct AssertInvliadPacked(AssertMesh<DefaultPackedMesh<u8, (), (), 20>>);
+    type BasicMesh<K, const GEN_BITS: usize = 0> = SlabMesh<K, Vertex, Tetra<K>, GEN_BITS>;
+
+    struct AssertValidNonZero1(AssertMesh<BasicMesh<NonZero<u32>, 4>>);
+    struct AssertValidNonZero2(AssertMesh<BasicMesh<NonZero<u16>>>);
+
+    // non-zero keys leave room for `None`, so optional IDs are as small as the keys themselves
+    const _: () = assert!(size_of::<Option<VertexId<NonZero<u32>>>>() == size_of::<u32>());
+
+    #[test]
+    fn nonzero_keys_are_offset_by_one() {
+        let nz = |n| NonZero::new(n).unwrap();
+        assert_eq!(<NonZero<u8> as SlabKey<0>>::pack(0, ()), nz(1));
+        assert_eq!(<NonZero<u8> as SlabKey<0>>::pack(254, ()), nz(255));
+        assert_eq!(<NonZero<u8> as SlabKey<0>>::unpack(nz(1)), (0, ()));
+        assert_eq!(<NonZero<u8> as SlabKey<0>>::unpack(nz(255)), (254, ()));
+        assert_eq!(<NonZero<u8> as SlabKey<0>>::capacity(None), 255);
+
+        assert_eq!(<NonZero<u8> as SlabKey<4>>::pack(0, 15), nz(0x1f));
+        assert_eq!(<NonZero<u8> as SlabKey<4>>::unpack(nz(0x1f)), (0, 15));
+        assert_eq!(<NonZero<u8> as SlabKey<4>>::unpack(nz(0xf0)), (14, 0));
+        assert_eq!(<NonZero<u8> as SlabKey<4>>::capacity(None), 15);
+
+        type Pair = (NonZero<u16>, u8);
+        assert_eq!(
+            <Pair as SlabKey<8>>::pack(0, 3),
+            (NonZero::new(1).unwrap(), 3)
+        );
+        assert_eq!(
+            <Pair as SlabKey<8>>::unpack((NonZero::new(1).unwrap(), 3)),
+            (0, 3)
+        );
+    }
+
+    #[test]
+    fn nonzero_keys_build_valid_meshes() {
+        use crate::builder::{Cuboid, Hexahedron, MeshBuilder};
+        use crate::validate::validate;
+
+        fn check<M: TetraMeshMut<Tetra: Clone> + Default>()
+        where
+            M::Vertex: From<Vec3>,
+            M::Tetra: From<TetraPrimitive<M::Key>>,
+        {
+            let mesh = Cuboid::UNIT_CUBE.build::<M>();
+            let report = validate(&mesh);
+            assert!(report.is_valid(), "{report}");
+            let mut mesh = Hexahedron::UNIT_CUBE.build::<M>();
+            // removing and adding back reuses the first slot, which has to come back as a valid key
+            let (id, tet) = mesh.tetras().next().map(|(id, t)| (id, t.clone())).unwrap();
+            mesh.remove_tetra(id);
+            let readded = mesh.add_tetra(tet);
+            assert!(mesh.get_tetra(readded).is_some());
+            let report = validate(&mesh);
+            assert!(report.is_valid(), "{report}");
+        }
+        check::<BasicMesh<NonZero<u32>>>();
+        check::<BasicMesh<NonZero<u16>, 4>>();
+        check::<BasicMesh<(NonZero<u16>, u8), 8>>();
+    }
+
+    #[test]
+    fn nonzero_keys_fill_every_index() {
+        let mut mesh = BasicMesh::<NonZero<u8>>::new();
+        for i in 0..255 {
+            mesh.add_vertex(Vertex::from(Vec3::splat(i as f32)));
+        }
+        let last = Vertex::from(Vec3::NEG_ONE);
+        let err = mesh.try_add_vertex(last).unwrap_err();
+        assert_eq!(err.value, last);
+        assert_eq!(err.error, CapacityError::Vertices { capacity: 255 });
+        assert_eq!(mesh.verts().count(), 255);
+    }
+
+    #[test]
+    fn packed_u8_capacity() {
+        type Mesh = DefaultPackedMesh<u8>;
+        assert_eq!(<u8 as SlabKey<0>>::capacity(None), 256);
+        assert_eq!(Mesh::max_verts(), 256);
+        assert_eq!(Mesh::max_tetras(), 63);
+
+        let mut mesh = Mesh::new();
+        let verts = (0..256)
+            .map(|i| {
+                mesh.try_add_vertex(Vertex::from(Vec3::splat(i as f32)))
+                    .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let extra = Vertex::from(Vec3::NEG_ONE);
+        let err = mesh.try_add_vertex(extra).unwrap_err();
+        assert_eq!(err.value, extra);
+        assert_eq!(err.error, CapacityError::Vertices { capacity: 256 });
+        // nothing aliased the first vertex
+        assert_eq!(mesh.get_vertex(verts[0]).unwrap().pos, Vec3::ZERO);
+
+        let tetra =
+            |i: usize| PackedTetra::from([0, 1, 2, 3].map(|k| (verts[(i + k) % 256], None)));
+        let tetras = (0..63)
+            .map(|i| mesh.try_add_tetra(tetra(i)).unwrap())
+            .collect::<Vec<_>>();
+        let err = mesh.try_add_tetra(tetra(63)).unwrap_err();
+        assert_eq!(err.value, tetra(63));
+        assert_eq!(err.error, CapacityError::Tetras { capacity: 63 });
+        assert_eq!(mesh.tetras().count(), 63);
+
+        // freeing a slot makes room again
+        mesh.remove_tetra(tetras[10]);
+        assert!(mesh.try_add_tetra(tetra(63)).is_ok());
+    }
+
+    #[test]
+    #[should_panic = "the mesh is out of vertex keys, since it can only hold 256 vertices"]
+    fn add_past_capacity_panics() {
+        let mut mesh = DefaultPackedMesh::<u8>::new();
+        for i in 0..257 {
+            mesh.add_vertex(Vertex::from(Vec3::splat(i as f32)));
+        }
+    }
 }
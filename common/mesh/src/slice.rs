@@ -0,0 +1,353 @@
+//! Slicing meshes with planes.
+//!
+//! Every tetrahedron crossed by the plane is cut into pieces on either side of it, with new vertices where its edges
+//! cross the plane. Faces on the plane are unlinked, so each side ends up with its own boundary along the cut, which
+//! shows up in the surface from [`TetraMesh::append_primitive_surface`] like any other boundary.
+
+use crate::components::{IdMap, split_groups};
+use crate::edit::{Edit, attach, detach};
+use crate::geometry::{bounds_of, signed_volume};
+use crate::region::pull;
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::HashMap;
+
+/// How close vertices have to be to the plane to be moved onto it, as a fraction of the size of the mesh.
+///
+/// Cutting an edge right next to one of its ends would leave behind tetrahedra that are too thin to be useful.
+const SNAP_FRACTION: f32 = 1e-4;
+
+/// Which side of a plane a tetrahedron is on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    /// Behind the plane, against its normal.
+    Below,
+    /// In front of the plane, along its normal.
+    Above,
+}
+
+/// The result of slicing a mesh in place with [`slice`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slice<K> {
+    /// The tetrahedra that were cut, and the pieces they were cut into.
+    pub edit: Edit<K>,
+    /// The vertices added where edges crossed the plane, and the copies of the ones on it.
+    pub added_verts: Vec<VertexId<K>>,
+    /// The tetrahedra below the plane.
+    pub below: Vec<TetraId<K>>,
+    /// The tetrahedra above the plane.
+    pub above: Vec<TetraId<K>>,
+}
+impl<K> Slice<K> {
+    /// Get the tetrahedra on one side of the plane.
+    pub fn side(&self, side: Side) -> &[TetraId<K>] {
+        match side {
+            Side::Below => &self.below,
+            Side::Above => &self.above,
+        }
+    }
+}
+
+/// Clip a polygon to the part on one side of the plane, keeping the vertices on the plane.
+fn clip<K: Copy + Ord + std::hash::Hash>(
+    poly: &[VertexId<K>],
+    sign: f32,
+    dist: &HashMap<VertexId<K>, f32>,
+    cuts: &HashMap<[VertexId<K>; 2], VertexId<K>>,
+) -> Vec<VertexId<K>> {
+    let mut out = Vec::with_capacity(poly.len() + 1);
+    for (k, &u) in poly.iter().enumerate() {
+        let v = poly[(k + 1) % poly.len()];
+        let [du, dv] = [u, v].map(|v| dist.get(&v).copied().unwrap_or(0.0));
+        if du * sign >= 0.0 {
+            out.push(u);
+        }
+        if du * dv < 0.0
+            && let Some(&cut) = cuts.get(&[u.min(v), u.max(v)])
+        {
+            out.push(cut);
+        }
+    }
+    out
+}
+
+/// Slice a mesh in place with a plane, through a point and with a normal.
+///
+/// Tetrahedra crossed by the plane are cut into pieces on either side of it, which keep the data of the tetrahedron
+/// they came from. Afterwards, every tetrahedron is on one side of the plane, and the two sides don't share any faces
+/// or vertices, since the side above gets its own copies of the vertices on the plane. Vertices very close to the plane
+/// are moved onto it, so that nothing is cut into slivers.
+pub fn slice<M: TetraMeshMut>(mesh: &mut M, point: Vec3, normal: Vec3) -> Slice<M::Key>
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let normal = normal.normalize_or_zero();
+    let [min, max] = bounds_of(mesh.verts().map(|(_, v)| v.as_vec3()));
+    let snap = (max - min).length() * SNAP_FRACTION;
+    let mut dist = HashMap::new();
+    let mut snapped = Vec::new();
+    for (id, vert) in mesh.verts() {
+        let pos = vert.as_vec3();
+        let d = (pos - point).dot(normal);
+        if d != 0.0 && d.abs() <= snap {
+            snapped.push((id, pos - normal * d));
+            dist.insert(id, 0.0);
+        } else {
+            dist.insert(id, d);
+        }
+    }
+    for (id, pos) in snapped {
+        if let Some(v) = mesh.get_vertex_mut(id) {
+            v.set_vec3(pos);
+        }
+    }
+
+    let mut sides = HashMap::new();
+    let mut old = Vec::new();
+    for (id, tet) in mesh.tetras() {
+        let ds = VertexIdx::VALS.map(|i| dist.get(&tet.vertex(i)).copied().unwrap_or(0.0));
+        let above = ds.iter().any(|&d| d > 0.0);
+        let below = ds.iter().any(|&d| d < 0.0);
+        match (below, above) {
+            (true, true) => old.push(id),
+            (false, true) => {
+                sides.insert(id, Side::Above);
+            }
+            _ => {
+                sides.insert(id, Side::Below);
+            }
+        }
+    }
+
+    // add a vertex on each edge crossing the plane, shared by every tetrahedron around it
+    let mut cuts = HashMap::new();
+    let mut added_verts = Vec::new();
+    for &id in &old {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+        for (k, &u) in verts.iter().enumerate() {
+            for &v in &verts[k + 1..] {
+                let key = [u.min(v), u.max(v)];
+                let [du, dv] = key.map(|v| dist.get(&v).copied().unwrap_or(0.0));
+                if du * dv >= 0.0 || cuts.contains_key(&key) {
+                    continue;
+                }
+                let (Some(a), Some(b)) = (mesh.get_vertex(key[0]), mesh.get_vertex(key[1])) else {
+                    continue;
+                };
+                let pos = a.as_vec3().lerp(b.as_vec3(), du / (du - dv));
+                let mut vert = a.clone();
+                vert.set_vec3(pos);
+                cuts.insert(key, mesh.add_vertex(vert));
+            }
+        }
+    }
+    for &id in cuts.values() {
+        dist.insert(id, 0.0);
+        added_verts.push(id);
+    }
+    added_verts.sort_unstable();
+
+    // fill the part of each cut tetrahedron on either side of the plane
+    let (x_axis, y_axis) = normal.any_orthonormal_pair();
+    let mut pieces = Vec::new();
+    for (idx, &id) in old.iter().enumerate() {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+        for (side, sign) in [(Side::Below, -1.0), (Side::Above, 1.0)] {
+            let mut faces = VertexIdx::VALS
+                .map(|i| clip(&i.others().map(|i| *i.in_arr(&verts)), sign, &dist, &cuts))
+                .into_iter()
+                .filter(|f| f.len() >= 3)
+                .collect::<Vec<_>>();
+            // the cap on the plane, sorted by angle around its center
+            let mut cap = faces
+                .iter()
+                .flatten()
+                .copied()
+                .filter(|v| dist.get(v) == Some(&0.0))
+                .collect::<Vec<_>>();
+            cap.sort_unstable();
+            cap.dedup();
+            let positions = cap
+                .iter()
+                .filter_map(|&v| mesh.get_vertex(v).map(|v| v.as_vec3()))
+                .collect::<Vec<_>>();
+            let center = positions.iter().sum::<Vec3>() / positions.len().max(1) as f32;
+            let mut order = cap.into_iter().zip(positions).collect::<Vec<_>>();
+            order.sort_by(|(_, a), (_, b)| {
+                let angle = |p: &Vec3| (*p - center).dot(y_axis).atan2((*p - center).dot(x_axis));
+                angle(a).total_cmp(&angle(b))
+            });
+            faces.push(order.into_iter().map(|(v, _)| v).collect());
+            let faces = faces.iter().map(Vec::as_slice).collect::<Vec<_>>();
+            pieces.extend(pull(&faces).into_iter().map(|verts| (idx, side, verts)));
+        }
+    }
+
+    let (open, values) = detach(mesh, &old);
+    let mut piece_sides = Vec::with_capacity(pieces.len());
+    let new = pieces
+        .into_iter()
+        .filter_map(|(idx, side, mut verts)| {
+            let points = verts.map(|v| mesh.get_vertex(v).map(|v| v.as_vec3()));
+            let volume = signed_volume(points.map(|p| p.unwrap_or_default()));
+            if volume == 0.0 || points.iter().any(Option::is_none) {
+                return None;
+            }
+            if volume < 0.0 {
+                verts.swap(2, 3);
+            }
+            let mut tet = values.get(idx)?.clone();
+            for (i, v) in VertexIdx::VALS.into_iter().zip(verts) {
+                tet.set_vertex(i, v);
+                tet.set_face(i, None);
+            }
+            piece_sides.push(side);
+            Some(tet)
+        })
+        .collect::<Vec<_>>();
+    let created = attach(mesh, open, new);
+    sides.extend(created.iter().copied().zip(piece_sides));
+
+    // unlink every face on the plane, including the ones between the two halves of a cut tetrahedron
+    let mut unlink = Vec::new();
+    for (id, tet) in mesh.tetras() {
+        for face in VertexIdx::VALS {
+            if let Some((n, j)) = tet.face(face)
+                && sides.get(&n) != sides.get(&id)
+            {
+                unlink.push((id, face));
+                unlink.push((n, j));
+            }
+        }
+    }
+    for (id, face) in unlink {
+        if let Some(tet) = mesh.get_tetra_mut(id) {
+            tet.set_face(face, None);
+        }
+    }
+
+    let mut below = Vec::new();
+    let mut above = Vec::new();
+    for (id, _) in mesh.tetras() {
+        match sides.get(&id) {
+            Some(Side::Above) => above.push(id),
+            _ => below.push(id),
+        }
+    }
+
+    // give the side above its own copies of the vertices on the plane, so the sides don't share anything
+    let mut copies = HashMap::new();
+    for &id in &above {
+        for i in VertexIdx::VALS {
+            let Some(v) = mesh.get_tetra(id).map(|t| t.vertex(i)) else {
+                continue;
+            };
+            if dist.get(&v) != Some(&0.0) {
+                continue;
+            }
+            let copy = match copies.get(&v) {
+                Some(&copy) => copy,
+                None => {
+                    let Some(vert) = mesh.get_vertex(v).cloned() else {
+                        continue;
+                    };
+                    let copy = mesh.add_vertex(vert);
+                    copies.insert(v, copy);
+                    added_verts.push(copy);
+                    copy
+                }
+            };
+            if let Some(tet) = mesh.get_tetra_mut(id) {
+                tet.set_vertex(i, copy);
+            }
+        }
+    }
+    Slice {
+        edit: Edit {
+            created,
+            removed: old,
+        },
+        added_verts,
+        below,
+        above,
+    }
+}
+
+/// Slice a mesh with a plane, moving each side into its own new mesh.
+///
+/// The pieces are returned in the order of [`Side`], below and then above, along with how their IDs map to the ones
+/// they had in the original mesh after slicing.
+pub fn slice_apart<M: TetraMeshMut + Default>(
+    mesh: &mut M,
+    point: Vec3,
+    normal: Vec3,
+) -> [(M, IdMap<M::Key>); 2]
+where
+    M::Vertex: Clone,
+    M::Tetra: Clone,
+{
+    let Slice { below, above, .. } = slice(mesh, point, normal);
+    let mut pieces = split_groups(mesh, &[below, above]).into_iter();
+    let mut next = || {
+        pieces.next().unwrap_or_else(|| {
+            (
+                M::default(),
+                IdMap {
+                    verts: HashMap::new(),
+                    tetras: HashMap::new(),
+                },
+            )
+        })
+    };
+    [next(), next()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::SlabMesh;
+    use crate::test_util::lattice;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    #[test]
+    fn slice_leaves_no_cracks() {
+        let planes = [
+            (Vec3::splat(0.5), Vec3::new(1.0, 2.0, 3.0)),
+            (Vec3::new(0.3, 0.6, 0.4), Vec3::new(-0.7, 0.2, 0.5)),
+            (Vec3::new(0.55, 0.45, 0.5), Vec3::new(0.1, -1.0, 0.3)),
+        ];
+        for (point, normal) in planes {
+            let mut mesh: Mesh = lattice(5);
+            slice(&mut mesh, point, normal);
+            let normal = normal.normalize();
+            for (id, tet) in mesh.tetras() {
+                for i in VertexIdx::VALS {
+                    if tet.face(i).is_some() {
+                        continue;
+                    }
+                    let points = i
+                        .others()
+                        .map(|j| mesh.get_vertex(tet.vertex(j)).unwrap().pos);
+                    let on_plane = points.iter().all(|&p| (p - point).dot(normal).abs() < 1e-4);
+                    let on_boundary = (0..3).any(|axis| {
+                        [0.0, 1.0]
+                            .into_iter()
+                            .any(|c| points.iter().all(|p| (p[axis] - c).abs() < 1e-4))
+                    });
+                    assert!(
+                        on_plane || on_boundary,
+                        "face {i:?} of {id:?} is unlinked inside the mesh: {points:?}"
+                    );
+                }
+            }
+        }
+    }
+}
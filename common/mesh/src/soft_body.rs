@@ -0,0 +1,515 @@
+//! Soft body simulation with the finite element method.
+//!
+//! Each tetrahedron is a linear elastic element with the rotation factored out, which is known as corotated linear
+//! elasticity. It stays stable when elements rotate a lot, which plain linear elasticity doesn't, while being cheap
+//! enough to run every frame. The elements are stepped with XPBD: the energy of each element is split into a constraint
+//! on its change in shape and one on its change in volume, which are solved one element at a time over a number of
+//! substeps. This stays stable with any timestep, and unlike plain position based dynamics, the stiffness of a material
+//! doesn't depend on the timestep.
+//!
+//! The shape a mesh has when it's first stepped is its rest shape. The stiffness of each tetrahedron comes from its data
+//! through [`Elasticity`], and its mass through [`Density`].
+
+use crate::ecs::DynMesh;
+use crate::mass::Density;
+use crate::traits::*;
+use bevy_ecs::system::{Query, Res};
+use bevy_math::{Mat3, Quat, Vec3};
+use bevy_time::Time;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// The number of iterations used to update the rotation of each element, starting from the one in the last substep.
+const ROTATION_ITERATIONS: usize = 2;
+/// The highest Poisson ratio that's used, since the volume constraint gets infinitely stiff at 0.5.
+const MAX_POISSON_RATIO: f32 = 0.49;
+
+/// Something with elastic properties, used for the tetrahedra of a [`SoftBody`].
+///
+/// This is implemented for [`Tetra`] when its data is elastic, so different parts of a body can be made of different
+/// materials.
+pub trait Elasticity {
+    /// Get the Young's modulus, which is how much stress it takes to stretch the material.
+    fn young_modulus(&self) -> f32;
+    /// Get the Poisson ratio, which is how much the material tries to keep its volume, between 0 and 0.5.
+    fn poisson_ratio(&self) -> f32;
+}
+/// Without any data, everything is soft enough to wobble, but stiff enough that something about a unit across holds its
+/// shape under its own weight, with the default density of 1.
+impl Elasticity for () {
+    #[inline(always)]
+    fn young_modulus(&self) -> f32 {
+        1000.0
+    }
+    #[inline(always)]
+    fn poisson_ratio(&self) -> f32 {
+        0.3
+    }
+}
+impl<K, F, T: Elasticity> Elasticity for Tetra<K, F, T> {
+    #[inline(always)]
+    fn young_modulus(&self) -> f32 {
+        self.data.young_modulus()
+    }
+    #[inline(always)]
+    fn poisson_ratio(&self) -> f32 {
+        self.data.poisson_ratio()
+    }
+}
+
+/// A material for soft bodies, with a density and elastic properties.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ElasticMaterial {
+    pub density: f32,
+    pub young_modulus: f32,
+    pub poisson_ratio: f32,
+}
+impl Default for ElasticMaterial {
+    fn default() -> Self {
+        Self {
+            density: ().density(),
+            young_modulus: ().young_modulus(),
+            poisson_ratio: ().poisson_ratio(),
+        }
+    }
+}
+impl Density for ElasticMaterial {
+    #[inline(always)]
+    fn density(&self) -> f32 {
+        self.density
+    }
+}
+impl Elasticity for ElasticMaterial {
+    #[inline(always)]
+    fn young_modulus(&self) -> f32 {
+        self.young_modulus
+    }
+    #[inline(always)]
+    fn poisson_ratio(&self) -> f32 {
+        self.poisson_ratio
+    }
+}
+
+/// Options for a [`SoftBody`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SoftBodyOptions {
+    /// The acceleration applied to every vertex.
+    pub gravity: Vec3,
+    /// The number of substeps each step is split into.
+    ///
+    /// More substeps make stiff materials behave more stiffly, at the cost of more time.
+    pub substeps: usize,
+    /// How quickly velocities decay, as a fraction per second.
+    pub damping: f32,
+}
+impl Default for SoftBodyOptions {
+    fn default() -> Self {
+        Self {
+            gravity: Vec3::new(0.0, -9.81, 0.0),
+            substeps: 10,
+            damping: 0.0,
+        }
+    }
+}
+
+/// The rest shape and material of a tetrahedron.
+#[derive(Debug, Clone, Copy)]
+struct Element {
+    /// The indices of its corners.
+    corners: [usize; 4],
+    /// The inverse of the matrix of its edges from the first corner, at rest.
+    inv_rest: Mat3,
+    volume: f32,
+    /// The Lamé parameters, for shear and for volume.
+    mu: f32,
+    lambda: f32,
+    /// The rotation from its rest shape, which is kept between steps to get a head start on finding the next one.
+    rotation: Quat,
+}
+
+/// The state of the simulation, which is built when the body is first stepped.
+#[derive(Debug, Clone)]
+struct State<K> {
+    verts: Vec<VertexId<K>>,
+    indices: HashMap<VertexId<K>, usize>,
+    mass: Vec<f32>,
+    velocity: Vec<Vec3>,
+    elements: Vec<Element>,
+    /// Every tetrahedron in the mesh when this was built, along with its corners.
+    tetras: Vec<(TetraId<K>, [VertexId<K>; 4])>,
+}
+impl<K: Copy + Eq> State<K> {
+    /// Check if a mesh still has exactly the vertices and tetrahedra this was built from.
+    ///
+    /// Comparing counts isn't enough, since a tetrahedron can be replaced by another one, or removed and added back
+    /// with a different corner, without changing them.
+    fn matches<M: TetraMesh<Key = K>>(&self, mesh: &M) -> bool {
+        self.verts.len() == mesh.verts().count()
+            && self.tetras.len() == mesh.tetras().count()
+            && self.verts.iter().all(|&v| mesh.get_vertex(v).is_some())
+            && self.tetras.iter().all(|&(id, corners)| {
+                mesh.get_tetra(id)
+                    .is_some_and(|t| VertexIdx::VALS.map(|i| t.vertex(i)) == corners)
+            })
+    }
+}
+
+/// A mesh being simulated as a soft body.
+///
+/// As a component, this is stepped by [`step_soft_bodies`] along with the [`DynMesh`] on the same entity. It can also
+/// be stepped directly with [`SoftBody::step`].
+#[derive(Debug, Clone, bevy_ecs_macros::Component)]
+pub struct SoftBody<K: Send + Sync + 'static> {
+    pub options: SoftBodyOptions,
+    pinned: HashSet<VertexId<K>>,
+    state: Option<State<K>>,
+}
+impl<K: Copy + Hash + Eq + Send + Sync + 'static> SoftBody<K> {
+    /// Create a soft body that takes its rest shape from the mesh the first time it's stepped.
+    pub fn new(options: SoftBodyOptions) -> Self {
+        Self {
+            options,
+            pinned: HashSet::new(),
+            state: None,
+        }
+    }
+    /// Pin a vertex in place, so the simulation never moves it.
+    ///
+    /// Pinned vertices can still be moved by changing the mesh, and the rest of the body follows them.
+    pub fn pin(&mut self, vertex: VertexId<K>) {
+        self.pinned.insert(vertex);
+        if let Some(state) = &mut self.state
+            && let Some(&i) = state.indices.get(&vertex)
+        {
+            state.velocity[i] = Vec3::ZERO;
+        }
+    }
+    /// Unpin a vertex, letting the simulation move it again.
+    pub fn unpin(&mut self, vertex: VertexId<K>) {
+        self.pinned.remove(&vertex);
+    }
+    /// Check if a vertex is pinned.
+    pub fn is_pinned(&self, vertex: VertexId<K>) -> bool {
+        self.pinned.contains(&vertex)
+    }
+    /// Get the velocity of a vertex, if the body has been stepped.
+    pub fn velocity(&self, vertex: VertexId<K>) -> Option<Vec3> {
+        let state = self.state.as_ref()?;
+        Some(state.velocity[*state.indices.get(&vertex)?])
+    }
+    /// Forget the rest shape and the velocities, so the next step starts over from the shape the mesh has then.
+    ///
+    /// This happens automatically when tetrahedra or vertices are added, removed, or replaced.
+    pub fn reset(&mut self) {
+        self.state = None;
+    }
+
+    /// Build the state from the current shape of a mesh.
+    fn build<M: TetraMesh<Key = K>>(mesh: &M) -> State<K>
+    where
+        M::Tetra: Elasticity + Density,
+    {
+        let verts = mesh.verts().map(|(id, _)| id).collect::<Vec<_>>();
+        let indices = verts
+            .iter()
+            .enumerate()
+            .map(|(i, &id)| (id, i))
+            .collect::<HashMap<_, _>>();
+        let mut mass = vec![0.0; verts.len()];
+        let mut elements = Vec::new();
+        for (_, tet) in mesh.tetras() {
+            let Some(points) = mesh.tetra_points(tet) else {
+                continue;
+            };
+            let corners = VertexIdx::VALS.map(|i| indices.get(&tet.vertex(i)).copied());
+            let [Some(a), Some(b), Some(c), Some(d)] = corners else {
+                continue;
+            };
+            let corners = [a, b, c, d];
+            let [a, b, c, d] = points;
+            let rest = Mat3::from_cols(b - a, c - a, d - a);
+            let volume = rest.determinant() / 6.0;
+            if volume <= 0.0 {
+                continue;
+            }
+            for &i in &corners {
+                mass[i] += tet.density() * volume / 4.0;
+            }
+            let young = tet.young_modulus();
+            let poisson = tet.poisson_ratio().min(MAX_POISSON_RATIO);
+            elements.push(Element {
+                corners,
+                inv_rest: rest.inverse(),
+                volume,
+                mu: young / (2.0 * (1.0 + poisson)),
+                lambda: young * poisson / ((1.0 + poisson) * (1.0 - 2.0 * poisson)),
+                rotation: Quat::IDENTITY,
+            });
+        }
+        State {
+            velocity: vec![Vec3::ZERO; verts.len()],
+            verts,
+            indices,
+            mass,
+            elements,
+            tetras: mesh
+                .tetras()
+                .map(|(id, tet)| (id, VertexIdx::VALS.map(|i| tet.vertex(i))))
+                .collect(),
+        }
+    }
+
+    /// Step the simulation forward by some amount of time, moving the vertices of the mesh.
+    pub fn step<M: TetraMeshMut<Key = K>>(&mut self, mesh: &mut M, dt: f32)
+    where
+        M::Tetra: Elasticity + Density,
+    {
+        if self.state.as_ref().is_none_or(|s| !s.matches(mesh)) {
+            self.state = Some(Self::build(mesh));
+        }
+        let Some(state) = &mut self.state else {
+            return;
+        };
+        let substeps = self.options.substeps.max(1);
+        let h = dt / substeps as f32;
+        if h <= 0.0 {
+            return;
+        }
+        let inv_mass = state
+            .verts
+            .iter()
+            .zip(&state.mass)
+            .map(|(v, &m)| {
+                if m > 0.0 && !self.pinned.contains(v) {
+                    m.recip()
+                } else {
+                    0.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let Some(mut pos) = state
+            .verts
+            .iter()
+            .map(|&v| mesh.get_vertex(v).map(|v| v.as_vec3()))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return;
+        };
+        let mut prev = pos.clone();
+        let decay = (1.0 - self.options.damping * h).max(0.0);
+
+        for _ in 0..substeps {
+            for i in 0..pos.len() {
+                if inv_mass[i] == 0.0 {
+                    state.velocity[i] = Vec3::ZERO;
+                    continue;
+                }
+                prev[i] = pos[i];
+                state.velocity[i] += self.options.gravity * h;
+                pos[i] += state.velocity[i] * h;
+            }
+            for element in &mut state.elements {
+                solve_element(element, &mut pos, &inv_mass, h);
+            }
+            for i in 0..pos.len() {
+                if inv_mass[i] != 0.0 {
+                    state.velocity[i] = (pos[i] - prev[i]) / h * decay;
+                }
+            }
+        }
+
+        for (&v, &p) in state.verts.iter().zip(&pos) {
+            if let Some(vert) = mesh.get_vertex_mut(v) {
+                vert.set_vec3(p);
+            }
+        }
+    }
+}
+impl<K: Copy + Hash + Eq + Send + Sync + 'static> Default for SoftBody<K> {
+    fn default() -> Self {
+        Self::new(SoftBodyOptions::default())
+    }
+}
+
+/// Find the rotation closest to a matrix, starting from a guess.
+///
+/// This is the iteration from Müller et al., "A Robust Method to Extract the Rotational Part of Deformations", which
+/// converges in a few iterations from a close guess, and still gives a rotation for inverted elements.
+fn extract_rotation(a: Mat3, mut q: Quat) -> Quat {
+    for _ in 0..ROTATION_ITERATIONS {
+        let r = Mat3::from_quat(q);
+        let torque = r.x_axis.cross(a.x_axis) + r.y_axis.cross(a.y_axis) + r.z_axis.cross(a.z_axis);
+        let alignment = r.x_axis.dot(a.x_axis) + r.y_axis.dot(a.y_axis) + r.z_axis.dot(a.z_axis);
+        let omega = torque / (alignment.abs() + 1e-9);
+        let angle = omega.length();
+        if angle < 1e-9 {
+            break;
+        }
+        q = (Quat::from_axis_angle(omega / angle, angle) * q).normalize();
+    }
+    q
+}
+
+/// Apply one constraint of an element, given its value and its gradient with respect to the deformation gradient.
+fn apply(
+    element: &Element,
+    pos: &mut [Vec3],
+    inv_mass: &[f32],
+    value: f32,
+    gradient: Mat3,
+    compliance: f32,
+) {
+    // the deformation gradient is the edges times the inverse rest edges, so this is the chain rule through that
+    let grads = gradient * element.inv_rest.transpose();
+    let grads = [
+        -(grads.x_axis + grads.y_axis + grads.z_axis),
+        grads.x_axis,
+        grads.y_axis,
+        grads.z_axis,
+    ];
+    let mut denom = compliance;
+    for (&i, g) in element.corners.iter().zip(&grads) {
+        denom += inv_mass[i] * g.length_squared();
+    }
+    if denom <= 0.0 {
+        return;
+    }
+    let lambda = -value / denom;
+    for (&i, g) in element.corners.iter().zip(&grads) {
+        pos[i] += *g * (inv_mass[i] * lambda);
+    }
+}
+
+/// Get the deformation gradient of an element, which maps its rest shape to its current one.
+fn deformation(element: &Element, pos: &[Vec3]) -> Mat3 {
+    let [a, b, c, d] = element.corners.map(|i| pos[i]);
+    Mat3::from_cols(b - a, c - a, d - a) * element.inv_rest
+}
+
+/// Solve the shape and volume constraints of an element for one substep.
+fn solve_element(element: &mut Element, pos: &mut [Vec3], inv_mass: &[f32], h: f32) {
+    let h2 = h * h;
+    // the shape constraint is how far the deformation is from a pure rotation, whose energy is mu times its square
+    let f = deformation(element, pos);
+    element.rotation = extract_rotation(f, element.rotation);
+    let r = Mat3::from_quat(element.rotation);
+    let diff = f - r;
+    let value = [diff.x_axis, diff.y_axis, diff.z_axis]
+        .iter()
+        .map(|c| c.length_squared())
+        .sum::<f32>()
+        .sqrt();
+    if value > 1e-9 && element.mu > 0.0 {
+        let compliance = 1.0 / (2.0 * element.mu * element.volume * h2);
+        apply(
+            element,
+            pos,
+            inv_mass,
+            value,
+            diff * value.recip(),
+            compliance,
+        );
+    }
+    // the volume constraint is the stretch along the rotated axes, whose energy is half of lambda times its square
+    let f = deformation(element, pos);
+    let value = r.x_axis.dot(f.x_axis) + r.y_axis.dot(f.y_axis) + r.z_axis.dot(f.z_axis) - 3.0;
+    if element.lambda > 0.0 {
+        let compliance = 1.0 / (element.lambda * element.volume * h2);
+        apply(element, pos, inv_mass, value, r, compliance);
+    }
+}
+
+/// Step every soft body whose mesh has the type `M` by the time since the last run of its schedule.
+///
+/// The step comes from [`Time`], so in `FixedUpdate` it's the fixed timestep, and elsewhere it's the frame time. This
+/// should run before [`sync_meshes`](crate::ecs::sync_meshes) so the moved surfaces show up in the same frame.
+pub fn step_soft_bodies<M: TetraMeshMut + Send + Sync + 'static>(
+    time: Res<Time>,
+    mut query: Query<(&mut DynMesh, &mut SoftBody<M::Key>)>,
+) where
+    M::Key: Send + Sync + 'static,
+    M::Tetra: Elasticity + Density,
+{
+    for (mut mesh, mut body) in query.iter_mut() {
+        let Some(mesh) = mesh.downcast_mut::<M>() else {
+            continue;
+        };
+        body.step(mesh, time.delta_secs());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Cuboid, MeshBuilder};
+    use crate::geometry::signed_volume;
+    use crate::slab_mesh::SlabMesh;
+    use bevy_ecs::system::RunSystemOnce;
+    use bevy_ecs::world::World;
+    use std::time::Duration;
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    fn volume(mesh: &Mesh) -> f32 {
+        mesh.tetras()
+            .map(|(_, tet)| signed_volume(mesh.tetra_points(tet).unwrap()))
+            .sum()
+    }
+
+    /// A unit cube hanging from its top corners.
+    fn hanging_cube() -> (Mesh, SoftBody<u32>) {
+        let mesh = Cuboid::UNIT_CUBE.build::<Mesh>();
+        let mut body = SoftBody::default();
+        for (id, v) in mesh.verts() {
+            if v.pos.y == 1.0 {
+                body.pin(id);
+            }
+        }
+        (mesh, body)
+    }
+
+    #[test]
+    fn pinned_cube_hangs() {
+        let (mut mesh, mut body) = hanging_cube();
+        for _ in 0..256 {
+            body.step(&mut mesh, 1.0 / 64.0);
+            for (id, v) in mesh.verts() {
+                assert!(v.pos.is_finite(), "{id:?} is at {}", v.pos);
+                assert!(
+                    v.pos.cmpge(Vec3::splat(-0.5)).all() && v.pos.cmple(Vec3::splat(1.5)).all(),
+                    "{id:?} is at {}",
+                    v.pos
+                );
+                if body.is_pinned(id) {
+                    assert_eq!(v.pos.y, 1.0);
+                }
+            }
+            let volume = volume(&mesh);
+            assert!((volume - 1.0).abs() < 0.1, "volume is {volume}");
+        }
+        // it sags a bit under its own weight, but not much since it's stiff enough to hold its shape
+        let lowest = mesh
+            .verts()
+            .map(|(_, v)| v.pos.y)
+            .fold(f32::INFINITY, f32::min);
+        assert!(lowest < 0.0 && lowest > -0.2, "lowest point is at {lowest}");
+    }
+
+    #[test]
+    fn system_steps_by_time() {
+        let (mut mesh, mut body) = hanging_cube();
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_millis(100));
+        world.insert_resource(time);
+        let (copy, copy_body) = hanging_cube();
+        let entity = world.spawn((DynMesh::from(copy), copy_body)).id();
+        world.run_system_once(step_soft_bodies::<Mesh>).unwrap();
+        body.step(&mut mesh, 0.1);
+        let stepped = world.get::<DynMesh>(entity).unwrap();
+        let stepped = stepped.downcast_ref::<Mesh>().unwrap();
+        for (id, v) in mesh.verts() {
+            assert_eq!(stepped.get_vertex(id).unwrap().pos, v.pos);
+        }
+    }
+}
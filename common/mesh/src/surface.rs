@@ -0,0 +1,1150 @@
+//! Tetrahedralization of closed triangle surfaces.
+//!
+//! The surface's vertices are tetrahedralized with [`Delaunay`], and then any triangles that don't show up as faces of
+//! the tetrahedralization are recovered by flipping the tetrahedra in the way. When that gets stuck, edges in the way
+//! are split by extra points off of the surface, and failing that, points are added just inside of the surface and it
+//! starts over, but never on the surface itself, so the triangles come out exactly as they went in. Finally, everything
+//! outside of the surface is thrown away, leaving the interior, where a point is outside if it takes an even number of
+//! crossings of the surface to get to it, so cavities are left empty.
+use crate::builder::{Affine3A, BuildMesh, IndexedIds, IndexedMesh, IndexedMeshError, MeshBuilder};
+use crate::delaunay::Delaunay;
+use crate::edit;
+use crate::geometry::{is_degenerate, position_key};
+use crate::predicates::orient3d;
+use crate::slab_mesh::SlabMesh;
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display, Formatter};
+
+/// The maximum number of rounds of boundary recovery before giving up.
+const MAX_RECOVERY_ROUNDS: usize = 64;
+/// The largest ring of tetrahedra around an edge that [`remove_edge`] will try to retriangulate.
+const MAX_RING: usize = 8;
+
+/// The mesh used while tetrahedralizing, with each vertex storing its index in the point array.
+type Scratch = SlabMesh<usize, Vertex<Option<u32>>, Tetra<usize>>;
+
+/// An error from tetrahedralizing a [`TriangleSurface`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SurfaceError {
+    /// A triangle references a vertex past the end of the vertex array.
+    VertexOutOfRange { triangle: usize, index: u32 },
+    /// A vertex used by a triangle isn't finite.
+    NonFiniteVertex { index: u32 },
+    /// A triangle has no area.
+    DegenerateTriangle { triangle: usize },
+    /// An edge isn't shared by exactly two triangles with opposite windings.
+    ///
+    /// The edge is given in terms of the input vertex indices.
+    NotWatertight { edge: [u32; 2] },
+    /// Some triangles still couldn't be recovered after inserting extra points, which can happen for
+    /// self-intersecting surfaces, surfaces wound inwards, or very thin features.
+    RecoveryFailed,
+    /// The final tetrahedra couldn't be assembled into a mesh.
+    Indexed(IndexedMeshError),
+}
+impl Display for SurfaceError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::VertexOutOfRange { triangle, index } => {
+                write!(f, "triangle {triangle} references missing vertex {index}")
+            }
+            Self::NonFiniteVertex { index } => write!(f, "vertex {index} isn't finite"),
+            Self::DegenerateTriangle { triangle } => write!(f, "triangle {triangle} is degenerate"),
+            Self::NotWatertight { edge } => write!(
+                f,
+                "edge {edge:?} isn't shared by exactly two consistently wound triangles"
+            ),
+            Self::RecoveryFailed => f.write_str("failed to recover the surface triangles"),
+            Self::Indexed(err) => Display::fmt(err, f),
+        }
+    }
+}
+impl std::error::Error for SurfaceError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        if let Self::Indexed(err) = self {
+            Some(err)
+        } else {
+            None
+        }
+    }
+}
+impl From<IndexedMeshError> for SurfaceError {
+    fn from(value: IndexedMeshError) -> Self {
+        Self::Indexed(value)
+    }
+}
+
+/// A closed triangle surface, which can be filled with tetrahedra.
+///
+/// The triangles must form a watertight, consistently wound surface, but vertices can be repeated, so triangle soups
+/// where each triangle has its own vertices work as long as the shared corners are at exactly the same position.
+///
+/// The boundary of the resulting mesh is exactly the input triangles, so [`TetraMesh::append_primitive_surface`]
+/// gives back the same surface, always wound outwards. Extra vertices can be added to the interior, but never to the
+/// surface, and if that isn't enough, tetrahedralizing fails with [`SurfaceError::RecoveryFailed`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TriangleSurface<V, F> {
+    pub verts: V,
+    pub faces: F,
+}
+impl<V, F> TriangleSurface<V, F> {
+    pub const fn new(verts: V, faces: F) -> Self {
+        Self { verts, faces }
+    }
+}
+#[cfg(feature = "render")]
+impl TriangleSurface<Vec<Vec3>, Vec<[u32; 3]>> {
+    /// Read a surface from a Bevy mesh.
+    ///
+    /// This returns `None` if the mesh isn't a triangle list with 3D positions.
+    pub fn from_mesh(mesh: &bevy_render::mesh::Mesh) -> Option<Self> {
+        use bevy_render::mesh::{Mesh, PrimitiveTopology, VertexAttributeValues};
+
+        if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+            return None;
+        }
+        let Some(VertexAttributeValues::Float32x3(verts)) =
+            mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+        else {
+            return None;
+        };
+        let verts = verts.iter().copied().map(Vec3::from).collect::<Vec<_>>();
+        let indices = mesh.indices().map_or_else(
+            || (0..verts.len() as u32).collect::<Vec<_>>(),
+            |i| i.iter().map(|i| i as u32).collect(),
+        );
+        let faces = indices
+            .chunks_exact(3)
+            .map(|c| [c[0], c[1], c[2]])
+            .collect();
+        Some(Self { verts, faces })
+    }
+}
+impl<V: AsRef<[Vec3]>, F: AsRef<[[u32; 3]]>> TriangleSurface<V, F> {
+    /// Tetrahedralize the surface, and get the result as an [`IndexedMesh`].
+    pub fn tetrahedralize(&self) -> Result<IndexedMesh<Vec<Vec3>, Vec<[u32; 4]>>, SurfaceError> {
+        let input = self.verts.as_ref();
+
+        // weld together vertices at the same position, remembering the first index for error messages
+        let mut lookup = HashMap::new();
+        let mut points = Vec::new();
+        let mut first = Vec::new();
+        let remap = input
+            .iter()
+            .enumerate()
+            .map(|(i, &v)| {
+                *lookup.entry(position_key(v)).or_insert_with(|| {
+                    points.push(v);
+                    first.push(i as u32);
+                    (points.len() - 1) as u32
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut triangles = Vec::with_capacity(self.faces.as_ref().len());
+        for (triangle, &face) in self.faces.as_ref().iter().enumerate() {
+            if let Some(&index) = face.iter().find(|&&i| i as usize >= input.len()) {
+                return Err(SurfaceError::VertexOutOfRange { triangle, index });
+            }
+            let tri = face.map(|i| remap[i as usize]);
+            let [a, b, c] = tri.map(|i| points[i as usize]);
+            if let Some(&index) = face.iter().find(|&&i| !input[i as usize].is_finite()) {
+                return Err(SurfaceError::NonFiniteVertex { index });
+            }
+            if tri[0] == tri[1]
+                || tri[1] == tri[2]
+                || tri[2] == tri[0]
+                || (b - a).cross(c - a) == Vec3::ZERO
+            {
+                return Err(SurfaceError::DegenerateTriangle { triangle });
+            }
+            triangles.push(tri);
+        }
+
+        let mut directed = HashMap::<_, u32>::with_capacity(triangles.len() * 3);
+        for &[a, b, c] in &triangles {
+            for edge in [[a, b], [b, c], [c, a]] {
+                *directed.entry(edge).or_default() += 1;
+            }
+        }
+        for (&[a, b], &count) in &directed {
+            if count != 1 || directed.get(&[b, a]) != Some(&1) {
+                return Err(SurfaceError::NotWatertight {
+                    edge: [first[a as usize], first[b as usize]],
+                });
+            }
+        }
+
+        let surface_points = points.len();
+        let constrained_faces = triangles.iter().map(|&t| sorted(t)).collect::<HashSet<_>>();
+        let constrained_edges = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| [[a, b], [b, c], [c, a]].map(sorted))
+            .collect::<HashSet<_>>();
+        let bounds = triangles.iter().flatten().fold(
+            [Vec3::INFINITY, Vec3::NEG_INFINITY],
+            |[min, max], &i| {
+                let p = points[i as usize];
+                [min.min(p), max.max(p)]
+            },
+        );
+        let mut round = 0;
+        let mesh = 'recover: loop {
+            // the tetrahedralization is rebuilt from scratch each round, since flips break the Delaunay property
+            // that inserting more points relies on
+            let mut delaunay = Delaunay::new(Scratch::new(), bounds);
+            let mut ids = HashMap::new();
+            for &i in triangles.iter().flatten() {
+                if let Entry::Vacant(e) = ids.entry(i) {
+                    let id = delaunay
+                        .insert(Vertex {
+                            pos: points[i as usize],
+                            data: Some(i),
+                        })
+                        .map_err(|_| SurfaceError::RecoveryFailed)?;
+                    e.insert(id);
+                }
+            }
+            for (i, &pos) in points.iter().enumerate().skip(surface_points) {
+                // an extra point that can't be inserted, like one that landed on top of another, is just left out
+                let _ = delaunay.insert(Vertex {
+                    pos,
+                    data: Some(i as u32),
+                });
+            }
+            let mut mesh = delaunay.into_mesh();
+            // a tetrahedron containing each vertex, so the ones around it can be found without searching the whole mesh
+            let mut incident = HashMap::new();
+            for (id, tet) in mesh.tetras() {
+                for i in VertexIdx::VALS {
+                    incident.insert(tet.vertex(i), id);
+                }
+            }
+
+            let missing = loop {
+                let (faces, edges) = collect_simplices(&mesh);
+                let missing = triangles
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, tri)| !faces.contains(&sorted(**tri)))
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                if missing.is_empty() {
+                    break 'recover mesh;
+                }
+                let mut progress = false;
+                for &tri in &missing {
+                    progress |= recover_by_flip(
+                        &mut mesh,
+                        &mut incident,
+                        &ids,
+                        triangles[tri],
+                        &edges,
+                        &constrained_faces,
+                        &constrained_edges,
+                    );
+                }
+                if progress {
+                    continue;
+                }
+
+                // flips can get stuck, especially when lots of the points are coplanar or cospherical, so an edge in
+                // the way of each missing triangle is split by a point nudged off of it, giving the flips more room
+                round += 1;
+                if round > MAX_RECOVERY_ROUNDS {
+                    return Err(SurfaceError::RecoveryFailed);
+                }
+                // a split usually makes room for the flips to recover its neighbors too
+                let mut split_near = HashSet::new();
+                for &tri in &missing {
+                    if triangles[tri].iter().any(|i| split_near.contains(i)) {
+                        continue;
+                    }
+                    if let Some((id, edge)) = crossing_edge(&mesh, &incident, &ids, triangles[tri])
+                        && let Some(edit) =
+                            split_ring(&mut mesh, id, edge, &constrained_faces, &mut points)
+                    {
+                        update_incident(&mesh, &mut incident, &edit.created);
+                        split_near.extend(triangles[tri]);
+                        progress = true;
+                    }
+                }
+                if !progress {
+                    break missing;
+                }
+            };
+
+            round += 1;
+            if round > MAX_RECOVERY_ROUNDS {
+                return Err(SurfaceError::RecoveryFailed);
+            }
+
+            // if there's nothing left to split, a point is added just behind each missing triangle and it starts over.
+            // that's inside of the surface as long as it's wound outwards, and harmlessly thrown away with the outside
+            // otherwise, but never on the surface
+            for tri in missing {
+                let [a, b, c] = triangles[tri].map(|i| points[i as usize]);
+                let normal = (b - a).cross(c - a).normalize();
+                let shortest = a.distance(b).min(b.distance(c)).min(c.distance(a));
+                points.push((a + b + c) / 3.0 - normal * shortest / 4.0);
+            }
+        };
+
+        // coplanar points can leave flat tetrahedra behind, which are merged into their neighbors where possible, or
+        // else removed along with one of their edges
+        let mut mesh = mesh;
+        loop {
+            let flat = mesh
+                .tetras()
+                .filter(|(_, t)| mesh.tetra_points(t).is_some_and(is_degenerate))
+                .map(|(id, _)| id)
+                .collect::<Vec<_>>();
+            let mut progress = false;
+            for id in flat {
+                let keep = |mesh: &Scratch, face: [VertexId<usize>; 3]| {
+                    let indices = face.map(|v| mesh.get_vertex(v).and_then(|v| v.data));
+                    match indices {
+                        [Some(a), Some(b), Some(c)] => {
+                            constrained_faces.contains(&sorted([a, b, c]))
+                        }
+                        _ => false,
+                    }
+                };
+                let vert = |pos| {
+                    points.push(pos);
+                    Vertex {
+                        pos,
+                        data: Some(points.len() as u32 - 1),
+                    }
+                };
+                if cancel_stacked(&mut mesh, id)
+                    || edit::remove_flat(&mut mesh, id, keep, vert).is_some()
+                {
+                    progress = true;
+                    continue;
+                }
+                // the ones touching the enclosing vertices are thrown away anyway
+                let Some(tet) = mesh.get_tetra(id) else {
+                    continue;
+                };
+                let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+                let Some(indices) = verts
+                    .map(|v| mesh.get_vertex(v).and_then(|v| v.data))
+                    .into_iter()
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+                let edges = (0..4)
+                    .flat_map(|i| ((i + 1)..4).map(move |j| [i, j]))
+                    .filter(|&[i, j]| {
+                        !constrained_edges.contains(&sorted([indices[i], indices[j]]))
+                    })
+                    .map(|[i, j]| sorted_ids([verts[i], verts[j]]))
+                    .collect::<Vec<_>>();
+                progress |= edges.iter().any(|&edge| {
+                    remove_edge(&mut mesh, id, edge, &[], &constrained_faces, false).is_some()
+                }) || edges.iter().any(|&edge| {
+                    split_ring(&mut mesh, id, edge, &constrained_faces, &mut points).is_some()
+                });
+            }
+            if !progress {
+                break;
+            }
+        }
+
+        let index_of = |t: &Tetra<usize>| {
+            VertexIdx::VALS.map(|i| mesh.get_vertex(t.vertex(i)).and_then(|v| v.data))
+        };
+
+        // flood fill from the tetrahedra touching the enclosing vertices, switching between outside and inside each
+        // time the surface is crossed, so that cavities inside of the surface are left out too
+        let mut inside = HashMap::new();
+        let mut queue = mesh
+            .tetras()
+            .filter(|(_, t)| index_of(t).contains(&None))
+            .map(|(id, _)| id)
+            .collect::<VecDeque<_>>();
+        inside.extend(queue.iter().map(|&id| (id, false)));
+        while let Some(id) = queue.pop_front() {
+            let tet = mesh.get_tetra(id).unwrap();
+            let indices = index_of(tet);
+            let here = inside[&id];
+            for face in VertexIdx::VALS {
+                let Some((n, _)) = tet.face(face) else {
+                    continue;
+                };
+                let [a, b, c] = face.others().map(|i| *i.in_arr(&indices));
+                let crossed = matches!((a, b, c), (Some(a), Some(b), Some(c)) if constrained_faces.contains(&sorted([a, b, c])));
+                if let Entry::Vacant(e) = inside.entry(n) {
+                    e.insert(here != crossed);
+                    queue.push_back(n);
+                }
+            }
+        }
+
+        // a flat tetrahedron left on the surface has the outside on one side of it, and leaving it out just moves the
+        // surface onto its faces on the other side, which cover the same area. flat tetrahedra can be stacked on top of
+        // each other, so leaving one out can expose the ones under it. each one only ever goes from kept to left out,
+        // so the result doesn't depend on the order they're visited in
+        let flat = mesh
+            .tetras()
+            .filter(|(id, _)| inside.get(id) == Some(&true))
+            .filter_map(|(id, t)| {
+                let points = mesh.tetra_points(t).filter(|&p| is_degenerate(p))?;
+                Some((id, edit::flat_sides(points)?))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut left_out = HashSet::new();
+        let mut queue = flat.keys().copied().collect::<Vec<_>>();
+        while let Some(id) = queue.pop() {
+            if left_out.contains(&id) {
+                continue;
+            }
+            let tet = mesh.get_tetra(id).unwrap();
+            let exposed = flat[&id].iter().any(|side| {
+                side.iter().all(|&i| {
+                    tet.face(i).is_some_and(|(n, _)| {
+                        inside.get(&n) == Some(&false) || left_out.contains(&n)
+                    })
+                })
+            });
+            if exposed {
+                left_out.insert(id);
+                queue.extend(
+                    VertexIdx::VALS
+                        .into_iter()
+                        .filter_map(|i| Some(tet.face(i)?.0))
+                        .filter(|n| flat.contains_key(n)),
+                );
+            }
+        }
+
+        let kept = mesh
+            .tetras()
+            .filter(|(id, _)| inside.get(id) == Some(&true) && !left_out.contains(id))
+            .map(|(_, tet)| index_of(tet).map(|i| i.unwrap()))
+            .collect::<Vec<_>>();
+
+        // the boundary has to be exactly the input triangles, which flat tetrahedra that couldn't be merged can get in
+        // the way of
+        let mut face_count = HashMap::<_, u32>::new();
+        for indices in &kept {
+            for face in VertexIdx::VALS {
+                *face_count
+                    .entry(sorted(face.others().map(|i| *i.in_arr(indices))))
+                    .or_default() += 1;
+            }
+        }
+        let mut boundary = face_count.iter().filter(|&(_, &n)| n == 1).map(|(f, _)| f);
+        if boundary.clone().count() != constrained_faces.len()
+            || !boundary.all(|f| constrained_faces.contains(f))
+        {
+            return Err(SurfaceError::RecoveryFailed);
+        }
+
+        let mut out_index = HashMap::new();
+        let mut out_verts = Vec::new();
+        let mut out_tetras = Vec::new();
+        for indices in kept {
+            out_tetras.push(indices.map(|i| {
+                *out_index.entry(i).or_insert_with(|| {
+                    out_verts.push(points[i as usize]);
+                    (out_verts.len() - 1) as u32
+                })
+            }));
+        }
+        Ok(IndexedMesh::new(out_verts, out_tetras))
+    }
+    /// Tetrahedralize the surface and append it, returning the IDs of the new vertices and tetrahedra.
+    pub fn try_append_to<M: BuildMesh>(&self, mesh: M) -> Result<IndexedIds<M::Key>, SurfaceError> {
+        Ok(self.tetrahedralize()?.try_append_to(mesh)?)
+    }
+    /// Tetrahedralize the surface into a new mesh.
+    pub fn try_build<M: Default>(&self) -> Result<M, SurfaceError>
+    where
+        for<'a> &'a mut M: BuildMesh,
+    {
+        let mut mesh = M::default();
+        self.try_append_to(&mut mesh)?;
+        Ok(mesh)
+    }
+}
+impl<V: AsRef<[Vec3]> + AsMut<[Vec3]>, F: AsRef<[[u32; 3]]>> MeshBuilder for TriangleSurface<V, F> {
+    type Transformed = Self;
+
+    /// Tetrahedralize and append this surface, panicking if it's invalid.
+    ///
+    /// See [`Self::try_append_to`] for a non-panicking version.
+    fn append_to<M: BuildMesh>(&self, mesh: M) {
+        if let Err(err) = self.try_append_to(mesh) {
+            panic!("failed to tetrahedralize surface: {err}");
+        }
+    }
+    fn transform(mut self, transform: Affine3A) -> Self::Transformed {
+        for pt in self.verts.as_mut() {
+            *pt = transform.transform_point3(*pt);
+        }
+        self
+    }
+}
+
+#[inline(always)]
+fn sorted<const N: usize>(mut arr: [u32; N]) -> [u32; N] {
+    arr.sort_unstable();
+    arr
+}
+
+/// Point every vertex of some new tetrahedra at one of them.
+///
+/// The flips here never remove a vertex from the tetrahedra they replace, so this keeps `incident` up to date.
+fn update_incident(
+    mesh: &Scratch,
+    incident: &mut HashMap<VertexId<usize>, TetraId<usize>>,
+    created: &[TetraId<usize>],
+) {
+    for &id in created {
+        if let Some(tet) = mesh.get_tetra(id) {
+            for i in VertexIdx::VALS {
+                incident.insert(tet.vertex(i), id);
+            }
+        }
+    }
+}
+
+/// Try to recover a triangle by removing a single edge that's in the way.
+///
+/// If one of the triangle's edges is missing, this looks for an edge crossing it, and otherwise for an edge piercing
+/// the triangle. Constrained edges and faces are never removed, so this can't undo earlier recoveries.
+fn recover_by_flip(
+    mesh: &mut Scratch,
+    incident: &mut HashMap<VertexId<usize>, TetraId<usize>>,
+    ids: &HashMap<u32, VertexId<usize>>,
+    [a, b, c]: [u32; 3],
+    present: &HashSet<[u32; 2]>,
+    constrained_faces: &HashSet<[u32; 3]>,
+    constrained_edges: &HashSet<[u32; 2]>,
+) -> bool {
+    let missing_edge = [[a, b], [b, c], [c, a]]
+        .into_iter()
+        .find(|&e| !present.contains(&sorted(e)));
+    if let Some([p, q]) = missing_edge
+        && flip_face(mesh, incident, [ids[&p], ids[&q]], constrained_faces)
+    {
+        return true;
+    }
+    let (pivot, want) = match missing_edge {
+        Some([p, q]) => (ids[&p], vec![ids[&p], ids[&q]]),
+        None => (ids[&a], vec![ids[&a], ids[&b], ids[&c]]),
+    };
+    let index_of = |mesh: &Scratch, v| mesh.get_vertex(v).and_then(|v| v.data);
+
+    // every edge with the pivot in its link is opposite to it in some tetrahedron
+    let mut candidates = Vec::new();
+    let mut seen = HashSet::new();
+    let Some(&start) = incident.get(&pivot) else {
+        return false;
+    };
+    for id in edit::vertex_star(mesh, start, pivot) {
+        let Some(tet) = mesh.get_tetra(id) else {
+            continue;
+        };
+        let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+        let Some(k) = verts.iter().position(|&v| v == pivot) else {
+            continue;
+        };
+        let others = VertexIdx::VALS[k].others().map(|i| *i.in_arr(&verts));
+        for (i, j) in [(0, 1), (1, 2), (2, 0)] {
+            let edge = sorted_ids([others[i], others[j]]);
+            if let [Some(x), Some(y)] = edge.map(|v| index_of(mesh, v))
+                && constrained_edges.contains(&sorted([x, y]))
+            {
+                continue;
+            }
+            if seen.insert(edge) {
+                candidates.push((id, edge));
+            }
+        }
+    }
+    candidates.into_iter().any(|(id, edge)| {
+        let Some(edit) = remove_edge(mesh, id, edge, &want, constrained_faces, true) else {
+            return false;
+        };
+        update_incident(mesh, incident, &edit.created);
+        true
+    })
+}
+
+/// Try to create an edge with a 2-3 flip, if the edge only crosses a single face.
+fn flip_face(
+    mesh: &mut Scratch,
+    incident: &mut HashMap<VertexId<usize>, TetraId<usize>>,
+    [p, q]: [VertexId<usize>; 2],
+    constrained: &HashSet<[u32; 3]>,
+) -> bool {
+    let Some(&start) = incident.get(&p) else {
+        return false;
+    };
+    let found = edit::vertex_star(mesh, start, p)
+        .into_iter()
+        .find_map(|id| {
+            let tet = mesh.get_tetra(id)?;
+            let corner = VertexIdx::VALS.into_iter().find(|&i| tet.vertex(i) == p)?;
+            let (n, i) = tet.face(corner)?;
+            (mesh.get_tetra(n)?.vertex(i) == q)
+                .then(|| (id, corner, corner.others().map(|i| tet.vertex(i))))
+        });
+    let Some((id, corner, face)) = found else {
+        return false;
+    };
+    let indices = face.map(|v| mesh.get_vertex(v).and_then(|v| v.data));
+    if let [Some(x), Some(y), Some(z)] = indices
+        && constrained.contains(&sorted([x, y, z]))
+    {
+        return false;
+    }
+    let Ok(edit) = edit::flip_2_3(mesh, id, corner) else {
+        return false;
+    };
+    update_incident(mesh, incident, &edit.created);
+    true
+}
+
+#[inline(always)]
+fn sorted_ids(mut arr: [VertexId<usize>; 2]) -> [VertexId<usize>; 2] {
+    arr.sort_unstable();
+    arr
+}
+
+/// Remove an edge by retriangulating the ring of tetrahedra around it, so that the new tetrahedra contain `want`.
+///
+/// The vertices in `want` must all be in the link of the edge, and form either a diagonal or a triangle of the link
+/// polygon, or else be empty to take any triangulation. This only succeeds if the new tetrahedra exactly fill the same
+/// space as the old ones. Some of them can be flat if `allow_flat` is set, which lets coplanar points be flipped
+/// through, leaving the flat tetrahedra to be cleaned up at the end.
+fn remove_edge(
+    mesh: &mut Scratch,
+    start: TetraId<usize>,
+    [a, c]: [VertexId<usize>; 2],
+    want: &[VertexId<usize>],
+    constrained: &HashSet<[u32; 3]>,
+    allow_flat: bool,
+) -> Option<edit::Edit<usize>> {
+    let (ring, link) = edge_ring(mesh, start, [a, c], MAX_RING)?;
+    if link.len() < 3 {
+        return None;
+    }
+    if removes_constrained(mesh, [a, c], &link, constrained) {
+        return None;
+    }
+    let mut positions = want
+        .iter()
+        .map(|w| link.iter().position(|v| v == w))
+        .collect::<Option<Vec<_>>>()?;
+    positions.sort_unstable();
+    if want.len() == 2
+        && (positions[1] - positions[0] == 1 || positions[1] - positions[0] == link.len() - 1)
+    {
+        // the two vertices are already connected by a face of the ring, so there's nothing to flip to
+        return None;
+    }
+
+    let pos = |v: VertexId<usize>| mesh.get_vertex(v).map(|v| v.pos);
+    let (pa, pc) = (pos(a)?, pos(c)?);
+    let old_volume = ring
+        .iter()
+        .filter_map(|&id| mesh.tetra_points(mesh.get_tetra(id)?))
+        .map(volume_f64)
+        .sum::<f64>();
+
+    // triangulate the parts of the link polygon between the wanted vertices separately
+    let mut options = match want.len() {
+        0 => triangulations(&link),
+        3 => vec![vec![[
+            link[positions[0]],
+            link[positions[1]],
+            link[positions[2]],
+        ]]],
+        _ => vec![Vec::new()],
+    };
+    for (n, &start) in positions.iter().enumerate() {
+        let end = positions[(n + 1) % positions.len()];
+        let len = (end + link.len() - start) % link.len();
+        let chain = (0..=len)
+            .map(|i| link[(start + i) % link.len()])
+            .collect::<Vec<_>>();
+        let parts = triangulations(&chain);
+        options = options
+            .iter()
+            .flat_map(|base| {
+                parts.iter().map(move |part| {
+                    let mut out = base.clone();
+                    out.extend_from_slice(part);
+                    out
+                })
+            })
+            .collect();
+    }
+
+    'options: for tris in options {
+        let mut new = Vec::with_capacity(tris.len() * 2);
+        let mut new_volume = 0.0;
+        for [p, q, r] in tris {
+            let (Some(pp), Some(pq), Some(pr)) = (pos(p), pos(q), pos(r)) else {
+                continue 'options;
+            };
+            let oa = orient3d(pa, pp, pq, pr);
+            let oc = orient3d(pc, pp, pq, pr);
+            let flat = oa == Ordering::Equal || oc == Ordering::Equal;
+            if oa == oc || (flat && !allow_flat) {
+                continue 'options;
+            }
+            let oa = if oa == Ordering::Equal {
+                oc.reverse()
+            } else {
+                oa
+            };
+            let (q, r, pq, pr) = if oa == Ordering::Greater {
+                (q, r, pq, pr)
+            } else {
+                (r, q, pr, pq)
+            };
+            if !allow_flat && (is_degenerate([pa, pp, pq, pr]) || is_degenerate([pc, pp, pr, pq])) {
+                continue 'options;
+            }
+            new.push([a, p, q, r]);
+            new.push([c, p, r, q]);
+            new_volume += volume_f64([pa, pp, pq, pr]) + volume_f64([pc, pp, pr, pq]);
+        }
+        if (new_volume - old_volume).abs() > old_volume * 1e-9 {
+            continue;
+        }
+        return Some(edit::replace_tetras(mesh, &ring, new));
+    }
+    None
+}
+
+/// Remove an edge by adding a vertex inside of the ring of tetrahedra around it, and connecting it to the outside of the
+/// ring.
+///
+/// This works even when [`remove_edge`] can't retriangulate the ring, as long as there's a point near the middle of the
+/// edge that can see all of the outside of the ring. The new vertex is strictly inside of the ring, so it's never on
+/// the surface.
+fn split_ring(
+    mesh: &mut Scratch,
+    start: TetraId<usize>,
+    [a, c]: [VertexId<usize>; 2],
+    constrained: &HashSet<[u32; 3]>,
+    points: &mut Vec<Vec3>,
+) -> Option<edit::Edit<usize>> {
+    let (ring, link) = edge_ring(mesh, start, [a, c], usize::MAX)?;
+    if removes_constrained(mesh, [a, c], &link, constrained) {
+        return None;
+    }
+    let pos = |v: VertexId<usize>| mesh.get_vertex(v).map(|v| v.pos);
+    let (pa, pc) = (pos(a)?, pos(c)?);
+    let link_points = link.iter().map(|&v| pos(v)).collect::<Option<Vec<_>>>()?;
+    let mid = (pa + pc) / 2.0;
+    // the enclosing vertices are far away, so they'd pull the center out of the ring, and they're thrown away anyway
+    let is_real = |v: VertexId<usize>| mesh.get_vertex(v).is_some_and(|v| v.data.is_some());
+    let real = link
+        .iter()
+        .zip(&link_points)
+        .filter(|(v, _)| is_real(**v))
+        .map(|(_, &p)| p)
+        .collect::<Vec<_>>();
+    let center = if real.is_empty() {
+        link_points.iter().sum::<Vec3>() / link.len() as f32
+    } else {
+        real.iter().sum::<Vec3>() / real.len() as f32
+    };
+
+    // the faces around the outside of the ring, all wound the same way
+    let faces = (0..link.len())
+        .flat_map(|i| {
+            let j = (i + 1) % link.len();
+            [
+                ([a, link[i], link[j]], [pa, link_points[i], link_points[j]]),
+                ([c, link[j], link[i]], [pc, link_points[j], link_points[i]]),
+            ]
+        })
+        .map(|(face, at)| (face, at, face.into_iter().all(is_real)))
+        .collect::<Vec<_>>();
+    // the middle of the edge sees everything but the faces of flat tetrahedra, so it's nudged towards the link
+    let (split, sign) = (1..=12).map(|i| 0.5f32.powi(i)).find_map(|t| {
+        let split = mid.lerp(center, t);
+        let sign = orient3d(split, faces[0].1[0], faces[0].1[1], faces[0].1[2]);
+        let sees = |&(_, [p, q, r], real): &(_, [Vec3; 3], bool)| {
+            orient3d(split, p, q, r) == sign && !(real && is_degenerate([split, p, q, r]))
+        };
+        (sign != Ordering::Equal && faces.iter().all(sees)).then_some((split, sign))
+    })?;
+
+    let vert = mesh.add_vertex(Vertex {
+        pos: split,
+        data: Some(points.len() as u32),
+    });
+    points.push(split);
+    let new = faces.into_iter().map(|([p, q, r], _, _)| {
+        if sign == Ordering::Greater {
+            [vert, p, q, r]
+        } else {
+            [vert, p, r, q]
+        }
+    });
+    Some(edit::replace_tetras(mesh, &ring, new))
+}
+
+/// Remove a flat tetrahedron along with a neighbor with the same corners, linking the tetrahedra on either side of them
+/// to each other directly.
+///
+/// Two of these stacked on top of each other cover the same flat area from opposite sides, so they cancel out, but they
+/// can't be merged into their neighbors one at a time since each one only has the other on one of its sides.
+fn cancel_stacked(mesh: &mut Scratch, id: TetraId<usize>) -> bool {
+    let corners = |id| {
+        let tet: &Tetra<usize> = mesh.get_tetra(id)?;
+        let mut verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+        verts.sort_unstable();
+        Some(verts)
+    };
+    let Some(verts) = corners(id) else {
+        return false;
+    };
+    let Some(other) = mesh.get_tetra(id).and_then(|tet| {
+        VertexIdx::VALS
+            .into_iter()
+            .filter_map(|i| Some(tet.face(i)?.0))
+            .find(|&n| n != id && corners(n) == Some(verts))
+    }) else {
+        return false;
+    };
+    // the faces that aren't shared between the two, keyed by their vertices
+    let outside = |id, other| {
+        let tet: &Tetra<usize> = mesh.get_tetra(id).unwrap();
+        VertexIdx::VALS
+            .into_iter()
+            .filter(|&i| tet.face(i).is_none_or(|(n, _)| n != other))
+            .map(|i| (tet.sorted_face(i), tet.face(i)))
+            .collect::<HashMap<_, _>>()
+    };
+    let (above, below) = (outside(id, other), outside(other, id));
+    if above.len() != below.len() || above.keys().any(|k| !below.contains_key(k)) {
+        return false;
+    }
+    mesh.remove_tetra(id);
+    mesh.remove_tetra(other);
+    for (key, a) in above {
+        let b = below[&key];
+        if let Some((n, i)) = a
+            && let Some(tet) = mesh.get_tetra_mut(n)
+        {
+            tet.set_face(i, b);
+        }
+        if let Some((n, i)) = b
+            && let Some(tet) = mesh.get_tetra_mut(n)
+        {
+            tet.set_face(i, a);
+        }
+    }
+    true
+}
+
+/// Find an edge in the way of a missing triangle, either passing through it or crossing one of its edges, along with a
+/// tetrahedron containing it.
+fn crossing_edge(
+    mesh: &Scratch,
+    incident: &HashMap<VertexId<usize>, TetraId<usize>>,
+    ids: &HashMap<u32, VertexId<usize>>,
+    triangle: [u32; 3],
+) -> Option<(TetraId<usize>, [VertexId<usize>; 2])> {
+    let corners = triangle.map(|i| ids[&i]);
+    let pos = |v: VertexId<usize>| mesh.get_vertex(v).map(|v| v.pos);
+    let [a, b, c] = [pos(corners[0])?, pos(corners[1])?, pos(corners[2])?];
+    let sides = [[0, 1], [1, 2], [2, 0]];
+    for &corner in &corners {
+        let Some(&start) = incident.get(&corner) else {
+            continue;
+        };
+        for id in edit::vertex_star(mesh, start, corner) {
+            let Some(tet) = mesh.get_tetra(id) else {
+                continue;
+            };
+            let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+            for (i, j) in [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)] {
+                let edge = [verts[i], verts[j]];
+                let (Some(p), Some(q)) = (pos(edge[0]), pos(edge[1])) else {
+                    continue;
+                };
+                let pierces =
+                    !edge.iter().any(|v| corners.contains(v)) && pierces([a, b, c], [p, q]);
+                let crosses = sides.iter().any(|&[s, t]| {
+                    !edge.contains(&corners[s])
+                        && !edge.contains(&corners[t])
+                        && crosses([[a, b, c][s], [a, b, c][t]], [p, q])
+                });
+                if pierces || crosses {
+                    return Some((id, edge));
+                }
+            }
+            // a side of the triangle can also pass through a face, in which case the edge of the face closest to it
+            // is the one in the way
+            for face in VertexIdx::VALS {
+                let face = face.others().map(|i| *i.in_arr(&verts));
+                let Some(at) = face.iter().map(|&v| pos(v)).collect::<Option<Vec<_>>>() else {
+                    continue;
+                };
+                let [x, y, z] = [at[0], at[1], at[2]];
+                let side = sides.iter().find(|&&[s, t]| {
+                    !face.contains(&corners[s])
+                        && !face.contains(&corners[t])
+                        && pierces([x, y, z], [[a, b, c][s], [a, b, c][t]])
+                });
+                if let Some(&[s, t]) = side {
+                    let [p, q] = [[a, b, c][s], [a, b, c][t]];
+                    let weights =
+                        [[y, z], [z, x], [x, y]].map(|[u, v]| volume_f64([p, q, u, v]).abs());
+                    let nearest = (0..3).min_by(|&i, &j| weights[i].total_cmp(&weights[j]))?;
+                    return Some((id, [face[(nearest + 1) % 3], face[(nearest + 2) % 3]]));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Check if a segment passes through the inside of a triangle.
+fn pierces([a, b, c]: [Vec3; 3], [p, q]: [Vec3; 2]) -> bool {
+    let (op, oq) = (orient3d(a, b, c, p), orient3d(a, b, c, q));
+    let around = [
+        orient3d(p, q, a, b),
+        orient3d(p, q, b, c),
+        orient3d(p, q, c, a),
+    ];
+    op != Ordering::Equal
+        && oq != Ordering::Equal
+        && op != oq
+        && around[0] != Ordering::Equal
+        && around.iter().all(|&o| o == around[0])
+}
+
+/// Check if two segments in the same plane cross each other.
+fn crosses([p, q]: [Vec3; 2], [x, y]: [Vec3; 2]) -> bool {
+    let normal = (q - p).cross(y - x);
+    if orient3d(p, q, x, y) != Ordering::Equal || normal == Vec3::ZERO {
+        return false;
+    }
+    // any point off of the plane splits it along each segment's line
+    let off = p + normal;
+    let splits = |[p, q]: [Vec3; 2], [x, y]: [Vec3; 2]| {
+        let (ox, oy) = (orient3d(p, q, off, x), orient3d(p, q, off, y));
+        ox != Ordering::Equal && oy != Ordering::Equal && ox != oy
+    };
+    splits([p, q], [x, y]) && splits([x, y], [p, q])
+}
+
+/// Check if any of the faces around an edge are constrained, since they're removed along with it.
+fn removes_constrained(
+    mesh: &Scratch,
+    [a, c]: [VertexId<usize>; 2],
+    link: &[VertexId<usize>],
+    constrained: &HashSet<[u32; 3]>,
+) -> bool {
+    let index_of = |v| mesh.get_vertex(v).and_then(|v| v.data);
+    link.iter().any(|&v| {
+        [index_of(a), index_of(c), index_of(v)]
+            .into_iter()
+            .collect::<Option<Vec<_>>>()
+            .is_some_and(|f| constrained.contains(&sorted([f[0], f[1], f[2]])))
+    })
+}
+
+fn volume_f64([a, b, c, d]: [Vec3; 4]) -> f64 {
+    let a = a.as_dvec3();
+    (b.as_dvec3() - a).dot((c.as_dvec3() - a).cross(d.as_dvec3() - a)) / 6.0
+}
+
+/// Get all of the triangulations of a polygon.
+fn triangulations<T: Copy>(poly: &[T]) -> Vec<Vec<[T; 3]>> {
+    let [first, .., last] = *poly else {
+        return vec![Vec::new()];
+    };
+    if poly.len() < 3 {
+        return vec![Vec::new()];
+    }
+    let mut out = Vec::new();
+    for i in 1..(poly.len() - 1) {
+        for left in triangulations(&poly[..=i]) {
+            for right in triangulations(&poly[i..]) {
+                let mut tris = vec![[first, poly[i], last]];
+                tris.extend_from_slice(&left);
+                tris.extend_from_slice(&right);
+                out.push(tris);
+            }
+        }
+    }
+    out
+}
+
+/// Get the tetrahedra around an edge, along with the cycle of vertices linking them.
+///
+/// The `i`th tetrahedron is made of the edge and the `i`th and `i + 1`th link vertices. Rings with more than `limit`
+/// tetrahedra are skipped.
+#[allow(clippy::type_complexity)]
+fn edge_ring(
+    mesh: &Scratch,
+    start: TetraId<usize>,
+    [a, c]: [VertexId<usize>; 2],
+    limit: usize,
+) -> Option<(Vec<TetraId<usize>>, Vec<VertexId<usize>>)> {
+    let other = |id: TetraId<usize>, not: [VertexId<usize>; 3]| {
+        let tet = mesh.get_tetra(id)?;
+        VertexIdx::VALS
+            .into_iter()
+            .map(|i| tet.vertex(i))
+            .find(|v| !not.contains(v))
+    };
+    let first = other(start, [a, c, a])?;
+    let mut ring = vec![start];
+    let mut link = vec![first];
+    let mut current = start;
+    let mut prev = first;
+    let mut next = other(start, [a, c, first])?;
+    loop {
+        let tet = mesh.get_tetra(current)?;
+        let face = VertexIdx::VALS
+            .into_iter()
+            .find(|&i| tet.vertex(i) == prev)?;
+        let (n, _) = tet.face(face)?;
+        if n == start {
+            break;
+        }
+        if ring.len() >= limit {
+            return None;
+        }
+        ring.push(n);
+        link.push(next);
+        prev = next;
+        next = other(n, [a, c, next])?;
+        current = n;
+    }
+    Some((ring, link))
+}
+
+/// Get every face and edge in the tetrahedralization, in terms of point indices.
+#[allow(clippy::type_complexity)]
+fn collect_simplices(mesh: &Scratch) -> (HashSet<[u32; 3]>, HashSet<[u32; 2]>) {
+    let mut faces = HashSet::new();
+    let mut edges = HashSet::new();
+    for (_, tet) in mesh.tetras() {
+        let indices = VertexIdx::VALS.map(|i| mesh.get_vertex(tet.vertex(i)).and_then(|v| v.data));
+        for face in VertexIdx::VALS {
+            if let [Some(a), Some(b), Some(c)] = face.others().map(|i| *i.in_arr(&indices)) {
+                faces.insert(sorted([a, b, c]));
+            }
+        }
+        for i in 0..4 {
+            for j in (i + 1)..4 {
+                if let (Some(a), Some(b)) = (indices[i], indices[j]) {
+                    edges.insert(sorted([a, b]));
+                }
+            }
+        }
+    }
+    (faces, edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::slab_mesh::DefaultPackedMesh;
+    use crate::validate::validate;
+    use std::f32::consts::{PI, TAU};
+
+    /// Tetrahedralize a surface, and check that its boundary is exactly the input triangles.
+    fn check(verts: Vec<Vec3>, faces: Vec<[u32; 3]>) {
+        let surface = TriangleSurface::new(verts, faces);
+        let mesh: DefaultPackedMesh<u16> = surface.try_build().unwrap();
+        let report = validate(&mesh);
+        assert!(report.is_valid(), "{report:?}");
+        let key = |verts: &[Vec3], [a, b, c]: [u32; 3]| {
+            // rotate the triangle to start at its smallest corner, keeping the winding
+            let tri = [a, b, c].map(|i| position_key(verts[i as usize]));
+            let min = (0..3).min_by_key(|&i| tri[i]).unwrap();
+            [tri[min], tri[(min + 1) % 3], tri[(min + 2) % 3]]
+        };
+        let mut expected = surface
+            .faces
+            .iter()
+            .map(|&f| key(&surface.verts, f))
+            .collect::<Vec<_>>();
+        let (mut verts, mut faces) = (Vec::new(), Vec::new());
+        mesh.append_primitive_surface(&mut verts, &mut faces);
+        let mut boundary = faces.iter().map(|&f| key(&verts, f)).collect::<Vec<_>>();
+        expected.sort_unstable();
+        boundary.sort_unstable();
+        assert_eq!(boundary, expected);
+    }
+
+    #[test]
+    fn cube() {
+        let verts = (0..8)
+            .map(|i| Vec3::new((i & 1) as f32, (i >> 1 & 1) as f32, (i >> 2) as f32))
+            .collect();
+        #[rustfmt::skip]
+        let faces = vec![
+            [0, 2, 3], [0, 3, 1], [4, 5, 7], [4, 7, 6],
+            [0, 1, 5], [0, 5, 4], [2, 6, 7], [2, 7, 3],
+            [0, 4, 6], [0, 6, 2], [1, 3, 7], [1, 7, 5],
+        ];
+        check(verts, faces);
+    }
+
+    #[test]
+    fn octahedron() {
+        let verts = vec![
+            Vec3::X,
+            Vec3::NEG_X,
+            Vec3::Y,
+            Vec3::NEG_Y,
+            Vec3::Z,
+            Vec3::NEG_Z,
+        ];
+        #[rustfmt::skip]
+        let faces = vec![
+            [0, 2, 4], [2, 1, 4], [1, 3, 4], [3, 0, 4],
+            [2, 0, 5], [1, 2, 5], [3, 1, 5], [0, 3, 5],
+        ];
+        check(verts, faces);
+    }
+
+    #[test]
+    fn sphere() {
+        // a UV sphere with 10 segments and 6 rings, so 100 triangles
+        const SEGMENTS: u32 = 10;
+        const RINGS: u32 = 6;
+        let mut verts = vec![Vec3::Z, Vec3::NEG_Z];
+        for ring in 1..RINGS {
+            let (sin, cos) = (ring as f32 * PI / RINGS as f32).sin_cos();
+            for seg in 0..SEGMENTS {
+                let (y, x) = (seg as f32 * TAU / SEGMENTS as f32).sin_cos();
+                verts.push(Vec3::new(x * sin, y * sin, cos));
+            }
+        }
+        let at = |ring: u32, seg: u32| 2 + (ring - 1) * SEGMENTS + seg % SEGMENTS;
+        let mut faces = Vec::new();
+        for seg in 0..SEGMENTS {
+            faces.push([0, at(1, seg), at(1, seg + 1)]);
+            faces.push([1, at(RINGS - 1, seg + 1), at(RINGS - 1, seg)]);
+            for ring in 1..(RINGS - 1) {
+                faces.push([at(ring, seg), at(ring + 1, seg), at(ring + 1, seg + 1)]);
+                faces.push([at(ring, seg), at(ring + 1, seg + 1), at(ring, seg + 1)]);
+            }
+        }
+        assert_eq!(faces.len(), 100);
+        check(verts, faces);
+    }
+}
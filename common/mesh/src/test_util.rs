@@ -0,0 +1,68 @@
+//! Fixtures shared between the tests of different modules.
+
+use crate::components::IdMap;
+use crate::delaunay::tetrahedralize;
+use crate::traits::*;
+use bevy_math::Vec3;
+
+/// A Delaunay tetrahedralization of a jittered lattice of `n` points on a side, filling the unit cube.
+///
+/// The jitter comes from a fixed seed, so the same `n` always gives the same mesh. Any vertex data starts out as its
+/// default.
+pub fn lattice<M: TetraMeshMut + Default>(n: u32) -> M
+where
+    M::Vertex: From<Vec3>,
+    M::Tetra: From<TetraPrimitive<M::Key>>,
+{
+    let mut seed = 1u32;
+    let mut jitter = move || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+    };
+    let mut verts = Vec::new();
+    for i in 0..n {
+        for j in 0..n {
+            for k in 0..n {
+                let mut pos = Vec3::new(i as f32, j as f32, k as f32);
+                for axis in 0..3 {
+                    if pos[axis] != 0.0 && pos[axis] != (n - 1) as f32 {
+                        pos[axis] += jitter() * 0.3;
+                    }
+                }
+                verts.push(M::Vertex::from(pos / (n - 1) as f32));
+            }
+        }
+    }
+    tetrahedralize(verts)
+}
+
+/// Check that everything in an ID map is in both meshes, with vertices at the same position, and tetrahedra using the
+/// mapped vertices in the same order.
+pub fn assert_map_resolves<M: TetraMesh, M2: TetraMesh>(
+    from: &M,
+    to: &M2,
+    map: &IdMap<M::Key, M2::Key>,
+) {
+    for (&old, &new) in &map.verts {
+        let (old_vert, new_vert) = (from.get_vertex(old), to.get_vertex(new));
+        assert!(old_vert.is_some(), "{old:?} isn't in the original mesh");
+        assert!(new_vert.is_some(), "{new:?} isn't in the new mesh");
+        assert_eq!(old_vert.unwrap().as_vec3(), new_vert.unwrap().as_vec3());
+    }
+    for (&old, &new) in &map.tetras {
+        let old_tet = from
+            .get_tetra(old)
+            .unwrap_or_else(|| panic!("{old:?} isn't in the original mesh"));
+        let new_tet = to
+            .get_tetra(new)
+            .unwrap_or_else(|| panic!("{new:?} isn't in the new mesh"));
+        for i in VertexIdx::VALS {
+            let vert = old_tet.vertex(i);
+            assert_eq!(
+                map.verts.get(&vert),
+                Some(&new_tet.vertex(i)),
+                "{old:?} and {new:?} differ at {i:?}"
+            );
+        }
+    }
+}
@@ -3,7 +3,7 @@
 use bevy_math::Vec3;
 use std::any::Any;
 use std::collections::{HashMap, HashSet};
-use std::fmt::{self, Debug, Formatter};
+use std::fmt::{self, Debug, Display, Formatter};
 use std::hash::Hash;
 
 /// A vertex index
@@ -147,6 +147,11 @@ pub trait FaceData<K>: Sized {
     fn from_option(opt: Option<(TetraId<K>, VertexIdx)>) -> Self {
         opt.map_or_else(Self::none, |(key, idx)| Self::pack(key, idx))
     }
+    /// Get the largest key that can be packed, if it's less than the largest value of `K`.
+    #[inline(always)]
+    fn max_key() -> Option<TetraId<K>> {
+        None
+    }
 }
 impl<K> FaceData<K> for Option<(TetraId<K>, VertexIdx)> {
     #[inline(always)]
@@ -197,6 +202,11 @@ macro_rules! for_int {
                 fn is_some(&self) -> bool {
                     self.0 != <$int>::MAX
                 }
+                /// The key is shifted over by two bits, and the largest one would pack to the same value as [`Self::none`].
+                #[inline(always)]
+                fn max_key() -> Option<TetraId<$int>> {
+                    Some(TetraId((<$int>::MAX >> 2) - 1))
+                }
             }
             impl Debug for PackedFace<$int> {
                 fn fmt(&self, f: &mut Formatter) -> fmt::Result {
@@ -217,6 +227,23 @@ pub struct PackedFace<K>(pub K);
 pub trait TetraData<K> {
     fn vertex(&self, vert: VertexIdx) -> VertexId<K>;
     fn face(&self, face: VertexIdx) -> Option<(TetraId<K>, VertexIdx)>;
+    /// Get the vertices of a face, sorted so that faces can be compared regardless of their winding.
+    fn sorted_face(&self, face: VertexIdx) -> [VertexId<K>; 3]
+    where
+        K: Ord,
+    {
+        let mut verts = face.others().map(|i| self.vertex(i));
+        verts.sort_unstable();
+        verts
+    }
+    /// Get the largest key that neighbors can have, if it's less than the largest value of `K`.
+    #[inline(always)]
+    fn max_key() -> Option<TetraId<K>>
+    where
+        Self: Sized,
+    {
+        None
+    }
 }
 pub trait TetraDataMut<K>: TetraData<K> {
     fn set_vertex(&mut self, vert: VertexIdx, val: VertexId<K>);
@@ -286,6 +313,10 @@ impl<K: Copy, F: FaceData<K> + Copy, T> TetraData<K> for Tetra<K, F, T> {
     fn face(&self, face: VertexIdx) -> Option<(TetraId<K>, VertexIdx)> {
         face.in_arr(&self.conns).1.into_option()
     }
+    #[inline(always)]
+    fn max_key() -> Option<TetraId<K>> {
+        F::max_key()
+    }
 }
 impl<K: Copy, F: FaceData<K> + Copy, T> TetraDataMut<K> for Tetra<K, F, T> {
     fn set_vertex(&mut self, vert: VertexIdx, val: VertexId<K>) {
@@ -319,7 +350,9 @@ pub struct TetraId<K>(pub K);
 /// A tetrahedral mesh.
 pub trait TetraMesh {
     /// A key type to use for indices.
-    type Key: Copy + Hash + Eq + Debug;
+    ///
+    /// Keys need to be ordered so that faces can be identified by their sorted vertices.
+    type Key: Copy + Hash + Eq + Ord + Debug;
     /// The type to use for vertices.
     type Vertex: VertexData;
     /// The type to use for tetrahedra.
@@ -344,6 +377,13 @@ pub trait TetraMesh {
     fn verts(&self) -> Self::VertsIter<'_>;
     /// Iterate over the tetrahedra.
     fn tetras(&self) -> Self::TetrasIter<'_>;
+    /// Get the positions of the four corners of a tetrahedron.
+    ///
+    /// This returns `None` if any of the vertices don't exist.
+    fn tetra_points(&self, tetra: &Self::Tetra) -> Option<[Vec3; 4]> {
+        let [a, b, c, d] = VertexIdx::VALS.map(|i| self.get_vertex(tetra.vertex(i)));
+        Some([a?, b?, c?, d?].map(VertexData::as_vec3))
+    }
     /// Quickly get the bounds for this mesh.
     ///
     /// The default value is `[Vec3::NEG_INFINITY, Vec3::INFINITY]`, but a more precise set of bounds should probably be used.
@@ -410,6 +450,43 @@ pub trait TetraMesh {
     }
 }
 
+/// An error from adding to a mesh whose keys can't refer to any more vertices or tetrahedra.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapacityError {
+    /// The mesh already has as many vertices as it can hold.
+    Vertices { capacity: usize },
+    /// The mesh already has as many tetrahedra as it can hold.
+    Tetras { capacity: usize },
+}
+impl Display for CapacityError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Vertices { capacity } => write!(
+                f,
+                "the mesh is out of vertex keys, since it can only hold {capacity} vertices"
+            ),
+            Self::Tetras { capacity } => write!(
+                f,
+                "the mesh is out of tetrahedron keys, since it can only hold {capacity} tetrahedra"
+            ),
+        }
+    }
+}
+impl std::error::Error for CapacityError {}
+
+/// An error from adding a vertex or tetrahedron to a full mesh, which gives the value back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddError<T> {
+    pub value: T,
+    pub error: CapacityError,
+}
+impl<T> Display for AddError<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.error, f)
+    }
+}
+impl<T: Debug> std::error::Error for AddError<T> {}
+
 /// A mutable tetrahedral mesh.
 pub trait TetraMeshMut: TetraMesh<Vertex: VertexDataMut, Tetra: TetraDataMut<Self::Key>> {
     /// Mutably get the vertex with the specified index.
@@ -417,9 +494,33 @@ pub trait TetraMeshMut: TetraMesh<Vertex: VertexDataMut, Tetra: TetraDataMut<Sel
     /// Mutably get the tetrahedron with the specified index.
     fn get_tetra_mut(&mut self, id: TetraId<Self::Key>) -> Option<&mut Self::Tetra>;
     /// Insert a vertex and get its ID.
+    ///
+    /// # Panics
+    /// This can panic if the mesh can't hold any more vertices, in which case [`Self::try_add_vertex`] returns an error.
     fn add_vertex(&mut self, vert: Self::Vertex) -> VertexId<Self::Key>;
     /// Insert a tetrahedron and get its ID.
+    ///
+    /// # Panics
+    /// This can panic if the mesh can't hold any more tetrahedra, in which case [`Self::try_add_tetra`] returns an error.
     fn add_tetra(&mut self, tetra: Self::Tetra) -> TetraId<Self::Key>;
+    /// Insert a vertex and get its ID, unless the mesh is full, in which case the error gives it back.
+    ///
+    /// The default implementation never fails.
+    fn try_add_vertex(
+        &mut self,
+        vert: Self::Vertex,
+    ) -> Result<VertexId<Self::Key>, AddError<Self::Vertex>> {
+        Ok(self.add_vertex(vert))
+    }
+    /// Insert a tetrahedron and get its ID, unless the mesh is full, in which case the error gives it back.
+    ///
+    /// The default implementation never fails.
+    fn try_add_tetra(
+        &mut self,
+        tetra: Self::Tetra,
+    ) -> Result<TetraId<Self::Key>, AddError<Self::Tetra>> {
+        Ok(self.add_tetra(tetra))
+    }
     /// Remove a vertex with a given ID.
     ///
     /// This is expected to be a stable operation, and the key may or may not be reused.
@@ -0,0 +1,426 @@
+//! Integrity checks for tetrahedral meshes.
+//!
+//! Meshes are easy to break by hand, and a broken face link usually doesn't show up until something walks the
+//! adjacency graph much later. [`validate`] checks everything that the rest of the crate assumes about a mesh.
+
+use crate::geometry::{is_degenerate, signed_volume};
+use crate::traits::*;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::{self, Display, Formatter};
+
+/// A single problem found in a mesh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Violation<K> {
+    /// A tetrahedron references a vertex that isn't in the mesh.
+    DanglingVertex {
+        tetra: TetraId<K>,
+        corner: VertexIdx,
+        vertex: VertexId<K>,
+    },
+    /// A face links to a tetrahedron that isn't in the mesh.
+    DanglingTetra {
+        tetra: TetraId<K>,
+        face: VertexIdx,
+        neighbor: TetraId<K>,
+    },
+    /// A face links to a neighbor, but the neighbor's face doesn't link back.
+    OneSidedFace {
+        tetra: TetraId<K>,
+        face: VertexIdx,
+        neighbor: (TetraId<K>, VertexIdx),
+        /// What the neighbor's face actually links to.
+        back: Option<(TetraId<K>, VertexIdx)>,
+    },
+    /// Two faces link to each other, but aren't made of the same three vertices.
+    MismatchedFace {
+        tetra: TetraId<K>,
+        face: VertexIdx,
+        neighbor: (TetraId<K>, VertexIdx),
+    },
+    /// A tetrahedron uses the same vertex more than once, or is too flat to have a meaningful volume.
+    Degenerate { tetra: TetraId<K>, volume: f32 },
+    /// A tetrahedron has a negative signed volume, so its faces point inwards.
+    Inverted { tetra: TetraId<K>, volume: f32 },
+    /// Two boundary faces are made of the same vertices, so they should have been linked.
+    DuplicateBoundaryFace {
+        first: (TetraId<K>, VertexIdx),
+        second: (TetraId<K>, VertexIdx),
+    },
+}
+impl<K> Violation<K> {
+    /// Get the tetrahedron this violation was found on.
+    pub fn tetra(&self) -> &TetraId<K> {
+        match self {
+            Self::DanglingVertex { tetra, .. }
+            | Self::DanglingTetra { tetra, .. }
+            | Self::OneSidedFace { tetra, .. }
+            | Self::MismatchedFace { tetra, .. }
+            | Self::Degenerate { tetra, .. }
+            | Self::Inverted { tetra, .. } => tetra,
+            Self::DuplicateBoundaryFace {
+                second: (tetra, _), ..
+            } => tetra,
+        }
+    }
+}
+impl<K: fmt::Debug> Display for Violation<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DanglingVertex {
+                tetra,
+                corner,
+                vertex,
+            } => write!(
+                f,
+                "{tetra:?} corner {corner:?} references missing vertex {vertex:?}"
+            ),
+            Self::DanglingTetra {
+                tetra,
+                face,
+                neighbor,
+            } => write!(
+                f,
+                "{tetra:?} face {face:?} links to missing tetrahedron {neighbor:?}"
+            ),
+            Self::OneSidedFace {
+                tetra,
+                face,
+                neighbor,
+                back,
+            } => write!(
+                f,
+                "{tetra:?} face {face:?} links to {neighbor:?}, which links back to {back:?}"
+            ),
+            Self::MismatchedFace {
+                tetra,
+                face,
+                neighbor,
+            } => write!(
+                f,
+                "{tetra:?} face {face:?} links to {neighbor:?}, but they don't share the same vertices"
+            ),
+            Self::Degenerate { tetra, volume } => {
+                write!(f, "{tetra:?} is degenerate (volume {volume})")
+            }
+            Self::Inverted { tetra, volume } => {
+                write!(f, "{tetra:?} is inverted (volume {volume})")
+            }
+            Self::DuplicateBoundaryFace { first, second } => write!(
+                f,
+                "boundary faces {first:?} and {second:?} have the same vertices but aren't linked"
+            ),
+        }
+    }
+}
+
+/// The result of [`validate`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationReport<K> {
+    pub violations: Vec<Violation<K>>,
+}
+impl<K> ValidationReport<K> {
+    /// Check if no problems were found.
+    #[inline(always)]
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+    /// Convert this report into a `Result`, so it can be used with `?` or `unwrap`.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_valid() { Ok(()) } else { Err(self) }
+    }
+}
+impl<K: fmt::Debug> Display for ValidationReport<K> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.violations.is_empty() {
+            return f.write_str("mesh is valid");
+        }
+        write!(f, "{} problems found in mesh:", self.violations.len())?;
+        for v in &self.violations {
+            write!(f, "\n- {v}")?;
+        }
+        Ok(())
+    }
+}
+impl<K: fmt::Debug> std::error::Error for ValidationReport<K> {}
+
+/// Check a mesh for broken connectivity and bad geometry.
+///
+/// This checks that:
+/// - every vertex referenced by a tetrahedron exists
+/// - every face link points to an existing tetrahedron, which links back through a face with the same three vertices
+/// - every tetrahedron has a positive signed volume
+/// - no two boundary faces share the same three vertices
+pub fn validate<M: TetraMesh>(mesh: &M) -> ValidationReport<M::Key> {
+    let mut violations = Vec::new();
+    let mut boundary = HashMap::new();
+    for (id, tet) in mesh.tetras() {
+        let mut dangling = false;
+        for corner in VertexIdx::VALS {
+            let vertex = tet.vertex(corner);
+            if mesh.get_vertex(vertex).is_none() {
+                dangling = true;
+                violations.push(Violation::DanglingVertex {
+                    tetra: id,
+                    corner,
+                    vertex,
+                });
+            }
+        }
+        if !dangling && let Some(points) = mesh.tetra_points(tet) {
+            let verts = VertexIdx::VALS.map(|i| tet.vertex(i));
+            let repeated = (0..4).any(|i| verts[(i + 1)..].contains(&verts[i]));
+            let volume = signed_volume(points);
+            if repeated || is_degenerate(points) {
+                violations.push(Violation::Degenerate { tetra: id, volume });
+            } else if volume < 0.0 {
+                violations.push(Violation::Inverted { tetra: id, volume });
+            }
+        }
+        for face in VertexIdx::VALS {
+            let Some(neighbor) = tet.face(face) else {
+                match boundary.entry(tet.sorted_face(face)) {
+                    Entry::Vacant(e) => {
+                        e.insert((id, face));
+                    }
+                    Entry::Occupied(e) => violations.push(Violation::DuplicateBoundaryFace {
+                        first: *e.get(),
+                        second: (id, face),
+                    }),
+                }
+                continue;
+            };
+            let Some(other) = mesh.get_tetra(neighbor.0) else {
+                violations.push(Violation::DanglingTetra {
+                    tetra: id,
+                    face,
+                    neighbor: neighbor.0,
+                });
+                continue;
+            };
+            let back = other.face(neighbor.1);
+            if back != Some((id, face)) {
+                violations.push(Violation::OneSidedFace {
+                    tetra: id,
+                    face,
+                    neighbor,
+                    back,
+                });
+            } else if tet.sorted_face(face) != other.sorted_face(neighbor.1) {
+                violations.push(Violation::MismatchedFace {
+                    tetra: id,
+                    face,
+                    neighbor,
+                });
+            }
+        }
+    }
+    ValidationReport { violations }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::*;
+    use crate::slab_mesh::SlabMesh;
+    use bevy_math::{Quat, Vec3};
+
+    type Mesh = SlabMesh<u32, Vertex, Tetra<u32>>;
+
+    /// A mesh with the origin and the unit vectors, for tests to add tetrahedra to.
+    fn corners() -> (Mesh, [VertexId<u32>; 5]) {
+        let mut mesh = Mesh::new();
+        let verts =
+            [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z, Vec3::NEG_Z].map(|p| mesh.add_vertex(p.into()));
+        (mesh, verts)
+    }
+
+    fn add(mesh: &mut Mesh, verts: [VertexId<u32>; 4]) -> TetraId<u32> {
+        mesh.add_tetra(verts.map(|v| (v, None)).into())
+    }
+
+    fn link(mesh: &mut Mesh, a: (TetraId<u32>, VertexIdx), b: (TetraId<u32>, VertexIdx)) {
+        mesh.get_tetra_mut(a.0).unwrap().set_face(a.1, Some(b));
+        mesh.get_tetra_mut(b.0).unwrap().set_face(b.1, Some(a));
+    }
+
+    #[test]
+    fn dangling_vertex() {
+        let (mut mesh, [o, x, y, _, _]) = corners();
+        let tetra = add(&mut mesh, [o, x, y, VertexId(9)]);
+        assert_eq!(
+            validate(&mesh).violations,
+            [Violation::DanglingVertex {
+                tetra,
+                corner: VertexIdx::V3,
+                vertex: VertexId(9),
+            }]
+        );
+    }
+
+    #[test]
+    fn dangling_tetra() {
+        let (mut mesh, [o, x, y, z, _]) = corners();
+        let tetra = add(&mut mesh, [o, x, y, z]);
+        mesh.get_tetra_mut(tetra)
+            .unwrap()
+            .set_face(VertexIdx::V0, Some((TetraId(5), VertexIdx::V1)));
+        assert_eq!(
+            validate(&mesh).violations,
+            [Violation::DanglingTetra {
+                tetra,
+                face: VertexIdx::V0,
+                neighbor: TetraId(5),
+            }]
+        );
+    }
+
+    #[test]
+    fn one_sided_face() {
+        let (mut mesh, [o, x, y, z, w]) = corners();
+        let first = add(&mut mesh, [o, x, y, z]);
+        let second = add(&mut mesh, [o, y, x, w]);
+        mesh.get_tetra_mut(first)
+            .unwrap()
+            .set_face(VertexIdx::V3, Some((second, VertexIdx::V3)));
+        assert_eq!(
+            validate(&mesh).violations,
+            [Violation::OneSidedFace {
+                tetra: first,
+                face: VertexIdx::V3,
+                neighbor: (second, VertexIdx::V3),
+                back: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn mismatched_face() {
+        let (mut mesh, [o, x, y, z, w]) = corners();
+        let first = add(&mut mesh, [o, x, y, z]);
+        let second = add(&mut mesh, [o, y, x, w]);
+        // the faces opposite the origins don't have the same vertices
+        link(&mut mesh, (first, VertexIdx::V0), (second, VertexIdx::V0));
+        let violations = validate(&mesh).violations;
+        assert_eq!(
+            violations[..2],
+            [
+                Violation::MismatchedFace {
+                    tetra: first,
+                    face: VertexIdx::V0,
+                    neighbor: (second, VertexIdx::V0),
+                },
+                Violation::MismatchedFace {
+                    tetra: second,
+                    face: VertexIdx::V0,
+                    neighbor: (first, VertexIdx::V0),
+                },
+            ]
+        );
+        // the faces that should have been linked instead are still open
+        assert_eq!(
+            violations[2..],
+            [Violation::DuplicateBoundaryFace {
+                first: (first, VertexIdx::V3),
+                second: (second, VertexIdx::V3),
+            }]
+        );
+    }
+
+    #[test]
+    fn degenerate() {
+        let (mut mesh, [o, x, y, _, _]) = corners();
+        let flat = mesh.add_vertex(Vec3::new(1.0, 1.0, 0.0).into());
+        let tetra = add(&mut mesh, [o, x, y, flat]);
+        assert_eq!(
+            validate(&mesh).violations,
+            [Violation::Degenerate { tetra, volume: 0.0 }]
+        );
+
+        let (mut mesh, [o, x, y, _, _]) = corners();
+        let tetra = add(&mut mesh, [o, x, y, x]);
+        // the repeated corner also makes two of its faces the same
+        assert_eq!(
+            validate(&mesh).violations[0],
+            Violation::Degenerate { tetra, volume: 0.0 }
+        );
+    }
+
+    #[test]
+    fn inverted() {
+        let (mut mesh, [o, x, y, z, _]) = corners();
+        let tetra = add(&mut mesh, [o, y, x, z]);
+        assert_eq!(
+            validate(&mesh).violations,
+            [Violation::Inverted {
+                tetra,
+                volume: -1.0 / 6.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn duplicate_boundary_face() {
+        let (mut mesh, [o, x, y, z, w]) = corners();
+        let first = add(&mut mesh, [o, x, y, z]);
+        let second = add(&mut mesh, [o, y, x, w]);
+        let report = validate(&mesh);
+        assert_eq!(
+            report.violations,
+            [Violation::DuplicateBoundaryFace {
+                first: (first, VertexIdx::V3),
+                second: (second, VertexIdx::V3),
+            }]
+        );
+        link(&mut mesh, (first, VertexIdx::V3), (second, VertexIdx::V3));
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+    }
+
+    #[test]
+    fn builders() {
+        fn check(mesh: Mesh, name: &str) {
+            let report = validate(&mesh);
+            assert!(report.is_valid(), "{name}: {report}");
+            assert!(mesh.tetras().count() > 0, "{name} is empty");
+        }
+        check(Cuboid::UNIT_CUBE.build(), "cuboid");
+        check(
+            Hexahedron::new([
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(1.5, 1.0, 0.0),
+                Vec3::new(0.5, 1.0, 0.0),
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(2.0, 0.0, 1.0),
+                Vec3::new(1.5, 1.0, 1.0),
+                Vec3::new(0.5, 1.0, 1.0),
+            ])
+            .build(),
+            "hexahedron",
+        );
+        check(
+            Hexahedron::CENTERED_CUBE
+                .rotate(Quat::from_rotation_y(0.7))
+                .build(),
+            "rotated hexahedron",
+        );
+        check(Octahedron::CENTERED.build(), "octahedron");
+        check(Bipyramid::centered(7).build(), "bipyramid");
+        check(
+            Transformed::new(
+                Bipyramid::centered(5),
+                Affine3A::from_scale(Vec3::splat(3.0)),
+            )
+            .build(),
+            "transformed bipyramid",
+        );
+        check(
+            IndexedMesh::new(
+                vec![Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::Z, Vec3::ONE],
+                vec![[0, 1, 2, 3], [1, 2, 3, 4]],
+            )
+            .build(),
+            "indexed mesh",
+        );
+    }
+}
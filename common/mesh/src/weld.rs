@@ -0,0 +1,216 @@
+//! Welding vertices that are at the same place.
+//!
+//! Shapes that are built separately and appended into one mesh each get their own vertices, so where they touch there
+//! are two copies of every vertex, and the faces between them aren't linked. [`weld`] merges the copies and links the
+//! faces, which turns the shapes into a single connected volume.
+
+use crate::geometry::position_key;
+use crate::traits::*;
+use bevy_math::Vec3;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+
+/// The changes made by [`weld`].
+#[derive(Debug, Clone)]
+pub struct Weld<K> {
+    /// The vertex each removed vertex was merged into.
+    ///
+    /// Vertices that were kept aren't in the map.
+    pub verts: HashMap<VertexId<K>, VertexId<K>>,
+    /// Tetrahedra that were removed because multiple of their corners were merged together.
+    pub removed: Vec<TetraId<K>>,
+    /// How many pairs of faces were linked.
+    pub linked: usize,
+}
+
+/// Get the vertices of a face in the order given by [`VertexIdx::face_order`].
+fn oriented_face<K: Copy, T: TetraData<K>>(tet: &T, face: VertexIdx) -> [VertexId<K>; 3] {
+    face.face_order().map(|i| tet.vertex(i))
+}
+
+/// Find the vertex each vertex should be merged into, if any.
+///
+/// Vertices are merged into the first vertex within the tolerance of them, so merges don't chain across distances
+/// longer than the tolerance.
+fn find_merges<M: TetraMesh>(
+    mesh: &M,
+    tolerance: f32,
+) -> HashMap<VertexId<M::Key>, VertexId<M::Key>> {
+    let mut merges = HashMap::new();
+    let mut cells = HashMap::<[i32; 3], Vec<(VertexId<M::Key>, Vec3)>>::new();
+    let cell = |pos: Vec3| (pos / tolerance).floor().as_ivec3().to_array();
+    for (id, vert) in mesh.verts() {
+        let pos = vert.as_vec3();
+        let [x, y, z] = cell(pos);
+        let mut found = None;
+        'search: for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let Some(kept) = cells.get(&[x + dx, y + dy, z + dz]) else {
+                        continue;
+                    };
+                    if let Some(&(other, _)) =
+                        kept.iter().find(|(_, p)| p.distance(pos) <= tolerance)
+                    {
+                        found = Some(other);
+                        break 'search;
+                    }
+                }
+            }
+        }
+        match found {
+            Some(other) => {
+                merges.insert(id, other);
+            }
+            None => cells.entry([x, y, z]).or_default().push((id, pos)),
+        }
+    }
+    merges
+}
+
+/// Merge vertices within a distance of each other, and link the faces that end up with the same vertices.
+///
+/// Merged vertices take the position and data of the vertex they're merged into, which is the first one in the mesh.
+/// A tolerance of zero only merges vertices at exactly the same position. Tetrahedra that end up with a repeated corner
+/// are removed, and faces are only linked when exactly two unlinked faces have the same vertices, facing each other.
+/// The builders split their sides the same way wherever they're placed, so touching shapes made by them match up, but
+/// faces that only overlap, like those of hexahedra split along different diagonals by hand, stay unlinked.
+///
+/// For a [`SlabMesh`](crate::slab_mesh::SlabMesh) with stitching enabled,
+/// [`enable_stitching`](crate::slab_mesh::SlabMesh::enable_stitching) should be called afterwards to rebuild its index.
+pub fn weld<M: TetraMeshMut>(mesh: &mut M, tolerance: f32) -> Weld<M::Key> {
+    let verts = if tolerance > 0.0 {
+        find_merges(mesh, tolerance)
+    } else {
+        let mut kept = HashMap::new();
+        let mut merges = HashMap::new();
+        for (id, vert) in mesh.verts() {
+            match kept.entry(position_key(vert.as_vec3())) {
+                Entry::Occupied(e) => {
+                    merges.insert(id, *e.get());
+                }
+                Entry::Vacant(e) => {
+                    e.insert(id);
+                }
+            }
+        }
+        merges
+    };
+    let mut removed = Vec::new();
+    if !verts.is_empty() {
+        let affected = mesh
+            .tetras()
+            .filter(|(_, t)| {
+                VertexIdx::VALS
+                    .iter()
+                    .any(|&i| verts.contains_key(&t.vertex(i)))
+            })
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        for id in affected {
+            let Some(tet) = mesh.get_tetra_mut(id) else {
+                continue;
+            };
+            for i in VertexIdx::VALS {
+                if let Some(&v) = verts.get(&tet.vertex(i)) {
+                    tet.set_vertex(i, v);
+                }
+            }
+            let corners = VertexIdx::VALS.map(|i| tet.vertex(i));
+            if (0..4).any(|a| (a + 1..4).any(|b| corners[a] == corners[b])) {
+                mesh.remove_tetra(id);
+                removed.push(id);
+            }
+        }
+        for &v in verts.keys() {
+            mesh.remove_vertex(v);
+        }
+    }
+    // every face without a neighbor, grouped by its vertices
+    let mut open = HashMap::<_, Vec<_>>::new();
+    for (id, tet) in mesh.tetras() {
+        for face in VertexIdx::VALS {
+            if tet
+                .face(face)
+                .is_none_or(|(n, _)| mesh.get_tetra(n).is_none())
+            {
+                open.entry(tet.sorted_face(face))
+                    .or_default()
+                    .push((id, face));
+            }
+        }
+    }
+    let mut linked = 0;
+    for faces in open.into_values() {
+        let [(a, i), (b, j)] = faces[..] else {
+            continue;
+        };
+        let (Some(ta), Some(tb)) = (mesh.get_tetra(a), mesh.get_tetra(b)) else {
+            continue;
+        };
+        // faces of tetrahedra on opposite sides wind the opposite way
+        let [x, y, z] = oriented_face(ta, i);
+        let other = oriented_face(tb, j);
+        if a == b || ![[x, z, y], [z, y, x], [y, x, z]].contains(&other) {
+            continue;
+        }
+        if let Some(t) = mesh.get_tetra_mut(a) {
+            t.set_face(i, Some((b, j)));
+        }
+        if let Some(t) = mesh.get_tetra_mut(b) {
+            t.set_face(j, Some((a, i)));
+        }
+        linked += 1;
+    }
+    Weld {
+        verts,
+        removed,
+        linked,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::{Hexahedron, MeshBuilder, Octahedron};
+    use crate::components::connected_components;
+    use crate::slab_mesh::DefaultPackedMesh;
+    use crate::validate::validate;
+
+    #[test]
+    fn weld_touching_builders() {
+        let mut mesh = DefaultPackedMesh::<u8>::new();
+        Hexahedron::CENTERED_CUBE
+            .translate(Vec3::new(2.0, 1.0, 0.0))
+            .append_to(&mut mesh);
+        Hexahedron::CENTERED_CUBE
+            .translate(Vec3::new(4.0, 1.0, 0.0))
+            .append_to(&mut mesh);
+        Octahedron::CENTERED
+            .translate(Vec3::new(-2.0, 1.0, 0.0))
+            .append_to(&mut mesh);
+        assert_eq!(connected_components(&mesh).len(), 3);
+        let weld = weld(&mut mesh, 0.0);
+        assert_eq!(weld.verts.len(), 4);
+        assert!(weld.removed.is_empty());
+        // the side the cubes share is split into two triangles
+        assert_eq!(weld.linked, 2);
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+        assert_eq!(connected_components(&mesh).len(), 2);
+    }
+
+    #[test]
+    fn weld_with_tolerance() {
+        let mut mesh = DefaultPackedMesh::<u8>::new();
+        Hexahedron::UNIT_CUBE.append_to(&mut mesh);
+        Hexahedron::UNIT_CUBE
+            .translate(Vec3::new(0.0, 0.0, 1.0005))
+            .append_to(&mut mesh);
+        assert_eq!(weld(&mut mesh, 1e-4).linked, 0);
+        let weld = weld(&mut mesh, 1e-3);
+        assert_eq!(weld.verts.len(), 4);
+        assert_eq!(weld.linked, 2);
+        assert!(validate(&mesh).is_valid(), "{}", validate(&mesh));
+        assert_eq!(connected_components(&mesh).len(), 1);
+    }
+}